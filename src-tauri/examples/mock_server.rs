@@ -0,0 +1,597 @@
+//! Local stand-in for the collection server, for trying enrollment and
+//! uploads without a real backend:
+//!
+//!     cargo run --example mock_server -- [--addr 127.0.0.1:8787] [--data mock_server_data]
+//!         [--enroll-code CODE] [--employee NAME] [--fail-every N] [--bad-policy-signature]
+//!
+//! Enroll the agent against `http://127.0.0.1:8787` with the code (default
+//! `DEMO-ENROLL`). Enrolled devices are kept in `<data>/devices.json`; every
+//! other request must carry a valid device signature. `--fail-every N`
+//! answers every Nth request with 503 to exercise retries; restarting the
+//! server forgets open upload sessions, which exercises the restart path.
+//!
+//! `GET /policy` serves `<data>/policy.json` signed with the server key in
+//! `<data>/server_key.hex` (created on first start). Edit the file and bump
+//! its `version` to push a new policy; `--bad-policy-signature` corrupts the
+//! signature so the agent's rejection can be tried.
+//!
+//! Administrator commands are queued with `POST /admin/commands` (see
+//! `queue_command`) and followed with `GET /admin/commands/<id>`. The agent
+//! long-polls `GET /commands?wait=N`, acknowledges each command and posts
+//! its result. Heartbeats posted to `/heartbeat` are kept in
+//! `<data>/heartbeats/` and listed by `GET /admin/heartbeats`.
+//!
+//! The agent's tests include this file and start it with `spawn` on port 0.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+struct Upload {
+    kind: String,
+    file_name: String,
+    size: u64,
+    sha256: String,
+    meta: serde_json::Value,
+    part: PathBuf,
+    offset: u64,
+    complete: bool,
+}
+
+struct Command {
+    device_id: String,
+    /// Signed JSON text, as sent to the agent
+    command: String,
+    signature: String,
+    /// queued, acked or done
+    state: &'static str,
+    result: Option<serde_json::Value>,
+}
+
+/// A command poll held open until a command arrives or `until` passes
+struct Parked {
+    request: Request,
+    device_id: String,
+    until: Instant,
+}
+
+/// Requests signed further than this from the server clock are refused
+const MAX_CLOCK_SKEW_MS: u64 = 5 * 60 * 1000;
+
+struct MockServer {
+    data: PathBuf,
+    /// Signs policies; its public half is handed out at enrollment
+    server_key: SigningKey,
+    bad_policy_signature: bool,
+    enroll_code: String,
+    employee: Option<String>,
+    /// device id -> hex public key
+    devices: HashMap<String, String>,
+    uploads: HashMap<String, Upload>,
+    commands: HashMap<String, Command>,
+    /// device id -> latest heartbeat
+    heartbeats: HashMap<String, serde_json::Value>,
+    /// Ids carry the start time so a restarted server never reuses one
+    started: u64,
+    next_id: u64,
+}
+
+type Reply = (u16, serde_json::Value);
+
+fn error(code: u16, msg: &str) -> Reply {
+    (code, serde_json::json!({ "error": msg }))
+}
+
+fn progress(id: &str, upload: &Upload) -> Reply {
+    (
+        200,
+        serde_json::json!({ "upload_id": id, "offset": upload.offset, "complete": upload.complete }),
+    )
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+/// `bytes <start>-<end>/<total>`
+fn parse_content_range(value: &str) -> Option<(u64, u64, u64)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?, total.parse().ok()?))
+}
+
+impl MockServer {
+    /// `None` parks a command poll until a command arrives or it times out
+    fn handle(&mut self, request: &mut Request) -> Option<Reply> {
+        let url = request.url().to_string();
+        let mut body = Vec::new();
+        if let Err(e) = request.as_reader().read_to_end(&mut body) {
+            return Some(error(400, &e.to_string()));
+        }
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (request.method(), parts.as_slice()) {
+            (Method::Post, ["enroll"]) => return Some(self.enroll(&body)),
+            (Method::Post, ["admin", "commands"]) => return Some(self.queue_command(&body)),
+            (Method::Get, ["admin", "commands", id]) => return Some(self.command_state(id)),
+            (Method::Get, ["admin", "heartbeats"]) => return Some((200, serde_json::json!(self.heartbeats))),
+            _ => {}
+        }
+        if let Err(reply) = self.authenticate(request, &url, &body) {
+            return Some(reply);
+        }
+        let device_id = header(request, "X-Device-Id").unwrap_or_default().to_string();
+        let reply = match (request.method(), parts.as_slice()) {
+            (Method::Get, ["commands"]) => {
+                let wait = query.strip_prefix("wait=").and_then(|w| w.parse().ok()).unwrap_or(0);
+                return self.poll_commands(&device_id, wait);
+            }
+            (Method::Post, ["commands", id, "ack"]) => self.update_command(id, &device_id, "acked", None),
+            (Method::Post, ["commands", id, "result"]) => {
+                match serde_json::from_slice::<serde_json::Value>(&body) {
+                    Ok(result) => self.update_command(id, &device_id, "done", Some(result)),
+                    Err(e) => error(400, &e.to_string()),
+                }
+            }
+            (Method::Post, ["heartbeat"]) => self.heartbeat(&device_id, &body),
+            (Method::Get, ["policy"]) => self.policy(),
+            (Method::Post, ["uploads"]) => self.open(&body),
+            (Method::Get, ["uploads", id]) => match self.uploads.get(*id) {
+                Some(u) => progress(id, u),
+                None => error(404, "Unknown upload"),
+            },
+            (Method::Put, ["uploads", id]) => {
+                let range = header(request, "Content-Range").and_then(parse_content_range);
+                match range {
+                    Some(range) => self.append(id, range, &body),
+                    None => error(400, "Missing or invalid Content-Range"),
+                }
+            }
+            _ => error(404, "Not found"),
+        };
+        Some(reply)
+    }
+
+    /// Queue a signed command, e.g.
+    /// `curl -d '{"action":{"type":"screenshot"}}' http://127.0.0.1:8787/admin/commands`.
+    /// `device_id` may be left out while a single device is enrolled. The
+    /// admin endpoints are unauthenticated; this is a local stand-in only.
+    fn queue_command(&mut self, body: &[u8]) -> Reply {
+        let req: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(e) => return error(400, &e.to_string()),
+        };
+        let device_id = match req["device_id"].as_str() {
+            Some(id) => id.to_string(),
+            None if self.devices.len() == 1 => self.devices.keys().next().cloned().unwrap_or_default(),
+            None => return error(400, "device_id is required when several devices are enrolled"),
+        };
+        if !self.devices.contains_key(&device_id) || !req["action"].is_object() {
+            return error(400, "Unknown device or missing action");
+        }
+        self.next_id += 1;
+        let id = format!("c{}_{}", self.started, self.next_id);
+        let now = now_ms();
+        let command = serde_json::json!({
+            "id": id,
+            "device_id": device_id,
+            "issued_by": req["issued_by"].as_str().unwrap_or("admin"),
+            "issued_ms": now,
+            "expires_ms": now + req["ttl_secs"].as_u64().unwrap_or(600) * 1000,
+            "action": req["action"],
+        })
+        .to_string();
+        let signature = hex(&self.server_key.sign(command.as_bytes()).to_bytes());
+        println!("📤 {} queued for {}: {}", id, device_id, req["action"]);
+        self.commands.insert(
+            id.clone(),
+            Command {
+                device_id,
+                command,
+                signature,
+                state: "queued",
+                result: None,
+            },
+        );
+        (200, serde_json::json!({ "id": id }))
+    }
+
+    fn command_state(&self, id: &str) -> Reply {
+        match self.commands.get(id) {
+            Some(c) => (200, serde_json::json!({ "id": id, "state": c.state, "result": c.result })),
+            None => error(404, "Unknown command"),
+        }
+    }
+
+    /// Commands not yet acknowledged, so one lost in transit is sent again.
+    /// `None` when there are none and the agent is willing to wait.
+    fn poll_commands(&self, device_id: &str, wait_secs: u64) -> Option<Reply> {
+        let pending: Vec<serde_json::Value> = self
+            .commands
+            .values()
+            .filter(|c| c.device_id == device_id && c.state == "queued")
+            .map(|c| serde_json::json!({ "command": c.command, "signature": c.signature }))
+            .collect();
+        if pending.is_empty() && wait_secs > 0 {
+            return None;
+        }
+        Some((200, serde_json::json!({ "commands": pending })))
+    }
+
+    fn update_command(&mut self, id: &str, device_id: &str, state: &'static str, result: Option<serde_json::Value>) -> Reply {
+        let Some(command) = self.commands.get_mut(id).filter(|c| c.device_id == device_id) else {
+            return error(404, "Unknown command");
+        };
+        command.state = state;
+        if let Some(result) = result {
+            println!("📨 {} finished: {}", id, result);
+            command.result = Some(result);
+        }
+        (200, serde_json::json!({ "id": id, "state": state }))
+    }
+
+    fn enroll(&mut self, body: &[u8]) -> Reply {
+        let req: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(e) => return error(400, &e.to_string()),
+        };
+        if req["enrollment_code"].as_str() != Some(self.enroll_code.as_str()) {
+            return error(403, "Unknown enrollment code");
+        }
+        let (Some(device_id), Some(public_key)) = (req["device_id"].as_str(), req["public_key"].as_str()) else {
+            return error(400, "device_id and public_key are required");
+        };
+        if unhex(public_key).is_none_or(|k| k.len() != 32) {
+            return error(400, "public_key must be 32 bytes of hex");
+        }
+        self.devices.insert(device_id.to_string(), public_key.to_string());
+        let saved = serde_json::to_string_pretty(&self.devices).map_err(|e| e.to_string()).and_then(|json| {
+            fs::write(self.data.join("devices.json"), json).map_err(|e| e.to_string())
+        });
+        if let Err(e) = saved {
+            return error(500, &e);
+        }
+        println!(
+            "🆔 Enrolled {} ({} on {})",
+            device_id,
+            req["user"].as_str().unwrap_or("?"),
+            req["hostname"].as_str().unwrap_or("?")
+        );
+        let policy_key = hex(self.server_key.verifying_key().as_bytes());
+        (
+            200,
+            serde_json::json!({ "device_id": device_id, "employee": self.employee, "policy_key": policy_key }),
+        )
+    }
+
+    /// Keep the latest heartbeat per device in memory and in `<data>/heartbeats/`
+    fn heartbeat(&mut self, device_id: &str, body: &[u8]) -> Reply {
+        let beat: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(e) => return error(400, &e.to_string()),
+        };
+        let dir = self.data.join("heartbeats");
+        let saved = fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(dir.join(format!("{}.json", device_id)), body));
+        if let Err(e) = saved {
+            return error(500, &e.to_string());
+        }
+        println!(
+            "💓 {} up {}s, {} queued, last error: {}",
+            device_id, beat["uptime_secs"], beat["upload_queue"]["pending"], beat["last_error"]["message"]
+        );
+        self.heartbeats.insert(device_id.to_string(), beat);
+        (200, serde_json::json!({ "ok": true }))
+    }
+
+    /// The policy text is signed exactly as stored, so any edit takes effect as-is
+    fn policy(&self) -> Reply {
+        let text = match fs::read_to_string(self.data.join("policy.json")) {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return error(404, "No policy"),
+            Err(e) => return error(500, &e.to_string()),
+        };
+        let mut signature = self.server_key.sign(text.as_bytes()).to_bytes();
+        if self.bad_policy_signature {
+            signature[0] ^= 0xff;
+        }
+        (200, serde_json::json!({ "policy": text, "signature": hex(&signature) }))
+    }
+
+    /// Check the device signature: method, path, timestamp and body hash
+    fn authenticate(&self, request: &Request, path: &str, body: &[u8]) -> Result<(), Reply> {
+        let (Some(device_id), Some(ts), Some(sig)) = (
+            header(request, "X-Device-Id"),
+            header(request, "X-Timestamp").and_then(|t| t.parse::<u64>().ok()),
+            header(request, "X-Signature").and_then(unhex),
+        ) else {
+            return Err(error(401, "Missing device signature"));
+        };
+        let key = self
+            .devices
+            .get(device_id)
+            .and_then(|k| unhex(k))
+            .and_then(|k| <[u8; 32]>::try_from(k).ok())
+            .and_then(|k| VerifyingKey::from_bytes(&k).ok())
+            .ok_or_else(|| error(401, "Device is not enrolled"))?;
+        if now_ms().abs_diff(ts) > MAX_CLOCK_SKEW_MS {
+            return Err(error(401, "Request timestamp too far from server time"));
+        }
+        let payload = format!("{}\n{}\n{}\n{:x}", request.method(), path, ts, Sha256::digest(body));
+        let sig = <[u8; 64]>::try_from(sig).map_err(|_| error(401, "Malformed signature"))?;
+        key.verify_strict(payload.as_bytes(), &Signature::from_bytes(&sig))
+            .map_err(|_| error(401, "Bad signature"))
+    }
+
+    fn open(&mut self, body: &[u8]) -> Reply {
+        let req: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(e) => return error(400, &e.to_string()),
+        };
+        let (Some(kind), Some(file_name), Some(size), Some(sha256)) = (
+            req["kind"].as_str(),
+            req["file_name"].as_str(),
+            req["size"].as_u64(),
+            req["sha256"].as_str(),
+        ) else {
+            return error(400, "kind, file_name, size and sha256 are required");
+        };
+        if file_name.contains(['/', '\\']) || file_name.starts_with('.') {
+            return error(400, "Invalid file_name");
+        }
+        self.next_id += 1;
+        let id = format!("u{}_{}", self.started, self.next_id);
+        let part = self.data.join(format!("{}.part", id));
+        if let Err(e) = fs::write(&part, b"") {
+            return error(500, &e.to_string());
+        }
+        let mut upload = Upload {
+            kind: kind.to_string(),
+            file_name: file_name.to_string(),
+            size,
+            sha256: sha256.to_string(),
+            meta: req["meta"].clone(),
+            part,
+            offset: 0,
+            complete: false,
+        };
+        println!("📥 {} opened: {} {} ({} bytes)", id, upload.kind, upload.file_name, size);
+        if size == 0 {
+            if let Err(reply) = self.finish(&id, &mut upload) {
+                return reply;
+            }
+        }
+        let reply = progress(&id, &upload);
+        self.uploads.insert(id, upload);
+        reply
+    }
+
+    fn append(&mut self, id: &str, (start, end, total): (u64, u64, u64), body: &[u8]) -> Reply {
+        let Some(mut upload) = self.uploads.remove(id) else {
+            return error(404, "Unknown upload");
+        };
+        // Out-of-order or repeated chunks are ignored; the client resumes from `offset`
+        if start == upload.offset && total == upload.size && end + 1 - start == body.len() as u64 && end < total {
+            let written = OpenOptions::new()
+                .append(true)
+                .open(&upload.part)
+                .and_then(|mut f| f.write_all(body));
+            if let Err(e) = written {
+                self.uploads.insert(id.to_string(), upload);
+                return error(500, &e.to_string());
+            }
+            upload.offset = end + 1;
+            if upload.offset == upload.size {
+                if let Err(reply) = self.finish(id, &mut upload) {
+                    return reply;
+                }
+            }
+        }
+        let reply = progress(id, &upload);
+        self.uploads.insert(id.to_string(), upload);
+        reply
+    }
+
+    /// Verify the hash and move the file into `<data>/<kind>/`. A mismatch
+    /// drops the session with 409 so the client starts over.
+    fn finish(&self, id: &str, upload: &mut Upload) -> Result<(), Reply> {
+        let bytes = fs::read(&upload.part).map_err(|e| error(500, &e.to_string()))?;
+        let actual = format!("{:x}", Sha256::digest(&bytes));
+        if actual != upload.sha256 {
+            let _ = fs::remove_file(&upload.part);
+            eprintln!("❌ {} failed verification", id);
+            return Err(error(409, "SHA-256 mismatch"));
+        }
+        let dir = self.data.join(&upload.kind);
+        fs::create_dir_all(&dir).map_err(|e| error(500, &e.to_string()))?;
+        let dest = dir.join(&upload.file_name);
+        fs::rename(&upload.part, &dest).map_err(|e| error(500, &e.to_string()))?;
+        let meta = serde_json::to_string_pretty(&upload.meta).unwrap_or_default();
+        let _ = fs::write(dest.with_extension("meta.json"), meta);
+        upload.complete = true;
+        println!("✅ {} stored {}", id, dest.display());
+        Ok(())
+    }
+}
+
+/// Settings taken from the command line
+pub struct Options {
+    pub data: PathBuf,
+    pub fail_every: u64,
+    pub enroll_code: String,
+    pub employee: Option<String>,
+    pub bad_policy_signature: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            data: PathBuf::from("mock_server_data"),
+            fail_every: 0,
+            enroll_code: "DEMO-ENROLL".to_string(),
+            employee: None,
+            bad_policy_signature: false,
+        }
+    }
+}
+
+impl MockServer {
+    /// Load enrolled devices and the server key from `options.data`,
+    /// creating the key on first start
+    fn open_data(options: &Options) -> Result<Self, String> {
+        let data = options.data.clone();
+        fs::create_dir_all(&data).map_err(|e| format!("cannot create data directory: {}", e))?;
+        let devices = fs::read_to_string(data.join("devices.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let key_path = data.join("server_key.hex");
+        let server_key = match fs::read_to_string(&key_path).ok().and_then(|k| unhex(k.trim())) {
+            Some(k) => SigningKey::from_bytes(&k.try_into().map_err(|_| "server_key.hex must hold 32 bytes")?),
+            None => {
+                let mut secret = [0u8; 32];
+                rand::rngs::OsRng.fill_bytes(&mut secret);
+                let key = SigningKey::from_bytes(&secret);
+                fs::write(&key_path, hex(&key.to_bytes())).map_err(|e| format!("cannot save server key: {}", e))?;
+                key
+            }
+        };
+        Ok(Self {
+            data,
+            server_key,
+            bad_policy_signature: options.bad_policy_signature,
+            enroll_code: options.enroll_code.clone(),
+            employee: options.employee.clone(),
+            devices,
+            uploads: HashMap::new(),
+            commands: HashMap::new(),
+            heartbeats: HashMap::new(),
+            started: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            next_id: 0,
+        })
+    }
+}
+
+/// Serve from a background thread. Returns the address actually bound, so
+/// tests can ask for port 0 and run side by side.
+pub fn spawn(addr: &str, options: Options) -> Result<SocketAddr, String> {
+    let state = MockServer::open_data(&options)?;
+    let server = Server::http(addr).map_err(|e| format!("cannot bind {}: {}", addr, e))?;
+    let bound = server.server_addr().to_ip().ok_or("not listening on an IP address")?;
+    std::thread::spawn(move || serve(server, state, options.fail_every));
+    Ok(bound)
+}
+
+fn main() {
+    let mut addr = "127.0.0.1:8787".to_string();
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--bad-policy-signature" {
+            options.bad_policy_signature = true;
+            continue;
+        }
+        match (arg.as_str(), args.next()) {
+            ("--addr", Some(v)) => addr = v,
+            ("--data", Some(v)) => options.data = PathBuf::from(v),
+            ("--fail-every", Some(v)) => options.fail_every = v.parse().expect("--fail-every takes a number"),
+            ("--enroll-code", Some(v)) => options.enroll_code = v,
+            ("--employee", Some(v)) => options.employee = Some(v),
+            _ => {
+                eprintln!(
+                    "usage: mock_server [--addr HOST:PORT] [--data DIR] [--enroll-code CODE] [--employee NAME] [--fail-every N] [--bad-policy-signature]"
+                );
+                std::process::exit(2);
+            }
+        }
+    }
+    let state = MockServer::open_data(&options).expect("cannot open data directory");
+    let server = Server::http(&addr).expect("cannot bind");
+    println!("Mock collection server on http://{} storing into {}", addr, options.data.display());
+    serve(server, state, options.fail_every);
+}
+
+fn serve(server: Server, mut state: MockServer, fail_every: u64) {
+    // One thread serves everything; long polls are parked instead of blocking it
+    let mut parked: Vec<Parked> = Vec::new();
+    let mut n = 0u64;
+    loop {
+        match server.recv_timeout(Duration::from_millis(100)) {
+            Ok(Some(mut request)) => {
+                n += 1;
+                let reply = if fail_every > 0 && n.is_multiple_of(fail_every) {
+                    Some(error(503, "Injected failure"))
+                } else {
+                    state.handle(&mut request)
+                };
+                match reply {
+                    Some(reply) => respond(request, reply),
+                    None => {
+                        let device_id = header(&request, "X-Device-Id").unwrap_or_default().to_string();
+                        let wait = request
+                            .url()
+                            .split_once("wait=")
+                            .and_then(|(_, w)| w.parse().ok())
+                            .unwrap_or(0u64)
+                            .min(60);
+                        parked.push(Parked {
+                            request,
+                            device_id,
+                            until: Instant::now() + Duration::from_secs(wait),
+                        });
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Server error: {}", e);
+                return;
+            }
+        }
+        let now = Instant::now();
+        for p in std::mem::take(&mut parked) {
+            match state.poll_commands(&p.device_id, if now >= p.until { 0 } else { 1 }) {
+                Some(reply) => respond(p.request, reply),
+                None => parked.push(p),
+            }
+        }
+    }
+}
+
+fn respond(request: Request, (code, body): Reply) {
+    let json = Header::from_bytes("Content-Type", "application/json").expect("static header");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(code)
+        .with_header(json);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to respond: {}", e);
+    }
+}
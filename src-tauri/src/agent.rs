@@ -0,0 +1,898 @@
+use chrono::Local;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audit::{AuditEntry, AuditLog, AuditQuery};
+use crate::capture::{
+    ffmpeg_encoders, prepare_recording, spawn_screenshot_worker, spawn_video, stop_screenshot_worker,
+    stop_video_worker, take_screenshot_now, VideoMode,
+};
+use crate::current_ts_millis;
+use crate::encoding::{self, EncodingProfile};
+use crate::ffmpeg::GrabBackend;
+use crate::heartbeat::{self, AgentError, DiskUsage, Heartbeat, QueueDepth, WorkerHealth};
+use crate::identity::{DeviceIdentity, RecordIdentity};
+use crate::input::{Metrics, spawn_input_listener};
+use crate::policy::{self, MonitoringPolicy, PolicySource, PolicyStatus};
+use crate::privacy::PrivacyPause;
+use crate::redaction::{self, RedactionPolicy};
+use crate::remote::{self, CommandChannel, CommandResult, LogName, RecentIds, Received, RemoteAction, RemoteStatus};
+use crate::schedule::ScheduleConfig;
+use crate::sessions::SessionManager;
+use crate::storage::{self, CaptureDirs, QuotaConfig, StorageRoots, StorageStatus};
+use crate::supervisor::{Supervisor, SupervisorEventKind, SupervisorLog};
+use crate::upload::{self, UploadClient, UploadConfig, UploadKind, UploadQueue, UploadStatus};
+use crate::users::UserStore;
+use crate::video::RecordingTarget;
+use crate::video_health::VideoHealth;
+use crate::worker::{Backoff, WorkerControl};
+
+#[derive(Clone)]
+pub struct CaptureHandle {
+    pub(crate) started_ms: u64,
+    pub(crate) input: Arc<WorkerControl>,
+    pub(crate) input_join_handle: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    pub(crate) screenshot: Arc<WorkerControl>,
+    pub(crate) join_handle: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    /// Schedule of the screenshot loop that should be running, if any
+    pub(crate) screenshot_spec: Arc<Mutex<Option<ScheduleConfig>>>,
+    pub(crate) video: Arc<WorkerControl>,
+    /// Recording that should be running, if any
+    pub(crate) video_spec: Arc<Mutex<Option<(RecordingTarget, VideoMode)>>>,
+    pub(crate) video_health: Arc<Mutex<VideoHealth>>,
+    pub(crate) ffmpeg_encoders: Arc<Mutex<Option<Vec<String>>>>,
+    /// Built-in encoding profiles with the configured ones laid over them
+    pub(crate) encoding_profiles: Arc<Mutex<Vec<EncodingProfile>>>,
+    pub(crate) video_join_handle: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    pub(crate) last_input_ts: Arc<AtomicU64>,
+    pub(crate) input_metrics: Arc<Mutex<Metrics>>, // cumulative counters from the input listener
+    pub(crate) activity_queue: Arc<Mutex<VecDeque<String>>>,
+    pub(crate) log_file_lock: Arc<Mutex<()>>,
+    pub(crate) redaction: Arc<Mutex<RedactionPolicy>>,
+    pub(crate) storage_quota: Arc<Mutex<QuotaConfig>>,
+    pub(crate) capture_dirs: Arc<CaptureDirs>,
+    pub(crate) storage: Arc<Mutex<StorageStatus>>,
+    /// Set by the storage manager when the disk is critically low
+    pub(crate) captures_paused: Arc<AtomicBool>,
+    /// Employee privacy pause and what is left of today's allowance
+    pub(crate) privacy_pause: Arc<Mutex<PrivacyPause>>,
+    pub(crate) users: Arc<Mutex<UserStore>>,
+    pub(crate) sessions: Arc<Mutex<SessionManager>>,
+    pub(crate) audit: Arc<Mutex<AuditLog>>,
+    pub(crate) upload_config: Arc<Mutex<UploadConfig>>,
+    pub(crate) upload_queue: Arc<UploadQueue>,
+    pub(crate) upload: Arc<Mutex<UploadStatus>>,
+    /// Activity events not yet handed to the upload queue
+    pub(crate) activity_batch: Arc<Mutex<Vec<String>>>,
+    pub(crate) identity: Arc<Mutex<DeviceIdentity>>,
+    /// Server-pushed policy in effect, if any
+    pub(crate) policy: Arc<Mutex<PolicyStatus>>,
+    pub(crate) remote: Arc<Mutex<RemoteStatus>>,
+    pub(crate) last_error: Arc<Mutex<Option<AgentError>>>,
+    /// Epoch millis of the last heartbeat the server accepted, 0 if none
+    pub(crate) last_heartbeat_ms: Arc<AtomicU64>,
+    /// Worker exits, panics and restarts seen by the supervisor
+    pub(crate) supervisor: Arc<Mutex<SupervisorLog>>,
+}
+
+impl CaptureHandle {
+    pub fn new() -> Self {
+        let (identity, identity_error) = match load_device_identity() {
+            Ok(identity) => (identity, None),
+            Err(e) => (DeviceIdentity::generate(current_ts_millis()), Some(e)),
+        };
+        let capture = Self {
+            started_ms: current_ts_millis(),
+            input: Arc::new(WorkerControl::new()),
+            input_join_handle: Arc::new(Mutex::new(None)),
+            screenshot: Arc::new(WorkerControl::new()),
+            join_handle: Arc::new(Mutex::new(None)),
+            screenshot_spec: Arc::new(Mutex::new(None)),
+            video: Arc::new(WorkerControl::new()),
+            video_spec: Arc::new(Mutex::new(None)),
+            video_health: Arc::new(Mutex::new(VideoHealth::default())),
+            ffmpeg_encoders: Arc::new(Mutex::new(None)),
+            encoding_profiles: Arc::new(Mutex::new(load_encoding_profiles())),
+            video_join_handle: Arc::new(Mutex::new(None)),
+            last_input_ts: Arc::new(AtomicU64::new(current_ts_millis())),
+            input_metrics: Arc::new(Mutex::new(Metrics::default())),
+            activity_queue: Arc::new(Mutex::new(VecDeque::with_capacity(200))),
+            log_file_lock: Arc::new(Mutex::new(())),
+            redaction: Arc::new(Mutex::new(load_redaction_policy())),
+            storage_quota: Arc::new(Mutex::new(load_storage_quota())),
+            capture_dirs: Arc::new(load_capture_dirs()),
+            storage: Arc::new(Mutex::new(StorageStatus::default())),
+            captures_paused: Arc::new(AtomicBool::new(false)),
+            privacy_pause: Arc::new(Mutex::new(load_privacy_pause())),
+            users: Arc::new(Mutex::new(load_user_store())),
+            sessions: Arc::new(Mutex::new(SessionManager::default())),
+            audit: Arc::new(Mutex::new(AuditLog::new(AUDIT_DIR))),
+            upload_config: Arc::new(Mutex::new(load_upload_config())),
+            upload_queue: Arc::new(UploadQueue::new(UPLOAD_QUEUE_DIR)),
+            upload: Arc::new(Mutex::new(UploadStatus::default())),
+            activity_batch: Arc::new(Mutex::new(Vec::new())),
+            identity: Arc::new(Mutex::new(identity)),
+            policy: Arc::new(Mutex::new(PolicyStatus::default())),
+            remote: Arc::new(Mutex::new(RemoteStatus::default())),
+            last_error: Arc::new(Mutex::new(None)),
+            last_heartbeat_ms: Arc::new(AtomicU64::new(0)),
+            supervisor: Arc::new(Mutex::new(SupervisorLog::default())),
+        };
+        // Enrolling saves a fresh identity file over the unusable one
+        if let Some(e) = identity_error {
+            let msg = format!("Cannot load device identity, running unenrolled: {}", e);
+            capture.supervisor.lock().unwrap().record("identity", SupervisorEventKind::Failed, msg.clone());
+            capture.report_error("identity", msg);
+        }
+        capture
+    }
+
+    /// No screenshots or recordings while the disk is critically low, a
+    /// privacy pause is in effect or the policy's work hours are over
+    pub(crate) fn captures_held(&self) -> bool {
+        self.captures_paused.load(Ordering::SeqCst)
+            || self.privacy_pause.lock().unwrap().is_active(current_ts_millis())
+            || self
+                .policy
+                .lock()
+                .unwrap()
+                .work_hours()
+                .is_some_and(|hours| !hours.contains(Local::now().naive_local()))
+    }
+
+    /// Remember a failure for the heartbeat's `last_error`
+    pub(crate) fn report_error(&self, source: &str, message: impl Into<String>) {
+        *self.last_error.lock().unwrap() = Some(AgentError {
+            source: source.to_string(),
+            message: message.into(),
+            at_ms: current_ts_millis(),
+        });
+    }
+
+    pub(crate) fn record_identity(&self) -> RecordIdentity {
+        self.identity.lock().unwrap().record_identity()
+    }
+
+    /// Queue a finished capture file for the collection server, if uploads
+    /// of that kind are enabled
+    pub(crate) fn enqueue_upload(&self, kind: UploadKind, path: &std::path::Path, meta: serde_json::Value) {
+        if !self.upload_config.lock().unwrap().wants(kind) {
+            return;
+        }
+        if let Err(e) = self.upload_queue.enqueue(kind, path, meta, current_ts_millis()) {
+            eprintln!("Failed to queue {} for upload: {}", path.display(), e);
+        }
+    }
+}
+
+impl Default for CaptureHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn record_audit(state: &CaptureHandle, actor: Option<String>, action: &str, params: serde_json::Value, error: Option<String>) {
+    write_audit(state, actor, action, params, error, false);
+}
+
+/// `record_audit` for status polls, marked so audit queries can leave them out
+pub(crate) fn record_poll(state: &CaptureHandle, actor: Option<String>, action: &str, params: serde_json::Value, error: Option<String>) {
+    write_audit(state, actor, action, params, error, true);
+}
+
+fn write_audit(state: &CaptureHandle, actor: Option<String>, action: &str, params: serde_json::Value, error: Option<String>, poll: bool) {
+    let now = current_ts_millis();
+    let entry = AuditEntry {
+        timestamp_ms: now,
+        timestamp: Local::now().to_rfc3339(),
+        actor,
+        action: action.to_string(),
+        params,
+        ok: error.is_none(),
+        error,
+        poll,
+    };
+    if let Err(e) = state.audit.lock().unwrap().record(&entry) {
+        eprintln!("Failed to write audit log: {}", e);
+    }
+}
+
+pub(crate) const REDACTION_RULES_PATH: &str = "config/redaction_rules.json";
+pub(crate) const STORAGE_QUOTA_PATH: &str = "config/storage_quota.json";
+const CAPTURE_DIRS_PATH: &str = "config/capture_dirs.json";
+pub(crate) const ENCODING_PROFILES_PATH: &str = "config/encoding_profiles.json";
+pub(crate) const USERS_PATH: &str = "config/users.json";
+/// Kept apart from `LOGS_DIR` so storage quotas never delete audit records
+const AUDIT_DIR: &str = "audit";
+/// Next to the audit log for the same reason
+pub(crate) const DENIED_LOG_PATH: &str = "audit/access_denied.jsonl";
+/// Where older versions kept the denial log
+const LEGACY_DENIED_LOG_PATH: &str = "logs/access_denied.jsonl";
+pub(crate) const UPLOAD_CONFIG_PATH: &str = "config/upload.json";
+const UPLOAD_QUEUE_DIR: &str = "upload_queue";
+pub(crate) const DEVICE_IDENTITY_PATH: &str = "config/device.json";
+/// Last verified server policy, applied at startup while the server is unreachable
+const POLICY_CACHE_PATH: &str = "config/policy.json";
+/// Running privacy pause and today's usage, kept across restarts
+pub(crate) const PRIVACY_PAUSE_PATH: &str = "status/privacy_pause.json";
+/// Commands already run, so a restart does not run a redelivered one again
+const REMOTE_IDS_PATH: &str = "status/remote_ids.json";
+/// Latest heartbeat, for checking on the agent without the UI
+pub(crate) const HEARTBEAT_PATH: &str = "status/heartbeat.json";
+const HEARTBEAT_SECS: u64 = 60;
+/// Supervisor events included in each heartbeat
+const HEALTH_EVENTS: usize = 20;
+const SUPERVISOR_CHECK_SECS: u64 = 2;
+pub(crate) const LOGS_DIR: &str = "logs";
+fn load_user_store() -> UserStore {
+    UserStore::load(std::path::Path::new(USERS_PATH)).unwrap_or_else(|e| {
+        eprintln!("Failed to load user store: {}", e);
+        UserStore::default()
+    })
+}
+
+fn load_storage_quota() -> QuotaConfig {
+    storage::load_config(std::path::Path::new(STORAGE_QUOTA_PATH)).unwrap_or_else(|e| {
+        eprintln!("Failed to load storage quotas, using defaults: {}", e);
+        QuotaConfig::default()
+    })
+}
+
+fn load_capture_dirs() -> CaptureDirs {
+    storage::load_capture_dirs(std::path::Path::new(CAPTURE_DIRS_PATH)).unwrap_or_else(|e| {
+        eprintln!("Failed to load capture directories, using defaults: {}", e);
+        CaptureDirs::default()
+    })
+}
+
+fn load_encoding_profiles() -> Vec<EncodingProfile> {
+    encoding::load_profiles(std::path::Path::new(ENCODING_PROFILES_PATH)).unwrap_or_else(|e| {
+        eprintln!("Failed to load encoding profiles, using built-ins: {}", e);
+        encoding::builtin_profiles()
+    })
+}
+
+fn load_upload_config() -> UploadConfig {
+    upload::load_config(std::path::Path::new(UPLOAD_CONFIG_PATH)).unwrap_or_else(|e| {
+        eprintln!("Failed to load upload settings, uploads disabled: {}", e);
+        UploadConfig::default()
+    })
+}
+
+fn load_device_identity() -> Result<DeviceIdentity, String> {
+    DeviceIdentity::load_or_create(std::path::Path::new(DEVICE_IDENTITY_PATH), current_ts_millis())
+        .map_err(|e| format!("{}: {}", DEVICE_IDENTITY_PATH, e))
+}
+
+/// Move a denial log left in `LOGS_DIR` by an older version out of reach of
+/// storage quotas
+fn migrate_denied_log() {
+    let (old, new) = (std::path::Path::new(LEGACY_DENIED_LOG_PATH), std::path::Path::new(DENIED_LOG_PATH));
+    if !old.exists() || new.exists() {
+        return;
+    }
+    let moved = std::fs::create_dir_all(AUDIT_DIR).and_then(|_| std::fs::rename(old, new));
+    if let Err(e) = moved {
+        eprintln!("Failed to move {} to {}: {}", old.display(), new.display(), e);
+    }
+}
+
+fn load_privacy_pause() -> PrivacyPause {
+    PrivacyPause::load(std::path::Path::new(PRIVACY_PAUSE_PATH)).unwrap_or_else(|e| {
+        eprintln!("Failed to load privacy pause state: {}", e);
+        PrivacyPause::default()
+    })
+}
+
+fn load_redaction_policy() -> RedactionPolicy {
+    let rules = redaction::load_rules(std::path::Path::new(REDACTION_RULES_PATH))
+        .and_then(RedactionPolicy::new);
+    match rules {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Failed to load redaction rules, using defaults: {}", e);
+            RedactionPolicy::default()
+        }
+    }
+}
+
+/// Periodically enforce quotas and pause captures when the disk is critically low
+fn spawn_storage_manager(capture: CaptureHandle) -> thread::JoinHandle<()> {
+    let roots = StorageRoots {
+        screenshots: capture.capture_dirs.screenshots.clone(),
+        videos: capture.capture_dirs.videos.clone(),
+        logs: PathBuf::from(LOGS_DIR),
+        upload_queue: PathBuf::from(UPLOAD_QUEUE_DIR),
+        audit: PathBuf::from(AUDIT_DIR),
+    };
+    thread::spawn(move || loop {
+        let config = capture.storage_quota.lock().unwrap().clone();
+        let mut status = capture.storage.lock().unwrap().clone();
+        storage::enforce(&config, &roots, || storage::free_space(&roots.screenshots), &mut status);
+        status.last_check_ms = current_ts_millis();
+
+        let was_paused = capture.captures_paused.swap(status.paused, Ordering::SeqCst);
+        match (&status.warning, was_paused) {
+            (Some(w), false) => {
+                eprintln!("⚠️ {}", w);
+                capture.report_error("storage", w.clone());
+            }
+            (None, true) => println!("💾 Disk space recovered, resuming captures"),
+            _ => {}
+        }
+        let paused = status.paused;
+        *capture.storage.lock().unwrap() = status;
+
+        // Check often while paused so captures resume promptly
+        let secs = if paused { 10 } else { config.check_interval_secs.max(10) };
+        thread::sleep(Duration::from_secs(secs));
+    })
+}
+
+const UPLOAD_POLL_SECS: u64 = 5;
+
+/// Hand buffered activity events to the upload queue as one JSON-lines file
+fn flush_activity_batch(capture: &CaptureHandle) {
+    let lines = std::mem::take(&mut *capture.activity_batch.lock().unwrap());
+    if lines.is_empty() {
+        return;
+    }
+    let name = format!("activity_{}.jsonl", Local::now().format("%Y%m%d_%H%M%S"));
+    let meta = serde_json::json!({ "events": lines.len(), "identity": capture.record_identity() });
+    let body = lines.join("\n") + "\n";
+    if let Err(e) = capture.upload_queue.enqueue_bytes(UploadKind::Activity, &name, body.as_bytes(), meta, current_ts_millis()) {
+        eprintln!("Failed to queue activity batch: {}", e);
+    }
+}
+
+/// Drain the upload queue in the background. Items wait in `UPLOAD_QUEUE_DIR`
+/// while offline and survive restarts.
+fn spawn_uploader(capture: CaptureHandle) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut client: Option<(String, UploadClient)> = None;
+        let mut last_batch = Instant::now();
+        loop {
+            let config = capture.upload_config.lock().unwrap().clone();
+            if last_batch.elapsed() >= Duration::from_secs(config.activity_batch_secs.max(5)) {
+                flush_activity_batch(&capture);
+                last_batch = Instant::now();
+            }
+
+            let signer = capture.identity.lock().unwrap().signer();
+            if !config.enabled || config.endpoint.trim().is_empty() {
+                capture.upload.lock().unwrap().enabled = false;
+            } else if let Some(signer) = signer {
+                if client.as_ref().is_none_or(|(endpoint, _)| *endpoint != config.endpoint) {
+                    client = Some((config.endpoint.clone(), UploadClient::new(&config.endpoint, signer)));
+                }
+                if let Some((_, c)) = &client {
+                    let mut status = capture.upload.lock().unwrap().clone();
+                    let previous_error = status.last_error.clone();
+                    upload::run_once(&capture.upload_queue, c, &config, current_ts_millis(), &mut status);
+                    if let Some(e) = status.last_error.as_ref().filter(|e| previous_error.as_ref() != Some(*e)) {
+                        capture.report_error("upload", e.clone());
+                    }
+                    *capture.upload.lock().unwrap() = status;
+                }
+            } else {
+                let mut status = capture.upload.lock().unwrap();
+                status.enabled = true;
+                status.last_error = Some("Device is not enrolled".into());
+            }
+            thread::sleep(Duration::from_secs(UPLOAD_POLL_SECS));
+        }
+    })
+}
+
+/// Actor recorded in the audit log for changes made by the server
+const SERVER_ACTOR: &str = "server";
+
+/// Install `policy` and make the workers match it. Older or equal versions
+/// are ignored. Workers are only touched when a new version arrives, so a
+/// manager can still start or stop captures by hand in between.
+///
+/// Everything that can be refused is checked before anything changes; if a
+/// worker still fails to start, the previous rules and workers are put back.
+fn apply_policy(capture: &CaptureHandle, policy: MonitoringPolicy, source: PolicySource) -> Result<bool, String> {
+    if !capture.policy.lock().unwrap().accepts(&policy) {
+        return Ok(false);
+    }
+    let redaction = RedactionPolicy::new(policy.redaction_rules.clone())?;
+    if let Some(name) = &policy.video.profile {
+        encoding::profile_by_name(&capture.encoding_profiles.lock().unwrap(), name)
+            .ok_or_else(|| format!("Unknown encoding profile '{}'", name))?;
+    }
+    if policy.screenshots_enabled {
+        std::fs::create_dir_all(&capture.capture_dirs.screenshots).map_err(|e| e.to_string())?;
+    }
+    let target = if policy.video.enabled {
+        Some(prepare_recording(capture, policy.video.profile.clone(), None)?)
+    } else {
+        None
+    };
+
+    println!("📋 Applying policy version {} ({:?})", policy.version, source);
+    let previous_screenshots = capture.screenshot_spec.lock().unwrap().clone();
+    let previous_video = capture.video_spec.lock().unwrap().clone();
+    let previous_redaction = std::mem::replace(&mut *capture.redaction.lock().unwrap(), redaction);
+    stop_screenshot_worker(capture);
+    stop_video_worker(capture);
+    if let Err(e) = start_policy_workers(capture, &policy, target) {
+        eprintln!("🚫 Policy version {} not applied, restoring previous settings: {}", policy.version, e);
+        *capture.redaction.lock().unwrap() = previous_redaction;
+        stop_screenshot_worker(capture);
+        stop_video_worker(capture);
+        if let Some(schedule) = previous_screenshots {
+            let _ = spawn_screenshot_worker(capture, schedule);
+        }
+        if let Some((target, mode)) = previous_video {
+            let _ = spawn_video(capture, target, mode);
+        }
+        return Err(e);
+    }
+    // Only now, so a policy whose workers failed to start is tried again on
+    // the next sync instead of counting as applied
+    let mut status = capture.policy.lock().unwrap();
+    status.policy = Some(policy);
+    status.source = Some(source);
+    status.applied_ms = Some(current_ts_millis());
+    status.last_error = None;
+    Ok(true)
+}
+
+fn start_policy_workers(capture: &CaptureHandle, policy: &MonitoringPolicy, target: Option<RecordingTarget>) -> Result<(), String> {
+    if policy.screenshots_enabled {
+        spawn_screenshot_worker(capture, policy.schedule.clone())?;
+    }
+    if let Some(target) = target {
+        spawn_video(
+            capture,
+            target,
+            VideoMode::Continuous {
+                segment_secs: policy.video.segment_secs,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Verify the cached policy and apply it, so the agent follows the last
+/// known policy even when it starts offline
+fn apply_cached_policy(capture: &CaptureHandle) {
+    let signed = match policy::load_cached(std::path::Path::new(POLICY_CACHE_PATH)) {
+        Ok(Some(signed)) => signed,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Failed to load cached policy: {}", e);
+            return;
+        }
+    };
+    let identity = capture.identity.lock().unwrap().clone();
+    let Some(key) = identity.enrollment.as_ref().and_then(|e| e.policy_key.clone()) else {
+        return;
+    };
+    let result = signed
+        .verify(&key, &identity.device_id)
+        .and_then(|policy| apply_policy(capture, policy, PolicySource::Cache));
+    if let Err(e) = result {
+        eprintln!("🚫 Cached policy not applied: {}", e);
+        capture.policy.lock().unwrap().last_error = Some(e);
+    }
+}
+
+/// Fetch the server's policy and apply it if it is newer than the one in
+/// effect. Returns the version applied, if any. Policies that fail
+/// verification are never applied or cached, and are recorded in the audit
+/// log.
+pub(crate) fn sync_policy(capture: &CaptureHandle) -> Result<Option<u64>, String> {
+    let identity = capture.identity.lock().unwrap().clone();
+    let (Some(signer), Some(enrollment)) = (identity.signer(), identity.enrollment.as_ref()) else {
+        return Err("Device is not enrolled".into());
+    };
+    let key = enrollment
+        .policy_key
+        .as_deref()
+        .ok_or("The server sent no policy key at enrollment; enroll the device again")?;
+
+    let fetched = policy::fetch(&enrollment.server, &signer, current_ts_millis());
+    capture.policy.lock().unwrap().last_fetch_ms = Some(current_ts_millis());
+    let Some(signed) = fetched? else {
+        return Ok(None);
+    };
+    let policy = match signed.verify(key, &identity.device_id) {
+        Ok(policy) => policy,
+        Err(e) => {
+            // Logged once, not on every refresh while the server keeps sending it
+            if capture.policy.lock().unwrap().last_error.as_ref() != Some(&e) {
+                eprintln!("🚫 {}", e);
+                record_audit(capture, Some(SERVER_ACTOR.into()), "apply_policy", serde_json::json!({}), Some(e.clone()));
+            }
+            return Err(e);
+        }
+    };
+    let version = policy.version;
+    if !capture.policy.lock().unwrap().accepts(&policy) {
+        return Ok(None);
+    }
+
+    let result = apply_policy(capture, policy, PolicySource::Server);
+    let params = serde_json::json!({ "version": version });
+    record_audit(capture, Some(SERVER_ACTOR.into()), "apply_policy", params, result.as_ref().err().cloned());
+    if !result? {
+        return Ok(None);
+    }
+    // Cached once applied, so a restart does not start from a policy that failed
+    policy::save_cached(std::path::Path::new(POLICY_CACHE_PATH), &signed)?;
+    Ok(Some(version))
+}
+
+fn spawn_policy_sync(capture: CaptureHandle) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        apply_cached_policy(&capture);
+        loop {
+            if let Err(e) = sync_policy(&capture) {
+                let mut status = capture.policy.lock().unwrap();
+                if status.last_error.as_ref() != Some(&e) {
+                    capture.report_error("policy", e.clone());
+                }
+                status.last_error = Some(e);
+            }
+            let refresh = capture
+                .policy
+                .lock()
+                .unwrap()
+                .policy
+                .as_ref()
+                .map_or(Duration::from_secs(policy::DEFAULT_REFRESH_SECS), |p| p.refresh());
+            thread::sleep(refresh);
+        }
+    })
+}
+
+/// Run an administrator's command through the same helpers as the local
+/// commands of the same name
+fn run_remote_action(capture: &CaptureHandle, action: RemoteAction) -> Result<serde_json::Value, String> {
+    match action {
+        RemoteAction::StartCapture { interval_secs, schedule } => {
+            let schedule = ScheduleConfig {
+                interval_secs,
+                ..schedule.unwrap_or_default()
+            };
+            spawn_screenshot_worker(capture, schedule)?;
+            Ok("Capture started".into())
+        }
+        RemoteAction::StopCapture => {
+            stop_screenshot_worker(capture);
+            Ok("Capture stopped".into())
+        }
+        RemoteAction::StartVideoCapture {
+            interval_secs,
+            duration_secs,
+            profile,
+            backend,
+        } => {
+            let target = prepare_recording(capture, profile, backend)?;
+            spawn_video(
+                capture,
+                target,
+                VideoMode::Interval {
+                    interval_secs,
+                    duration_secs,
+                },
+            )?;
+            Ok("Video capture loop started".into())
+        }
+        RemoteAction::StopVideoCapture => {
+            if !stop_video_worker(capture) {
+                return Err("Video capture not running".into());
+            }
+            Ok("Video capture stopped".into())
+        }
+        RemoteAction::Screenshot => {
+            let meta = take_screenshot_now(capture)?;
+            serde_json::to_value(meta).map_err(|e| e.to_string())
+        }
+        RemoteAction::PullLogs { log, lines } => {
+            let lines = lines.unwrap_or(remote::DEFAULT_LOG_LINES).clamp(1, remote::MAX_LOG_LINES);
+            let entries = match log {
+                LogName::Activity => remote::tail_lines(&std::path::Path::new(LOGS_DIR).join("activity.log"), lines)?,
+                LogName::AccessDenied => remote::tail_lines(std::path::Path::new(DENIED_LOG_PATH), lines)?,
+                LogName::Audit => {
+                    let query = AuditQuery {
+                        limit: Some(lines),
+                        ..Default::default()
+                    };
+                    let entries = capture.audit.lock().unwrap().query(&query)?;
+                    entries.iter().rev().filter_map(|e| serde_json::to_string(e).ok()).collect()
+                }
+            };
+            Ok(serde_json::json!({ "log": log, "lines": entries }))
+        }
+    }
+}
+
+/// Verify, acknowledge, run and report one command. Refused commands are
+/// reported too, so the server stops redelivering them. Returns `false` for
+/// a command that was already handled.
+fn handle_remote_command(capture: &CaptureHandle, channel: &CommandChannel, signed: &remote::SignedCommand, recent: &mut RecentIds) -> bool {
+    let identity = capture.identity.lock().unwrap().clone();
+    let key = identity
+        .enrollment
+        .as_ref()
+        .and_then(|e| e.policy_key.clone())
+        .unwrap_or_default();
+    let command = match remote::receive(signed, &key, &identity.device_id, current_ts_millis(), recent) {
+        Received::Run(command) => {
+            // Before running, so a crash mid-command cannot lead to a rerun
+            if let Err(e) = recent.save(std::path::Path::new(REMOTE_IDS_PATH)) {
+                eprintln!("Failed to save handled command ids: {}", e);
+            }
+            command
+        }
+        Received::Duplicate => return false,
+        Received::Rejected { id: None, error } => {
+            eprintln!("🚫 {}", error);
+            return false;
+        }
+        Received::Rejected { id: Some(id), error } => {
+            eprintln!("🚫 {} ({})", error, id);
+            record_audit(capture, Some(SERVER_ACTOR.into()), "remote_command", serde_json::json!({ "id": id }), Some(error.clone()));
+            let _ = channel.report(&id, &CommandResult::new(Err(error), current_ts_millis()));
+            return true;
+        }
+    };
+    if let Err(e) = channel.ack(&command.id) {
+        eprintln!("Failed to acknowledge command {}: {}", command.id, e);
+    }
+
+    let actor = command.issued_by.clone().unwrap_or_else(|| SERVER_ACTOR.into());
+    let (name, result) = match serde_json::from_value::<RemoteAction>(command.action.clone()) {
+        Ok(action) => {
+            let name = action.name();
+            println!("📡 Remote {} from {} ({})", name, actor, command.id);
+            (name, run_remote_action(capture, action))
+        }
+        Err(e) => ("unknown", Err(format!("Unsupported command: {}", e))),
+    };
+    let params = serde_json::json!({ "id": command.id, "command": name, "action": command.action });
+    record_audit(capture, Some(actor), "remote_command", params, result.as_ref().err().cloned());
+    {
+        let mut status = capture.remote.lock().unwrap();
+        status.executed += 1;
+        status.last_command = Some(name.to_string());
+    }
+    if let Err(e) = channel.report(&command.id, &CommandResult::new(result, current_ts_millis())) {
+        eprintln!("Failed to report result of command {}: {}", command.id, e);
+    }
+    true
+}
+
+/// Long-poll the server for administrator commands while enrolled
+fn spawn_command_channel(capture: CaptureHandle) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut channel: Option<CommandChannel> = None;
+        let mut recent = RecentIds::load(std::path::Path::new(REMOTE_IDS_PATH)).unwrap_or_else(|e| {
+            eprintln!("Failed to load handled command ids: {}", e);
+            RecentIds::default()
+        });
+        let mut backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(300));
+        loop {
+            let identity = capture.identity.lock().unwrap().clone();
+            let server = identity.enrollment.as_ref().map(|e| e.server.clone());
+            let (Some(signer), Some(server)) = (identity.signer(), server) else {
+                channel = None;
+                thread::sleep(Duration::from_secs(30));
+                continue;
+            };
+            if channel.as_ref().is_none_or(|c| c.server() != server) {
+                channel = Some(CommandChannel::new(&server, signer));
+            }
+            let Some(c) = &channel else { continue };
+
+            let polled = c.poll(remote::POLL_WAIT_SECS);
+            {
+                let mut status = capture.remote.lock().unwrap();
+                status.connected = polled.is_ok();
+                status.last_poll_ms = Some(current_ts_millis());
+                status.last_error = polled.as_ref().err().cloned();
+            }
+            match polled {
+                Ok(commands) => {
+                    backoff.reset();
+                    let mut any_new = false;
+                    for signed in &commands {
+                        any_new |= handle_remote_command(&capture, c, signed, &mut recent);
+                    }
+                    // Don't spin while the server keeps resending handled commands
+                    if !commands.is_empty() && !any_new {
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                }
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    eprintln!("⚠️ Command channel: {} (retrying in {}s)", e, delay.as_secs());
+                    capture.report_error("commands", e);
+                    thread::sleep(delay);
+                }
+            }
+        }
+    })
+}
+
+pub(crate) fn build_heartbeat(capture: &CaptureHandle) -> Heartbeat {
+    let now = current_ts_millis();
+    let identity = capture.identity.lock().unwrap().summary();
+    let storage = capture.storage.lock().unwrap().clone();
+    let last_sent = capture.last_heartbeat_ms.load(Ordering::SeqCst);
+    Heartbeat {
+        device_id: identity.device_id,
+        hostname: identity.hostname,
+        user: identity.user,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp_ms: now,
+        started_ms: capture.started_ms,
+        uptime_secs: now.saturating_sub(capture.started_ms) / 1000,
+        workers: WorkerHealth {
+            input: capture.input.status(),
+            screenshots: capture.screenshot.status(),
+            video: capture.video.status(),
+        },
+        upload_queue: QueueDepth {
+            pending: capture.upload_queue.items().len(),
+            failed: capture.upload_queue.failed_count(),
+        },
+        disk: DiskUsage {
+            used_bytes: storage.total_bytes,
+            free_bytes: storage.free_bytes,
+            paused: storage.paused,
+            last_check_ms: storage.last_check_ms,
+        },
+        captures_held: capture.captures_held(),
+        privacy_pause: capture.privacy_pause.lock().unwrap().status(now),
+        policy_version: capture.policy.lock().unwrap().version(),
+        commands_connected: capture.remote.lock().unwrap().connected,
+        last_error: capture.last_error.lock().unwrap().clone(),
+        health_events: capture.supervisor.lock().unwrap().recent(HEALTH_EVENTS),
+        last_sent_ms: (last_sent > 0).then_some(last_sent),
+    }
+}
+
+/// Write a heartbeat to `HEARTBEAT_PATH` and, once enrolled, send it to the
+/// server every `HEARTBEAT_SECS`
+fn spawn_heartbeat(capture: CaptureHandle) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_failure: Option<String> = None;
+        loop {
+            let heartbeat = build_heartbeat(&capture);
+            if let Err(e) = heartbeat::write_local(std::path::Path::new(HEARTBEAT_PATH), &heartbeat) {
+                eprintln!("Failed to write heartbeat: {}", e);
+            }
+            let identity = capture.identity.lock().unwrap().clone();
+            if let (Some(signer), Some(enrollment)) = (identity.signer(), identity.enrollment.as_ref()) {
+                match heartbeat::send(&enrollment.server, &signer, &heartbeat, current_ts_millis()) {
+                    Ok(()) => {
+                        capture.last_heartbeat_ms.store(heartbeat.timestamp_ms, Ordering::SeqCst);
+                        last_failure = None;
+                    }
+                    // Logged once per outage rather than every minute
+                    Err(e) if last_failure.as_ref() != Some(&e) => {
+                        eprintln!("⚠️ Heartbeat not delivered: {}", e);
+                        last_failure = Some(e);
+                    }
+                    Err(_) => {}
+                }
+            }
+            thread::sleep(Duration::from_secs(HEARTBEAT_SECS));
+        }
+    })
+}
+
+/// Put every long-running thread and capture worker under a supervisor that
+/// restarts them with backoff when they exit, fail or panic
+fn spawn_supervisor(capture: &CaptureHandle) {
+    let mut supervisor = Supervisor::new(capture.supervisor.clone());
+
+    let c = capture.clone();
+    supervisor.add_service("storage", move || spawn_storage_manager(c.clone()));
+    let c = capture.clone();
+    supervisor.add_service("upload", move || spawn_uploader(c.clone()));
+    let c = capture.clone();
+    supervisor.add_service("policy", move || spawn_policy_sync(c.clone()));
+    let c = capture.clone();
+    supervisor.add_service("commands", move || spawn_command_channel(c.clone()));
+    let c = capture.clone();
+    supervisor.add_service("heartbeat", move || spawn_heartbeat(c.clone()));
+
+    spawn_input_listener(capture.clone(), std::path::Path::new(LOGS_DIR));
+    let c = capture.clone();
+    supervisor.add_worker("input", capture.input.clone(), capture.input_join_handle.clone(), move || {
+        spawn_input_listener(c.clone(), std::path::Path::new(LOGS_DIR));
+        Ok(true)
+    });
+    let c = capture.clone();
+    supervisor.add_worker("screenshots", capture.screenshot.clone(), capture.join_handle.clone(), move || {
+        // Bound first: the spawn takes the spec lock again
+        let spec = c.screenshot_spec.lock().unwrap().clone();
+        match spec {
+            Some(schedule) => spawn_screenshot_worker(&c, schedule).map(|_| true),
+            None => Ok(false),
+        }
+    });
+    let c = capture.clone();
+    supervisor.add_worker("video", capture.video.clone(), capture.video_join_handle.clone(), move || {
+        let spec = c.video_spec.lock().unwrap().clone();
+        match spec {
+            Some((target, mode)) => spawn_video(&c, target, mode).map(|_| true),
+            None => Ok(false),
+        }
+    });
+
+    let capture = capture.clone();
+    thread::spawn(move || loop {
+        for event in supervisor.check() {
+            match event.kind {
+                SupervisorEventKind::Exited | SupervisorEventKind::Panicked | SupervisorEventKind::Failed => {
+                    capture.report_error(&event.worker, event.message)
+                }
+                SupervisorEventKind::PersistentFailure { .. } => {
+                    record_audit(&capture, None, "worker_failing", serde_json::json!({ "worker": event.worker }), Some(event.message))
+                }
+                _ => {}
+            }
+        }
+        thread::sleep(Duration::from_secs(SUPERVISOR_CHECK_SECS));
+    });
+}
+
+/// Start monitoring: the input listener, background services and capture
+/// workers under the supervisor. Shared by the GUI and the headless agent.
+pub fn start(capture: &CaptureHandle) {
+    migrate_denied_log();
+    spawn_supervisor(capture);
+
+    // Probe ffmpeg once at startup so a missing binary or codec shows up
+    // early, in the heartbeat's last error as well as the console
+    let probe_handle = capture.clone();
+    thread::spawn(move || match ffmpeg_encoders(&probe_handle) {
+        Ok(list) => {
+            println!("🎞️ ffmpeg available with {} encoders", list.len());
+            let profiles = probe_handle.encoding_profiles.lock().unwrap().clone();
+            let Some(profile) = encoding::profile_by_name(&profiles, encoding::DEFAULT_PROFILE) else {
+                return;
+            };
+            match encoding::resolve_profile(&profile, GrabBackend::for_platform(), &list) {
+                Ok((_, None)) => {}
+                Ok((_, Some(note))) => {
+                    eprintln!("⚠️ {}", note);
+                    probe_handle.report_error("ffmpeg", note);
+                }
+                Err(e) => {
+                    let msg = format!("Default profile cannot run, recordings will use MJPEG: {}", e);
+                    eprintln!("⚠️ {}", msg);
+                    probe_handle.report_error("ffmpeg", msg);
+                }
+            }
+        }
+        Err(e) => {
+            let msg = format!("ffmpeg unavailable, recordings will use MJPEG: {}", e);
+            eprintln!("⚠️ {}", msg);
+            probe_handle.report_error("ffmpeg", msg);
+        }
+    });
+}
+
+/// Wind down before the process exits: finish the current screenshot and
+/// recording, queue buffered activity for upload and leave a final
+/// heartbeat. The input listener and services end with the process.
+pub fn shutdown(capture: &CaptureHandle) {
+    stop_screenshot_worker(capture);
+    stop_video_worker(capture);
+    flush_activity_batch(capture);
+    if let Err(e) = heartbeat::write_local(std::path::Path::new(HEARTBEAT_PATH), &build_heartbeat(capture)) {
+        eprintln!("Failed to write heartbeat: {}", e);
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const CURRENT_FILE: &str = "audit.jsonl";
+/// The current file is renamed to audit_<timestamp>.jsonl past this size
+pub const ROTATE_BYTES: u64 = 20 * 1024 * 1024;
+pub const DEFAULT_QUERY_LIMIT: usize = 200;
+pub const MAX_QUERY_LIMIT: usize = 5000;
+
+/// One command invocation. Secrets (tokens, passwords) are never recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp_ms: u64,
+    pub timestamp: String,
+    /// `None` when the caller had no valid session
+    pub actor: Option<String>,
+    pub action: String,
+    pub params: Value,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// A status query the UI repeats every few seconds
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub poll: bool,
+}
+
+/// Filters for `query`; all optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    /// Case-insensitive exact match
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    pub failures_only: bool,
+    /// Leave out status polls
+    pub exclude_polls: bool,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let actor_ok = match (&self.actor, &entry.actor) {
+            (Some(want), Some(actor)) => actor.eq_ignore_ascii_case(want),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        actor_ok
+            && self.action.as_ref().is_none_or(|a| *a == entry.action)
+            && self.since_ms.is_none_or(|t| entry.timestamp_ms >= t)
+            && self.until_ms.is_none_or(|t| entry.timestamp_ms <= t)
+            && (!self.failures_only || !entry.ok)
+            && (!self.exclude_polls || !entry.poll)
+    }
+}
+
+/// Append-only JSON-lines log. Entries are never rewritten; full files are
+/// rotated aside and kept. The storage manager counts the directory towards
+/// the total but never deletes from it.
+#[derive(Debug)]
+pub struct AuditLog {
+    dir: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let path = self.dir.join(CURRENT_FILE);
+        if fs::metadata(&path).is_ok_and(|m| m.len() >= ROTATE_BYTES) {
+            self.rotate(&path)?;
+        }
+        let json = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", json).map_err(|e| e.to_string())
+    }
+
+    fn rotate(&self, path: &Path) -> Result<(), String> {
+        let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        let mut rotated = self.dir.join(format!("audit_{}.jsonl", stamp));
+        // Never overwrite an earlier file rotated within the same second
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = self.dir.join(format!("audit_{}_{}.jsonl", stamp, n));
+            n += 1;
+        }
+        fs::rename(path, &rotated).map_err(|e| format!("Failed to rotate audit log: {}", e))
+    }
+
+    /// Files oldest first; rotated names sort by timestamp and the current file comes last
+    fn files(&self) -> Vec<PathBuf> {
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .map(|rd| {
+                rd.filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| {
+                        p.file_name()
+                            .and_then(|n| n.to_str())
+                            .is_some_and(|n| n.starts_with("audit_") && n.ends_with(".jsonl"))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        rotated.sort();
+        rotated.push(self.dir.join(CURRENT_FILE));
+        rotated
+    }
+
+    /// Matching entries, newest first
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, String> {
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+        let mut out = Vec::new();
+        for path in self.files().iter().rev() {
+            let text = match fs::read_to_string(path) {
+                Ok(t) => t,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+            };
+            for line in text.lines().rev() {
+                // A line cut short by a crash is skipped, not fatal
+                let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else {
+                    continue;
+                };
+                if query.matches(&entry) {
+                    out.push(entry);
+                    if out.len() >= limit {
+                        return Ok(out);
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ts: u64, action: &str, poll: bool) -> AuditEntry {
+        AuditEntry {
+            timestamp_ms: ts,
+            timestamp: String::new(),
+            actor: Some("alice".into()),
+            action: action.into(),
+            params: Value::Null,
+            ok: true,
+            error: None,
+            poll,
+        }
+    }
+
+    #[test]
+    fn polls_are_recorded_and_can_be_filtered_out() {
+        let dir = std::env::temp_dir().join(format!("audit_polls_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let log = AuditLog::new(&dir);
+        log.record(&entry(1, "start_capture", false)).unwrap();
+        log.record(&entry(2, "capture_status", true)).unwrap();
+
+        let all = log.query(&AuditQuery::default()).unwrap();
+        let actions: Vec<_> = all.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["capture_status", "start_capture"]);
+        assert!(all[0].poll);
+
+        let query = AuditQuery { exclude_polls: true, ..Default::default() };
+        let actions: Vec<_> = log.query(&query).unwrap().into_iter().map(|e| e.action).collect();
+        assert_eq!(actions, ["start_capture"]);
+
+        // Entries written before polls were marked read back as non-polls
+        let old = r#"{"timestamp_ms":3,"timestamp":"","actor":null,"action":"login","params":{},"ok":true}"#;
+        assert!(!serde_json::from_str::<AuditEntry>(old).unwrap().poll);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
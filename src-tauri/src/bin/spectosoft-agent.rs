@@ -0,0 +1,130 @@
+//! Headless monitoring agent for running as a background service.
+//!
+//! `run` stays in the foreground until Ctrl+C or a service stop, `start`
+//! launches it in the background, `stop` shuts a running agent down and
+//! `status` prints its last heartbeat. Captures at startup come from
+//! `config/agent.json` under the data directory.
+
+use chrono::{Local, TimeZone};
+use std::sync::mpsc;
+use tauri_react_demo_lib::daemon::{self, AgentStatus};
+use tauri_react_demo_lib::worker::{WorkerState, WorkerStatus};
+
+const USAGE: &str = "usage: spectosoft-agent <run|start|stop|status> [--dir <data dir>] [--json]";
+/// Health events shown by `status`
+const STATUS_EVENTS: usize = 5;
+
+fn main() {
+    let mut command = None;
+    let mut json = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => {
+                let Some(dir) = args.next() else {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
+                };
+                if let Err(e) = std::env::set_current_dir(&dir) {
+                    eprintln!("Cannot use {}: {}", dir, e);
+                    std::process::exit(2);
+                }
+            }
+            "--json" => json = true,
+            _ if command.is_none() => command = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let result = match command.as_deref() {
+        Some("run") => run(),
+        Some("start") => daemon::start_detached().map(|pid| println!("Agent started (pid {})", pid)),
+        Some("stop") => daemon::request_stop().map(|pid| println!("Agent stopped (pid {})", pid)),
+        Some("status") => daemon::status().map(|status| {
+            print_status(&status, json);
+            // Like service scripts: 3 means not running
+            if status.pid.is_none() {
+                std::process::exit(3);
+            }
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = tx.send(());
+    })
+    .map_err(|e| format!("Cannot install signal handler: {}", e))?;
+    daemon::run(&rx)
+}
+
+fn format_ms(ms: u64) -> String {
+    Local
+        .timestamp_millis_opt(ms as i64)
+        .single()
+        .map_or_else(|| ms.to_string(), |t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn worker_line(name: &str, status: &WorkerStatus) -> String {
+    let state = match &status.state {
+        WorkerState::Stopped => "stopped".to_string(),
+        WorkerState::Running => format!("running, {} done", status.progress_count),
+        WorkerState::Failed(reason) => format!("failed: {}", reason),
+    };
+    format!("  {:<12}{}", name, state)
+}
+
+fn print_status(status: &AgentStatus, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(status).unwrap_or_default());
+        return;
+    }
+    match status.pid {
+        Some(pid) => println!("Agent:        running (pid {})", pid),
+        None => println!("Agent:        not running"),
+    }
+    let Some(hb) = &status.heartbeat else {
+        println!("No heartbeat written yet");
+        return;
+    };
+    println!("Heartbeat:    {}", format_ms(hb.timestamp_ms));
+    println!("Device:       {} ({}, {})", hb.device_id, hb.hostname, hb.user);
+    println!("Uptime:       {}s", hb.uptime_secs);
+    println!("Workers:");
+    println!("{}", worker_line("input", &hb.workers.input));
+    println!("{}", worker_line("screenshots", &hb.workers.screenshots));
+    println!("{}", worker_line("video", &hb.workers.video));
+    println!("Upload queue: {} pending, {} failed", hb.upload_queue.pending, hb.upload_queue.failed);
+    let free = hb.disk.free_bytes.map_or("unknown".to_string(), |b| format!("{} MB", b / (1024 * 1024)));
+    println!("Disk:         {} MB used, {} free", hb.disk.used_bytes / (1024 * 1024), free);
+    println!("Captures:     {}", if hb.captures_held { "held" } else { "active" });
+    match hb.policy_version {
+        Some(v) => println!("Policy:       version {}", v),
+        None => println!("Policy:       local settings"),
+    }
+    println!("Server:       {}", if hb.commands_connected { "connected" } else { "not connected" });
+    if let Some(e) = &hb.last_error {
+        println!("Last error:   [{}] {} ({})", e.source, e.message, format_ms(e.at_ms));
+    }
+    if !hb.health_events.is_empty() {
+        println!("Recent health events:");
+    }
+    let start = hb.health_events.len().saturating_sub(STATUS_EVENTS);
+    for event in &hb.health_events[start..] {
+        let kind = serde_json::to_value(&event.kind).unwrap_or_default();
+        let kind = kind["kind"].as_str().unwrap_or_default();
+        println!("  {} {} {}: {}", format_ms(event.ts_ms), event.worker, kind, event.message);
+    }
+}
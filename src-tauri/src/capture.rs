@@ -0,0 +1,612 @@
+use chrono::Local;
+use image::{ImageBuffer, Rgba};
+use scrap::{Capturer, Display};
+#[cfg(windows)]
+use std::collections::HashMap;
+#[cfg(windows)]
+use std::ffi::OsString;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+use std::sync::atomic::Ordering;
+use std::{fs, thread};
+use std::time::{Duration, Instant};
+#[cfg(windows)]
+use windows::Win32::{
+    Foundation::{CloseHandle, BOOL, HWND, LPARAM, POINT, RECT},
+    Graphics::Gdi::{GetMonitorInfoW, MonitorFromPoint, MONITORINFOEXW, MONITOR_DEFAULTTOPRIMARY},
+    System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    },
+    UI::WindowsAndMessaging::{
+        EnumWindows, GetForegroundWindow, GetWindowRect, GetWindowTextLengthW, GetWindowTextW,
+        GetWindowThreadProcessId, IsIconic, IsWindowVisible,
+    },
+};
+
+use crate::agent::CaptureHandle;
+use crate::current_ts_millis;
+use crate::encoding::{self, EncoderBackend};
+use crate::ffmpeg::{DisplayGeometry, GrabBackend};
+use crate::input::Metrics;
+use crate::motion::MotionConfig;
+use crate::redaction::{self, WindowInfo};
+use crate::schedule::{CaptureTrigger, ScheduleConfig, Scheduler};
+use crate::screenshot_meta::{self, DisplayInfo, ForegroundWindow, IdleState, ScreenshotMeta};
+use crate::upload::UploadKind;
+use crate::video::{self, RecordingTarget};
+
+/// Encoders reported by `ffmpeg -encoders`, probed once and cached
+pub(crate) fn ffmpeg_encoders(state: &CaptureHandle) -> Result<Vec<String>, String> {
+    let mut cached = state.ffmpeg_encoders.lock().unwrap();
+    if let Some(list) = cached.as_ref() {
+        return Ok(list.clone());
+    }
+    let list = encoding::probe_encoders()?;
+    *cached = Some(list.clone());
+    Ok(list)
+}
+
+/// Resolve the output directory, grab backend, display size and encoding
+/// profile for a new recording. A profile whose encoder is missing from the
+/// ffmpeg build runs with a fallback encoder. Without an explicit `encoder`,
+/// ffmpeg is used when it can run the profile and the in-process MJPEG
+/// writer otherwise.
+pub(crate) fn prepare_recording(
+    state: &CaptureHandle,
+    profile: Option<String>,
+    encoder: Option<EncoderBackend>,
+) -> Result<RecordingTarget, String> {
+    let output_dir = state.capture_dirs.videos.clone();
+    std::fs::create_dir_all(&output_dir).map_err(|e| e.to_string())?;
+
+    let name = profile.unwrap_or_else(|| encoding::DEFAULT_PROFILE.to_string());
+    let profile = encoding::profile_by_name(&state.encoding_profiles.lock().unwrap(), &name)
+        .ok_or_else(|| format!("Unknown encoding profile '{}'", name))?;
+    let backend = GrabBackend::for_platform();
+    let resolved = ffmpeg_encoders(state).and_then(|encoders| encoding::resolve_profile(&profile, backend, &encoders));
+    let (encoding, encoder) = match (encoder, resolved) {
+        (Some(EncoderBackend::Mjpeg), _) => (profile, EncoderBackend::Mjpeg),
+        (_, Ok((resolved, note))) => {
+            if let Some(note) = note {
+                eprintln!("⚠️ {}", note);
+            }
+            (resolved, EncoderBackend::Ffmpeg)
+        }
+        (Some(EncoderBackend::Ffmpeg), Err(e)) => return Err(e),
+        (None, Err(e)) => {
+            eprintln!("ffmpeg cannot record ({}), falling back to in-process MJPEG", e);
+            (profile, EncoderBackend::Mjpeg)
+        }
+    };
+
+    let geometry = primary_display_geometry()?;
+    println!(
+        "🎬 Recording {:?} at {}x{} with profile '{}' ({:?})",
+        backend, geometry.width, geometry.height, encoding.name, encoder
+    );
+
+    Ok(RecordingTarget {
+        output_dir,
+        backend,
+        geometry,
+        encoding,
+        encoder,
+    })
+}
+
+/// Claim the video worker and run `body` on its own thread. Fails if a
+/// recording is already running.
+fn spawn_video_worker(
+    capture: &CaptureHandle,
+    body: impl FnOnce(&CaptureHandle) -> Result<(), String> + Send + 'static,
+) -> Result<(), String> {
+    if capture.video.is_running() {
+        return Err("Video capture already running".to_string());
+    }
+    // A previous loop that stopped or failed on its own has already exited.
+    // Joined before `start` so the supervisor never sees the old thread
+    // finished while the worker is marked running and takes it for a panic.
+    if let Some(old) = capture.video_join_handle.lock().unwrap().take() {
+        let _ = old.join();
+    }
+    capture
+        .video
+        .start()
+        .map_err(|_| "Video capture already running".to_string())?;
+
+    let worker = capture.clone();
+    let handle = thread::spawn(move || match body(&worker) {
+        Ok(()) => worker.video.finish(),
+        Err(e) => {
+            worker.report_error("video", e.clone());
+            worker.video.fail(e);
+        }
+    });
+
+    // store join handle so stop can join
+    *capture.video_join_handle.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+/// Which loop the video worker runs
+#[derive(Debug, Clone)]
+pub(crate) enum VideoMode {
+    Interval { interval_secs: u64, duration_secs: u64 },
+    Continuous { segment_secs: u64 },
+    Motion(MotionConfig),
+}
+
+/// Start a recording and remember it, so the supervisor can bring the same
+/// recording back if the worker fails
+pub(crate) fn spawn_video(capture: &CaptureHandle, target: RecordingTarget, mode: VideoMode) -> Result<(), String> {
+    *capture.video_spec.lock().unwrap() = Some((target.clone(), mode.clone()));
+    spawn_video_worker(capture, move |capture| match mode {
+        VideoMode::Interval {
+            interval_secs,
+            duration_secs,
+        } => {
+            println!("🎬 Video capture loop started");
+            let result = video::video_loop(capture, &target, interval_secs, duration_secs);
+            println!("🎬 Video capture loop exiting");
+            result
+        }
+        VideoMode::Continuous { segment_secs } => {
+            println!("🎬 Continuous recording started ({}s segments)", segment_secs);
+            let result = video::continuous_video_loop(capture, &target, segment_secs.max(1));
+            println!("🎬 Continuous recording exiting");
+            result
+        }
+        VideoMode::Motion(motion) => {
+            println!("🎬 Motion-triggered recording started");
+            let result = video::motion_video_loop(capture, &target, motion);
+            println!("🎬 Motion-triggered recording exiting");
+            result
+        }
+    })
+}
+
+/// Ask the video loop to stop and wait for it; `false` if none was running.
+/// A failed recording is not restarted after this.
+pub(crate) fn stop_video_worker(capture: &CaptureHandle) -> bool {
+    *capture.video_spec.lock().unwrap() = None;
+    if !capture.video.is_running() {
+        return false;
+    }
+    capture.video.request_stop();
+    if let Some(h) = capture.video_join_handle.lock().unwrap().take() {
+        let _ = h.join();
+    }
+    true
+}
+
+/// Size of the primary display as the capture backends see it
+fn primary_display_geometry() -> Result<DisplayGeometry, String> {
+    let display = Display::primary().map_err(|e| format!("Failed to get display: {:?}", e))?;
+    Ok(DisplayGeometry {
+        width: display.width() as u32,
+        height: display.height() as u32,
+        offset_x: 0,
+        offset_y: 0,
+    })
+}
+
+/// Name of the display screenshots are taken from: its GDI device name
+/// (`\\.\DISPLAY1`) on Windows
+#[cfg(windows)]
+fn primary_display_name() -> String {
+    // The primary monitor is the one holding the desktop origin
+    let monitor = unsafe { MonitorFromPoint(POINT { x: 0, y: 0 }, MONITOR_DEFAULTTOPRIMARY) };
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    if !unsafe { GetMonitorInfoW(monitor, &mut info.monitorInfo) }.as_bool() {
+        return "primary".into();
+    }
+    let len = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+    OsString::from_wide(&info.szDevice[..len]).to_string_lossy().to_string()
+}
+
+/// Name of the display screenshots are taken from: the X11 display
+/// (`:0`), or the main display on macOS
+#[cfg(not(windows))]
+fn primary_display_name() -> String {
+    std::env::var("DISPLAY").unwrap_or_else(|_| "main".into())
+}
+
+/// Window title with bounds checking
+#[cfg(windows)]
+unsafe fn window_title(hwnd: HWND) -> Option<String> {
+    let title_len = GetWindowTextLengthW(hwnd);
+    if title_len == 0 || title_len > 1024 {
+        return None;
+    }
+
+    let mut buffer = vec![0u16; (title_len + 1) as usize];
+    GetWindowTextW(hwnd, &mut buffer);
+    Some(
+        OsString::from_wide(&buffer)
+            .to_string_lossy()
+            .trim_end_matches('\0')
+            .to_string(),
+    )
+}
+
+/// Executable names of all running processes by pid, from one snapshot
+#[cfg(windows)]
+unsafe fn process_names() -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+
+    let snapshot = match CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) {
+        Ok(s) => s,
+        Err(_) => return names,
+    };
+    let mut entry = PROCESSENTRY32W::default();
+    entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+
+    if Process32FirstW(snapshot, &mut entry).is_ok() {
+        loop {
+            let len = entry
+                .szExeFile
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(entry.szExeFile.len());
+            let name = OsString::from_wide(&entry.szExeFile[..len]);
+            names.insert(entry.th32ProcessID, name.to_string_lossy().to_string());
+            if Process32NextW(snapshot, &mut entry).is_err() {
+                break;
+            }
+        }
+    }
+    let _ = CloseHandle(snapshot);
+
+    names
+}
+
+/// Executable name for a pid, "Unknown" if it cannot be resolved
+#[cfg(windows)]
+fn name_for_pid(names: &HashMap<u32, String>, pid: u32) -> String {
+    names.get(&pid).cloned().unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// Get active window + process info with error handling
+#[cfg(windows)]
+pub(crate) fn get_active_window_info() -> Option<(String, String, String, u32)> {
+    unsafe {
+        let hwnd: HWND = GetForegroundWindow();
+        if hwnd.0 == 0 {
+            return None;
+        }
+
+        let mut pid: u32 = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+
+        let window_title = window_title(hwnd)?;
+        let process_name = name_for_pid(&process_names(), pid);
+
+        Some((
+            process_name.clone(),
+            process_name,
+            window_title,
+            pid,
+        ))
+    }
+}
+
+/// Title, process and screen rectangle of a top-level window
+#[cfg(windows)]
+unsafe fn window_info(hwnd: HWND, names: &HashMap<u32, String>) -> Option<WindowInfo> {
+    let title = window_title(hwnd)?;
+
+    let mut pid: u32 = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid));
+
+    let mut rect = RECT::default();
+    GetWindowRect(hwnd, &mut rect).ok()?;
+
+    Some(WindowInfo {
+        process_name: name_for_pid(names, pid),
+        title,
+        rect: redaction::Rect {
+            left: rect.left,
+            top: rect.top,
+            right: rect.right,
+            bottom: rect.bottom,
+        },
+    })
+}
+
+#[cfg(windows)]
+fn foreground_window_info() -> Option<WindowInfo> {
+    unsafe {
+        let hwnd: HWND = GetForegroundWindow();
+        if hwnd.0 == 0 {
+            return None;
+        }
+        window_info(hwnd, &process_names())
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn collect_visible_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let windows = &mut *(lparam.0 as *mut Vec<HWND>);
+    if IsWindowVisible(hwnd).as_bool() && !IsIconic(hwnd).as_bool() {
+        windows.push(hwnd);
+    }
+    BOOL(1) // keep enumerating
+}
+
+/// All visible, non-minimized top-level windows that have a title.
+/// Process names come from a single snapshot so this stays cheap enough
+/// to run for every video frame.
+#[cfg(windows)]
+fn visible_windows() -> Vec<WindowInfo> {
+    let mut hwnds: Vec<HWND> = Vec::new();
+    unsafe {
+        if let Err(e) = EnumWindows(
+            Some(collect_visible_window),
+            LPARAM(&mut hwnds as *mut Vec<HWND> as isize),
+        ) {
+            eprintln!("EnumWindows failed: {}", e);
+        }
+        let names = process_names();
+        hwnds.into_iter().filter_map(|hwnd| window_info(hwnd, &names)).collect()
+    }
+}
+
+/// Whether windows can be enumerated, which redaction depends on
+const WINDOW_TRACKING: bool = cfg!(windows);
+
+// Window tracking is only implemented on Windows. Elsewhere no foreground
+// window is known: activity is logged without window fields, screenshots
+// carry no window, and captures are refused while redaction rules are set
+// (see `redaction_plan`).
+#[cfg(not(windows))]
+pub(crate) fn get_active_window_info() -> Option<(String, String, String, u32)> {
+    None
+}
+
+#[cfg(not(windows))]
+fn foreground_window_info() -> Option<WindowInfo> {
+    None
+}
+
+#[cfg(not(windows))]
+fn visible_windows() -> Vec<WindowInfo> {
+    Vec::new()
+}
+
+const SCHEDULE_POLL_MS: u64 = 250;
+
+/// Start the screenshot loop on its own thread; fails if it is already running
+pub(crate) fn spawn_screenshot_worker(capture: &CaptureHandle, schedule: ScheduleConfig) -> Result<(), String> {
+    let out_path = capture.capture_dirs.screenshots.clone();
+    fs::create_dir_all(&out_path).map_err(|e| e.to_string())?;
+
+    if capture.screenshot.is_running() {
+        return Err("Capture already running".to_string());
+    }
+    // A previous loop that stopped or failed on its own has already exited;
+    // joined before `start`, see `spawn_video_worker`
+    if let Some(old) = capture.join_handle.lock().unwrap().take() {
+        let _ = old.join();
+    }
+    capture
+        .screenshot
+        .start()
+        .map_err(|_| "Capture already running".to_string())?;
+    *capture.screenshot_spec.lock().unwrap() = Some(schedule.clone());
+
+    let worker = capture.clone();
+    let handle = thread::spawn(move || {
+        match screenshot_loop(&worker, &out_path, schedule) {
+            Ok(()) => worker.screenshot.finish(),
+            Err(e) => {
+                worker.report_error("screenshots", e.clone());
+                worker.screenshot.fail(e);
+            }
+        }
+    });
+
+    *capture.join_handle.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+/// A failed loop is not restarted after this
+pub(crate) fn stop_screenshot_worker(capture: &CaptureHandle) {
+    *capture.screenshot_spec.lock().unwrap() = None;
+    capture.screenshot.request_stop();
+    if let Some(h) = capture.join_handle.lock().unwrap().take() {
+        let _ = h.join();
+    }
+}
+
+/// Runs until stop is requested; an `Err` marks the capture as failed
+fn screenshot_loop(capture: &CaptureHandle, out_path: &std::path::Path, schedule: ScheduleConfig) -> Result<(), String> {
+    let control = &capture.screenshot;
+
+    let display = Display::primary().map_err(|e| format!("Failed to get display: {:?}", e))?;
+    let mut capturer = Capturer::new(display).map_err(|e| format!("Failed to create capturer: {:?}", e))?;
+
+    let w = capturer.width();
+    let h = capturer.height();
+    let mut metrics_at_last_capture = capture.input_metrics.lock().unwrap().clone();
+    let mut scheduler = Scheduler::new(schedule, Instant::now());
+    let idle_threshold_secs = scheduler.config().idle_threshold_secs;
+
+    while control.should_run() {
+        if capture.captures_held() {
+            control.wait(Duration::from_millis(SCHEDULE_POLL_MS));
+            continue;
+        }
+        let idle_ms = current_ts_millis().saturating_sub(capture.last_input_ts.load(Ordering::SeqCst));
+        let idle = idle_ms > idle_threshold_secs * 1000;
+        let foreground_app = get_active_window_info().map(|(app, ..)| app);
+        let trigger = match scheduler.poll(Instant::now(), foreground_app.as_deref(), idle) {
+            Some(t) => t,
+            None => {
+                control.wait(Duration::from_millis(SCHEDULE_POLL_MS));
+                continue;
+            }
+        };
+
+        let frame = loop {
+            match capturer.frame() {
+                Ok(b) => break b.to_vec(),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if !control.wait(Duration::from_millis(10)) {
+                        return Ok(());
+                    }
+                }
+                Err(e) => return Err(format!("Capture error: {:?}", e)),
+            }
+        };
+        scheduler.reschedule(Instant::now());
+
+        let idle = IdleState {
+            idle,
+            idle_ms,
+            threshold_secs: idle_threshold_secs,
+        };
+        let metrics_now = capture.input_metrics.lock().unwrap().clone();
+        let input_since_last = metrics_now.since(&metrics_at_last_capture);
+        match save_screenshot(capture, &frame, (w, h), out_path, trigger, idle, input_since_last) {
+            Ok(Some(_)) => {
+                control.progress();
+                metrics_at_last_capture = metrics_now;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                capture.report_error("screenshots", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Redaction for a frame grabbed just now. Privacy rules are evaluated
+/// against the windows on screen at grab time, so call this per frame.
+///
+/// Fails closed: where windows cannot be enumerated no rule could ever
+/// match, so while any rule is set the frame must not be kept at all.
+pub(crate) fn redaction_plan(capture: &CaptureHandle) -> Result<redaction::RedactionPlan, String> {
+    let foreground_window = foreground_window_info();
+    let windows = visible_windows();
+    let policy = capture.redaction.lock().unwrap();
+    if !WINDOW_TRACKING && !policy.rules().is_empty() {
+        return Err("Capture refused: redaction rules are set but windows cannot be tracked on this platform; \
+                    remove the rules to capture without redaction"
+            .into());
+    }
+    Ok(policy.plan(foreground_window.as_ref(), &windows))
+}
+
+/// Redact, save and index one grabbed BGRA frame. `Ok(None)` when a
+/// sensitive window in the foreground vetoed the capture.
+fn save_screenshot(
+    capture: &CaptureHandle,
+    frame: &[u8],
+    (w, h): (usize, usize),
+    out_path: &std::path::Path,
+    trigger: CaptureTrigger,
+    idle: IdleState,
+    input_since_last: Metrics,
+) -> Result<Option<ScreenshotMeta>, String> {
+    let plan = redaction_plan(capture)?;
+    if let Some(rule) = &plan.skip {
+        println!("🔒 Skipping screenshot, sensitive window in foreground ({})", rule);
+        return Ok(None);
+    }
+
+    // Sample the foreground window as close to the grab as possible
+    let foreground = get_active_window_info().map(|(app, process, title, pid)| ForegroundWindow {
+        app_name: app,
+        process_name: process,
+        window_title: title,
+        pid,
+    });
+
+    // Pre-allocate buffer with exact size needed
+    let mut buf: Vec<u8> = Vec::with_capacity(w * h * 4);
+
+    // Convert BGRA to RGBA
+    for chunk in frame.chunks_exact(4) {
+        buf.push(chunk[2]); // R
+        buf.push(chunk[1]); // G
+        buf.push(chunk[0]); // B
+        buf.push(255);      // A
+    }
+
+    let redactions = redaction::apply(&mut buf, w, h, &plan);
+
+    let img = ImageBuffer::<Rgba<u8>, _>::from_raw(w as u32, h as u32, buf)
+        .ok_or("Frame does not match the display size")?;
+    let now = Local::now();
+    let ts = now.timestamp_millis();
+    let path = out_path.join(format!("screenshot_{}.png", ts));
+    img.save(&path).map_err(|e| format!("Save failed: {}", e))?;
+
+    let meta = ScreenshotMeta {
+        file: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        timestamp: now.to_rfc3339(),
+        timestamp_ms: ts,
+        foreground,
+        display: DisplayInfo {
+            name: primary_display_name(),
+            width: w as u32,
+            height: h as u32,
+        },
+        idle,
+        trigger: Some(trigger),
+        input_since_last,
+        sha256: screenshot_meta::sha256_file(&path).unwrap_or_default(),
+        redactions,
+        downsampled: false,
+        identity: Some(capture.record_identity()),
+    };
+    if let Err(e) = screenshot_meta::write_sidecar(&path, &meta) {
+        eprintln!("Sidecar write failed: {}", e);
+    }
+    capture.enqueue_upload(UploadKind::Screenshot, &path, serde_json::to_value(&meta).unwrap_or_default());
+    Ok(Some(meta))
+}
+
+/// One screenshot outside the schedule, with its own capturer so it works
+/// whether or not the screenshot loop is running
+pub(crate) fn take_screenshot_now(capture: &CaptureHandle) -> Result<ScreenshotMeta, String> {
+    if capture.captures_held() {
+        return Err("Captures are paused".into());
+    }
+    let out_path = capture.capture_dirs.screenshots.clone();
+    fs::create_dir_all(&out_path).map_err(|e| e.to_string())?;
+
+    let display = Display::primary().map_err(|e| format!("Failed to get display: {:?}", e))?;
+    let mut capturer = Capturer::new(display).map_err(|e| format!("Failed to create capturer: {:?}", e))?;
+    let (w, h) = (capturer.width(), capturer.height());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let frame = loop {
+        match capturer.frame() {
+            Ok(b) => break b.to_vec(),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => return Err(format!("Capture error: {:?}", e)),
+        }
+    };
+
+    let threshold_secs = capture
+        .policy
+        .lock()
+        .unwrap()
+        .policy
+        .as_ref()
+        .map_or(ScheduleConfig::default().idle_threshold_secs, |p| p.schedule.idle_threshold_secs);
+    let idle_ms = current_ts_millis().saturating_sub(capture.last_input_ts.load(Ordering::SeqCst));
+    let idle = IdleState {
+        idle: idle_ms > threshold_secs * 1000,
+        idle_ms,
+        threshold_secs,
+    };
+    save_screenshot(capture, &frame, (w, h), &out_path, CaptureTrigger::OnDemand, idle, Metrics::default())?
+        .ok_or_else(|| "Skipped, a sensitive window is in the foreground".to_string())
+}
@@ -0,0 +1,827 @@
+use serde::Serialize;
+use tauri::State;
+use std::sync::atomic::Ordering;
+
+use crate::agent::{
+    build_heartbeat, CaptureHandle, DENIED_LOG_PATH, DEVICE_IDENTITY_PATH, ENCODING_PROFILES_PATH, PRIVACY_PAUSE_PATH,
+    record_audit, record_poll, REDACTION_RULES_PATH, STORAGE_QUOTA_PATH, sync_policy, UPLOAD_CONFIG_PATH, USERS_PATH,
+};
+use crate::audit::{AuditEntry, AuditQuery};
+use crate::capture::{
+    ffmpeg_encoders, prepare_recording, spawn_screenshot_worker, spawn_video,
+    stop_screenshot_worker, stop_video_worker, VideoMode,
+};
+use crate::current_ts_millis;
+use crate::encoding::{self, EncoderBackend, EncodingProfile};
+use crate::ffmpeg::GrabBackend;
+use crate::heartbeat::Heartbeat;
+use crate::identity::{self, IdentitySummary};
+use crate::motion::MotionConfig;
+use crate::policy::PolicyStatus;
+use crate::privacy::PauseStatus;
+use crate::recording_index::{self, RecordingEntry};
+use crate::redaction::{self, RedactionPolicy, RedactionRule};
+use crate::remote::RemoteStatus;
+use crate::roles::{self, Permission, Role};
+use crate::schedule::ScheduleConfig;
+use crate::screenshot_meta::{self, ScreenshotMeta};
+use crate::sessions::Session;
+use crate::storage::{self, QuotaConfig, StorageStatus};
+use crate::thumbnails;
+use crate::upload::{self, UploadConfig, UploadStatus};
+use crate::users::{UserStore, UserSummary};
+use crate::video_health::VideoHealth;
+use crate::worker::WorkerStatus;
+
+#[tauri::command]
+pub fn start_video_capture(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    interval_secs: u64,
+    duration_secs: u64,
+    profile: Option<String>,
+    backend: Option<EncoderBackend>,
+) -> Result<String, String> {
+    let params = serde_json::json!({
+        "interval_secs": interval_secs,
+        "duration_secs": duration_secs,
+        "profile": profile,
+        "backend": backend,
+    });
+    audited(&state, &token, "start_video_capture", params, || {
+        require(&state, &token, "start_video_capture", Permission::ControlCapture)?;
+        let target = prepare_recording(&state, profile, backend)?;
+        spawn_video(
+            &state,
+            target,
+            VideoMode::Interval {
+                interval_secs,
+                duration_secs,
+            },
+        )?;
+        Ok("Video capture loop started".into())
+    })
+}
+
+/// Record only while the screen changes or the user is active, with a short
+/// pre-roll. Shares the video worker; stop with `stop_video_capture`.
+#[tauri::command]
+pub fn start_motion_video(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    profile: Option<String>,
+    motion: Option<MotionConfig>,
+) -> Result<String, String> {
+    let params = serde_json::json!({ "profile": profile, "motion": motion });
+    audited(&state, &token, "start_motion_video", params, || {
+        require(&state, &token, "start_motion_video", Permission::ControlCapture)?;
+        let target = prepare_recording(&state, profile, Some(EncoderBackend::Ffmpeg))?;
+        spawn_video(&state, target, VideoMode::Motion(motion.unwrap_or_default()))?;
+        Ok("Motion-triggered recording started".into())
+    })
+}
+
+/// Record without gaps, rolling to a new file every `segment_secs`.
+/// Shares the video worker with `start_video_capture`; stop with `stop_video_capture`.
+#[tauri::command]
+pub fn start_continuous_video(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    segment_secs: u64,
+    profile: Option<String>,
+    backend: Option<EncoderBackend>,
+) -> Result<String, String> {
+    let params = serde_json::json!({
+        "segment_secs": segment_secs,
+        "profile": profile,
+        "backend": backend,
+    });
+    audited(&state, &token, "start_continuous_video", params, || {
+        require(&state, &token, "start_continuous_video", Permission::ControlCapture)?;
+        let target = prepare_recording(&state, profile, backend)?;
+        spawn_video(&state, target, VideoMode::Continuous { segment_secs })?;
+        Ok("Continuous recording started".into())
+    })
+}
+
+#[tauri::command]
+pub fn stop_video_capture(state: State<'_, CaptureHandle>, token: String) -> Result<String, String> {
+    audited(&state, &token, "stop_video_capture", serde_json::json!({}), || {
+        require(&state, &token, "stop_video_capture", Permission::ControlCapture)?;
+        if !stop_video_worker(&state) {
+            return Err("Video capture not running".into());
+        }
+        Ok("Video capture stopped".into())
+    })
+}
+
+/// The caller's session if its role grants `permission`. Fails the command
+/// when the token is unknown or expired, while a forced password change is
+/// pending, or when the role is too low; every refusal is logged.
+fn authorize(state: &CaptureHandle, token: &str, command: &str, permission: Permission, touch: bool) -> Result<Session, String> {
+    let denied_log = std::path::Path::new(DENIED_LOG_PATH);
+    let session = match state.sessions.lock().unwrap().validate(token, current_ts_millis(), touch) {
+        Ok(s) => s,
+        Err(e) => {
+            roles::log_denied(denied_log, command, None, None, permission, &e);
+            return Err(e);
+        }
+    };
+    let refusal = if session.must_change_password {
+        Some("Password change required".to_string())
+    } else if !session.role.allows(permission) {
+        Some(format!("Permission denied: {} requires the {:?} role", command, permission.min_role()))
+    } else {
+        None
+    };
+    if let Some(reason) = refusal {
+        roles::log_denied(denied_log, command, Some(&session.username), Some(session.role), permission, &reason);
+        return Err(reason);
+    }
+    Ok(session)
+}
+
+fn require(state: &CaptureHandle, token: &str, command: &str, permission: Permission) -> Result<Session, String> {
+    authorize(state, token, command, permission, true)
+}
+
+/// `require` for background polling, which must not keep an unattended
+/// session alive
+fn require_quiet(state: &CaptureHandle, token: &str, command: &str, permission: Permission) -> Result<Session, String> {
+    authorize(state, token, command, permission, false)
+}
+
+/// Run a command body and record its outcome in the audit log
+fn audited_as<T>(
+    state: &CaptureHandle,
+    actor: Option<String>,
+    action: &str,
+    params: serde_json::Value,
+    body: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let result = body();
+    record_audit(state, actor, action, params, result.as_ref().err().cloned());
+    result
+}
+
+/// `audited_as` attributed to the holder of `token`, looked up before the
+/// body runs so `logout` is still attributed
+fn audited<T>(
+    state: &CaptureHandle,
+    token: &str,
+    action: &str,
+    params: serde_json::Value,
+    body: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let actor = state.sessions.lock().unwrap().username(token, current_ts_millis());
+    audited_as(state, actor, action, params, body)
+}
+
+/// `audited` for read-only status queries the UI polls every few seconds.
+/// Every call is recorded, marked as a poll so audit queries can filter
+/// routine polling out.
+fn audited_quiet<T>(
+    state: &CaptureHandle,
+    token: &str,
+    action: &str,
+    params: serde_json::Value,
+    body: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let actor = state.sessions.lock().unwrap().username(token, current_ts_millis());
+    let result = body();
+    record_poll(state, actor, action, params, result.as_ref().err().cloned());
+    result
+}
+
+#[tauri::command]
+pub fn get_recent_activity(state: State<'_, CaptureHandle>, token: String, limit: Option<usize>) -> Result<Vec<String>, String> {
+    let params = serde_json::json!({ "limit": limit });
+    audited(&state, &token, "get_recent_activity", params, || {
+        require_quiet(&state, &token, "get_recent_activity", Permission::ViewActivity)?;
+        let limit = limit.unwrap_or(50).min(200); // Cap at 200
+        if let Ok(queue) = state.activity_queue.lock() {
+            let len = queue.len();
+            let start = len.saturating_sub(limit);
+            Ok(queue.iter().skip(start).cloned().collect())
+        } else {
+            Ok(vec![])
+        }
+    })
+}
+
+#[tauri::command]
+pub fn is_idle(state: State<'_, CaptureHandle>, token: String, threshold_secs: u64) -> Result<bool, String> {
+    let params = serde_json::json!({ "threshold_secs": threshold_secs });
+    audited_quiet(&state, &token, "is_idle", params, || {
+        require_quiet(&state, &token, "is_idle", Permission::ViewStatus)?;
+        let last = state.last_input_ts.load(Ordering::SeqCst);
+        let now = current_ts_millis();
+        Ok(now.saturating_sub(last) > (threshold_secs * 1000))
+    })
+}
+
+#[tauri::command]
+pub fn clear_activity(state: State<'_, CaptureHandle>, token: String) -> Result<(), String> {
+    audited(&state, &token, "clear_activity", serde_json::json!({}), || {
+        require(&state, &token, "clear_activity", Permission::DeleteHistory)?;
+        if let Ok(mut q) = state.activity_queue.lock() {
+            q.clear();
+        }
+        Ok(())
+    })
+}
+
+#[derive(Serialize)]
+pub struct LoginResponse {
+    success: bool,
+    message: String,
+    /// The password was reset by an administrator and must be changed now
+    must_change_password: bool,
+    /// Pass to every other command; `None` when login failed
+    token: Option<String>,
+    /// Lets the UI hide what the user may not do
+    role: Option<Role>,
+}
+
+fn save_users(users: &UserStore) -> Result<(), String> {
+    users.save(std::path::Path::new(USERS_PATH))
+}
+
+#[tauri::command]
+pub fn login(state: State<'_, CaptureHandle>, username: String, password: String) -> LoginResponse {
+    let mut users = state.users.lock().unwrap();
+    let result = users.authenticate(&username, &password, current_ts_millis());
+    // Failure counters and lockouts must survive a restart
+    if let Err(e) = save_users(&users) {
+        eprintln!("Failed to save user store: {}", e);
+    }
+    record_audit(
+        &state,
+        Some(username.trim().to_string()),
+        "login",
+        serde_json::json!({ "username": username }),
+        result.as_ref().err().map(|e| e.detail()),
+    );
+    match result {
+        Ok(user) => {
+            let session = state.sessions.lock().unwrap().issue(
+                &user.username,
+                user.role,
+                user.must_change_password,
+                current_ts_millis(),
+            );
+            LoginResponse {
+                success: true,
+                message: "Login successful".into(),
+                must_change_password: user.must_change_password,
+                token: Some(session.token),
+                role: Some(user.role),
+            }
+        }
+        Err(e) => LoginResponse {
+            success: false,
+            message: e.to_string(),
+            must_change_password: false,
+            token: None,
+            role: None,
+        },
+    }
+}
+
+#[tauri::command]
+pub fn logout(state: State<'_, CaptureHandle>, token: String) -> Result<String, String> {
+    audited(&state, &token, "logout", serde_json::json!({}), || {
+        if state.sessions.lock().unwrap().revoke(&token) {
+            Ok("Logged out".into())
+        } else {
+            Err("Not logged in".into())
+        }
+    })
+}
+
+/// The logged-in user, so the UI can restore itself after a reload
+#[tauri::command]
+pub fn current_session(state: State<'_, CaptureHandle>, token: String) -> Result<Session, String> {
+    audited_quiet(&state, &token, "current_session", serde_json::json!({}), || {
+        state.sessions.lock().unwrap().validate(&token, current_ts_millis(), false)
+    })
+}
+
+/// True until the first administrator account has been created
+#[tauri::command]
+pub fn auth_setup_required(state: State<'_, CaptureHandle>) -> bool {
+    let required = state.users.lock().unwrap().needs_setup() && !std::path::Path::new(USERS_PATH).exists();
+    record_audit(&state, None, "auth_setup_required", serde_json::json!({ "required": required }), None);
+    required
+}
+
+/// First-run setup: create the initial admin. Refused once any account exists.
+#[tauri::command]
+pub fn setup_admin(state: State<'_, CaptureHandle>, username: String, password: String) -> Result<String, String> {
+    let actor = Some(username.trim().to_string());
+    let params = serde_json::json!({ "username": username });
+    audited_as(&state, actor, "setup_admin", params, || {
+        let mut users = state.users.lock().unwrap();
+        // An unreadable store on disk must not reopen setup
+        if !users.needs_setup() || std::path::Path::new(USERS_PATH).exists() {
+            return Err("Setup has already been completed".into());
+        }
+        users.create(&username, &password, Role::Admin, current_ts_millis())?;
+        save_users(&users)?;
+        Ok(format!("Administrator '{}' created", username.trim()))
+    })
+}
+
+#[tauri::command]
+pub fn create_user(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    username: String,
+    password: String,
+    role: Option<Role>,
+) -> Result<String, String> {
+    let params = serde_json::json!({ "username": username, "role": role });
+    audited(&state, &token, "create_user", params, || {
+        require(&state, &token, "create_user", Permission::ManageUsers)?;
+        let mut users = state.users.lock().unwrap();
+        users.create(&username, &password, role.unwrap_or_default(), current_ts_millis())?;
+        save_users(&users)?;
+        Ok(format!("User '{}' created", username.trim()))
+    })
+}
+
+/// Change the logged-in user's own password. Allowed while a forced change is pending.
+#[tauri::command]
+pub fn change_password(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    old_password: String,
+    new_password: String,
+) -> Result<String, String> {
+    audited(&state, &token, "change_password", serde_json::json!({}), || {
+        let session = state.sessions.lock().unwrap().validate(&token, current_ts_millis(), true)?;
+        let mut users = state.users.lock().unwrap();
+        let result = users.change_password(&session.username, &old_password, &new_password, current_ts_millis());
+        save_users(&users)?;
+        result?;
+        state.sessions.lock().unwrap().password_changed(&token);
+        Ok("Password changed".into())
+    })
+}
+
+/// Set a temporary password, unlock the account and force a change at next login
+#[tauri::command]
+pub fn reset_password(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    username: String,
+    temporary_password: String,
+) -> Result<String, String> {
+    let params = serde_json::json!({ "username": username });
+    audited(&state, &token, "reset_password", params, || {
+        require(&state, &token, "reset_password", Permission::ManageUsers)?;
+        let mut users = state.users.lock().unwrap();
+        users.reset_password(&username, &temporary_password, current_ts_millis())?;
+        save_users(&users)?;
+        state.sessions.lock().unwrap().revoke_user(&username);
+        Ok(format!("Password for '{}' reset", username.trim()))
+    })
+}
+
+#[tauri::command]
+pub fn list_users(state: State<'_, CaptureHandle>, token: String) -> Result<Vec<UserSummary>, String> {
+    audited(&state, &token, "list_users", serde_json::json!({}), || {
+        require(&state, &token, "list_users", Permission::ManageUsers)?;
+        Ok(state.users.lock().unwrap().summaries(current_ts_millis()))
+    })
+}
+
+/// Search the audit log, newest first
+#[tauri::command]
+pub fn query_audit_log(state: State<'_, CaptureHandle>, token: String, query: Option<AuditQuery>) -> Result<Vec<AuditEntry>, String> {
+    let query = query.unwrap_or_default();
+    let params = serde_json::json!({ "query": query });
+    audited(&state, &token, "query_audit_log", params, || {
+        require(&state, &token, "query_audit_log", Permission::ViewAudit)?;
+        state.audit.lock().unwrap().query(&query)
+    })
+}
+
+/// Change a user's role. Their sessions end so the new role applies at next login.
+#[tauri::command]
+pub fn set_user_role(state: State<'_, CaptureHandle>, token: String, username: String, role: Role) -> Result<String, String> {
+    let params = serde_json::json!({ "username": username, "role": role });
+    audited(&state, &token, "set_user_role", params, || {
+        require(&state, &token, "set_user_role", Permission::ManageUsers)?;
+        let mut users = state.users.lock().unwrap();
+        users.set_role(&username, role)?;
+        save_users(&users)?;
+        state.sessions.lock().unwrap().revoke_user(&username);
+        Ok(format!("'{}' is now {:?}", username.trim(), role))
+    })
+}
+
+/// Hold screenshots and recordings for up to an hour, within a daily
+/// allowance; any role may do this
+#[tauri::command]
+pub fn pause_capture(state: State<'_, CaptureHandle>, token: String, minutes: u64) -> Result<u64, String> {
+    let params = serde_json::json!({ "minutes": minutes });
+    audited(&state, &token, "pause_capture", params, || {
+        let session = require(&state, &token, "pause_capture", Permission::PauseForPrivacy)?;
+        let mut pause = state.privacy_pause.lock().unwrap();
+        let until = pause.pause(&session.username, minutes, current_ts_millis())?;
+        pause.save(std::path::Path::new(PRIVACY_PAUSE_PATH))?;
+        println!("⏸️ Captures paused for privacy by {} for {} min", session.username, minutes);
+        Ok(until)
+    })
+}
+
+#[tauri::command]
+pub fn resume_capture(state: State<'_, CaptureHandle>, token: String) -> Result<(), String> {
+    audited(&state, &token, "resume_capture", serde_json::json!({}), || {
+        let session = require(&state, &token, "resume_capture", Permission::PauseForPrivacy)?;
+        let mut pause = state.privacy_pause.lock().unwrap();
+        if pause.resume(current_ts_millis()) {
+            pause.save(std::path::Path::new(PRIVACY_PAUSE_PATH))?;
+            println!("▶️ Captures resumed by {}", session.username);
+        }
+        Ok(())
+    })
+}
+
+/// Whether a privacy pause is running and how much of today's allowance is left
+#[tauri::command]
+pub fn privacy_pause_status(state: State<'_, CaptureHandle>, token: String) -> Result<PauseStatus, String> {
+    audited_quiet(&state, &token, "privacy_pause_status", serde_json::json!({}), || {
+        require_quiet(&state, &token, "privacy_pause_status", Permission::ViewStatus)?;
+        Ok(state.privacy_pause.lock().unwrap().status(current_ts_millis()))
+    })
+}
+
+#[tauri::command]
+pub fn start_capture(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    // outputDir: String,
+    interval_secs: u64,
+    schedule: Option<ScheduleConfig>,
+) -> Result<String, String> {
+    let params = serde_json::json!({ "interval_secs": interval_secs, "schedule": schedule });
+    audited(&state, &token, "start_capture", params, || {
+        require(&state, &token, "start_capture", Permission::ControlCapture)?;
+        let schedule = ScheduleConfig {
+            interval_secs,
+            ..schedule.unwrap_or_default()
+        };
+        spawn_screenshot_worker(&state, schedule)?;
+        Ok("Capture started".into())
+    })
+}
+
+#[tauri::command]
+pub fn stop_capture(state: State<'_, CaptureHandle>, token: String) -> Result<String, String> {
+    audited(&state, &token, "stop_capture", serde_json::json!({}), || {
+        require(&state, &token, "stop_capture", Permission::ControlCapture)?;
+        stop_screenshot_worker(&state);
+        Ok("Capture stopped".into())
+    })
+}
+
+#[tauri::command]
+pub fn capture_status(state: State<'_, CaptureHandle>, token: String) -> Result<bool, String> {
+    audited_quiet(&state, &token, "capture_status", serde_json::json!({}), || {
+        require_quiet(&state, &token, "capture_status", Permission::ViewStatus)?;
+        Ok(state.screenshot.is_running())
+    })
+}
+
+/// Running/Stopped/Failed(reason) plus timing for the screenshot loop
+#[tauri::command]
+pub fn capture_state(state: State<'_, CaptureHandle>, token: String) -> Result<WorkerStatus, String> {
+    audited_quiet(&state, &token, "capture_state", serde_json::json!({}), || {
+        require_quiet(&state, &token, "capture_state", Permission::ViewStatus)?;
+        Ok(state.screenshot.status())
+    })
+}
+
+/// Screenshot metadata, newest first, optionally filtered by foreground app
+#[tauri::command]
+pub fn list_screenshots(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    app: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<ScreenshotMeta>, String> {
+    let params = serde_json::json!({ "app": app, "limit": limit });
+    audited(&state, &token, "list_screenshots", params, || {
+        require(&state, &token, "list_screenshots", Permission::ViewActivity)?;
+        let limit = limit.unwrap_or(50).min(500);
+        Ok(screenshot_meta::list_sidecars(&state.capture_dirs.screenshots, app.as_deref(), limit))
+    })
+}
+
+#[tauri::command]
+pub fn get_redaction_rules(state: State<'_, CaptureHandle>, token: String) -> Result<Vec<RedactionRule>, String> {
+    audited(&state, &token, "get_redaction_rules", serde_json::json!({}), || {
+        require(&state, &token, "get_redaction_rules", Permission::ViewActivity)?;
+        Ok(state.redaction.lock().unwrap().rules().to_vec())
+    })
+}
+
+#[tauri::command]
+pub fn set_redaction_rules(state: State<'_, CaptureHandle>, token: String, rules: Vec<RedactionRule>) -> Result<String, String> {
+    let params = serde_json::json!({ "rules": rules });
+    audited(&state, &token, "set_redaction_rules", params, || {
+        require(&state, &token, "set_redaction_rules", Permission::ManagePolicy)?;
+        if state.policy.lock().unwrap().policy.is_some() {
+            return Err("Redaction rules are set by the server policy".into());
+        }
+        let policy = RedactionPolicy::new(rules)?;
+        redaction::save_rules(std::path::Path::new(REDACTION_RULES_PATH), policy.rules())?;
+        *state.redaction.lock().unwrap() = policy;
+        Ok("Redaction rules updated".into())
+    })
+}
+
+#[derive(Serialize)]
+pub struct RecordingHealth {
+    worker: WorkerStatus,
+    ffmpeg: VideoHealth,
+}
+
+/// Video worker state plus live ffmpeg progress, stderr tail and recent
+/// health events (starts, exits, stalls, dropped frames, restarts)
+#[tauri::command]
+pub fn recording_health(state: State<'_, CaptureHandle>, token: String) -> Result<RecordingHealth, String> {
+    audited_quiet(&state, &token, "recording_health", serde_json::json!({}), || {
+        require_quiet(&state, &token, "recording_health", Permission::ViewStatus)?;
+        Ok(RecordingHealth {
+            worker: state.video.status(),
+            ffmpeg: state.video_health.lock().unwrap().clone(),
+        })
+    })
+}
+
+#[derive(Serialize)]
+pub struct ProfileInfo {
+    #[serde(flatten)]
+    profile: EncodingProfile,
+    available: bool,
+    unavailable_reason: Option<String>,
+    /// Encoder used instead of the profile's own, which ffmpeg lacks
+    fallback_codec: Option<String>,
+}
+
+/// Encoding profiles and whether this machine's ffmpeg can run them
+#[tauri::command]
+pub fn list_encoding_profiles(state: State<'_, CaptureHandle>, token: String) -> Result<Vec<ProfileInfo>, String> {
+    audited(&state, &token, "list_encoding_profiles", serde_json::json!({}), || {
+        require(&state, &token, "list_encoding_profiles", Permission::ViewActivity)?;
+        let encoders = ffmpeg_encoders(&state);
+        let backend = GrabBackend::for_platform();
+        let profiles = state.encoding_profiles.lock().unwrap().clone();
+        Ok(profiles
+            .into_iter()
+            .map(|profile| {
+                let resolved = encoders
+                    .as_ref()
+                    .map_err(|e| e.clone())
+                    .and_then(|list| encoding::resolve_profile(&profile, backend, list));
+                let fallback_codec = match &resolved {
+                    Ok((used, Some(_))) => Some(used.codec.clone()),
+                    _ => None,
+                };
+                ProfileInfo {
+                    profile,
+                    available: resolved.is_ok(),
+                    unavailable_reason: resolved.err(),
+                    fallback_codec,
+                }
+            })
+            .collect())
+    })
+}
+
+/// Store `profiles` over the built-ins; one with a built-in's name replaces
+/// it. Applies to recordings started afterwards.
+#[tauri::command]
+pub fn set_encoding_profiles(state: State<'_, CaptureHandle>, token: String, profiles: Vec<EncodingProfile>) -> Result<String, String> {
+    let params = serde_json::json!({ "profiles": profiles });
+    audited(&state, &token, "set_encoding_profiles", params, || {
+        require(&state, &token, "set_encoding_profiles", Permission::ManagePolicy)?;
+        encoding::validate_profiles(&profiles)?;
+        let path = std::path::Path::new(ENCODING_PROFILES_PATH);
+        encoding::save_profiles(path, &profiles)?;
+        *state.encoding_profiles.lock().unwrap() = encoding::load_profiles(path)?;
+        Ok("Encoding profiles updated".into())
+    })
+}
+
+/// Resolve an RFC 3339 time (e.g. an activity entry's `timestamp`) to the
+/// video file covering it and the seek offset in seconds
+#[tauri::command]
+pub fn locate_recording(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    timestamp: String,
+) -> Result<Option<recording_index::ClipLocation>, String> {
+    let params = serde_json::json!({ "timestamp": timestamp });
+    audited(&state, &token, "locate_recording", params, || {
+        require(&state, &token, "locate_recording", Permission::ViewActivity)?;
+        let at = chrono::DateTime::parse_from_rfc3339(&timestamp).map_err(|e| format!("Invalid timestamp: {}", e))?;
+        let dir = &state.capture_dirs.videos;
+        let entries = recording_index::load(dir);
+        Ok(recording_index::locate(dir, &entries, at.timestamp_millis().max(0) as u64))
+    })
+}
+
+/// Indexed video clips and segments, newest first
+#[tauri::command]
+pub fn list_recordings(state: State<'_, CaptureHandle>, token: String, limit: Option<usize>) -> Result<Vec<RecordingEntry>, String> {
+    let params = serde_json::json!({ "limit": limit });
+    audited(&state, &token, "list_recordings", params, || {
+        require(&state, &token, "list_recordings", Permission::ViewActivity)?;
+        let limit = limit.unwrap_or(50).min(1000);
+        let mut entries = recording_index::load(&state.capture_dirs.videos);
+        entries.reverse();
+        entries.truncate(limit);
+        Ok(entries)
+    })
+}
+
+/// JPEG of the frame `offset_secs` into an indexed clip, returned as a file path
+#[tauri::command]
+pub fn extract_video_frame(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    file: String,
+    offset_secs: f64,
+    height: Option<u32>,
+) -> Result<String, String> {
+    let params = serde_json::json!({ "file": file, "offset_secs": offset_secs, "height": height });
+    audited(&state, &token, "extract_video_frame", params, || {
+        require(&state, &token, "extract_video_frame", Permission::ViewActivity)?;
+        let height = height.unwrap_or(thumbnails::DEFAULT_HEIGHT);
+        let path = thumbnails::extract_frame(&state.capture_dirs.videos, &file, offset_secs, height)?;
+        Ok(path.to_string_lossy().to_string())
+    })
+}
+
+/// Filmstrip of `count` thumbnails spread across an indexed clip
+#[tauri::command]
+pub fn video_thumbnails(
+    state: State<'_, CaptureHandle>,
+    token: String,
+    file: String,
+    count: Option<u32>,
+    height: Option<u32>,
+) -> Result<Vec<thumbnails::Thumbnail>, String> {
+    let params = serde_json::json!({ "file": file, "count": count, "height": height });
+    audited(&state, &token, "video_thumbnails", params, || {
+        require(&state, &token, "video_thumbnails", Permission::ViewActivity)?;
+        thumbnails::strip(
+            &state.capture_dirs.videos,
+            &file,
+            count.unwrap_or(10),
+            height.unwrap_or(thumbnails::DEFAULT_HEIGHT),
+        )
+    })
+}
+
+/// Usage per category, free disk space and whether captures are paused
+#[tauri::command]
+pub fn storage_status(state: State<'_, CaptureHandle>, token: String) -> Result<StorageStatus, String> {
+    audited_quiet(&state, &token, "storage_status", serde_json::json!({}), || {
+        require_quiet(&state, &token, "storage_status", Permission::ViewStatus)?;
+        Ok(state.storage.lock().unwrap().clone())
+    })
+}
+
+#[tauri::command]
+pub fn get_storage_quota(state: State<'_, CaptureHandle>, token: String) -> Result<QuotaConfig, String> {
+    audited(&state, &token, "get_storage_quota", serde_json::json!({}), || {
+        require(&state, &token, "get_storage_quota", Permission::ViewActivity)?;
+        Ok(state.storage_quota.lock().unwrap().clone())
+    })
+}
+
+/// Takes effect on the storage manager's next pass
+#[tauri::command]
+pub fn set_storage_quota(state: State<'_, CaptureHandle>, token: String, quota: QuotaConfig) -> Result<String, String> {
+    let params = serde_json::json!({ "quota": quota });
+    audited(&state, &token, "set_storage_quota", params, || {
+        require(&state, &token, "set_storage_quota", Permission::ManagePolicy)?;
+        storage::save_config(std::path::Path::new(STORAGE_QUOTA_PATH), &quota)?;
+        *state.storage_quota.lock().unwrap() = quota;
+        Ok("Storage quotas updated".into())
+    })
+}
+
+/// Queue depth and progress of uploads to the collection server
+#[tauri::command]
+pub fn upload_status(state: State<'_, CaptureHandle>, token: String) -> Result<UploadStatus, String> {
+    audited_quiet(&state, &token, "upload_status", serde_json::json!({}), || {
+        require_quiet(&state, &token, "upload_status", Permission::ViewStatus)?;
+        Ok(state.upload.lock().unwrap().clone())
+    })
+}
+
+#[tauri::command]
+pub fn get_upload_config(state: State<'_, CaptureHandle>, token: String) -> Result<UploadConfig, String> {
+    audited(&state, &token, "get_upload_config", serde_json::json!({}), || {
+        require(&state, &token, "get_upload_config", Permission::ViewActivity)?;
+        Ok(state.upload_config.lock().unwrap().clone())
+    })
+}
+
+#[tauri::command]
+pub fn set_upload_config(state: State<'_, CaptureHandle>, token: String, config: UploadConfig) -> Result<String, String> {
+    let params = serde_json::json!({ "config": config });
+    audited(&state, &token, "set_upload_config", params, || {
+        require(&state, &token, "set_upload_config", Permission::ManagePolicy)?;
+        let endpoint = config.endpoint.trim();
+        if config.enabled && !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+            return Err("Upload endpoint must be an http:// or https:// URL".into());
+        }
+        if config.chunk_bytes < 64 * 1024 {
+            return Err("Upload chunks must be at least 64 KiB".into());
+        }
+        upload::save_config(std::path::Path::new(UPLOAD_CONFIG_PATH), &config)?;
+        *state.upload_config.lock().unwrap() = config;
+        Ok("Upload settings updated".into())
+    })
+}
+
+#[tauri::command]
+pub fn device_identity(state: State<'_, CaptureHandle>, token: String) -> Result<IdentitySummary, String> {
+    audited(&state, &token, "device_identity", serde_json::json!({}), || {
+        require(&state, &token, "device_identity", Permission::ViewStatus)?;
+        Ok(state.identity.lock().unwrap().summary())
+    })
+}
+
+/// Register this device with a collection server and send uploads there
+#[tauri::command]
+pub fn enroll_device(state: State<'_, CaptureHandle>, token: String, server: String, enrollment_code: String) -> Result<String, String> {
+    let params = serde_json::json!({ "server": server });
+    audited(&state, &token, "enroll_device", params, || {
+        require(&state, &token, "enroll_device", Permission::ManagePolicy)?;
+        // Enrolled on a copy so the network call does not block uploads and captures
+        let mut identity = state.identity.lock().unwrap().clone();
+        identity::enroll(&mut identity, &server, &enrollment_code, current_ts_millis())?;
+        identity.save(std::path::Path::new(DEVICE_IDENTITY_PATH))?;
+        let device_id = identity.device_id.clone();
+        let server = identity.enrollment.as_ref().map(|e| e.server.clone()).unwrap_or_default();
+        *state.identity.lock().unwrap() = identity;
+
+        let mut config = state.upload_config.lock().unwrap();
+        config.endpoint = server.clone();
+        config.enabled = true;
+        upload::save_config(std::path::Path::new(UPLOAD_CONFIG_PATH), &config)?;
+        println!("🆔 Device {} enrolled with {}", device_id, server);
+        Ok(format!("Device {} enrolled", device_id))
+    })
+}
+
+/// The server policy in effect, where it came from and the last sync error
+#[tauri::command]
+pub fn policy_status(state: State<'_, CaptureHandle>, token: String) -> Result<PolicyStatus, String> {
+    audited_quiet(&state, &token, "policy_status", serde_json::json!({}), || {
+        require_quiet(&state, &token, "policy_status", Permission::ViewStatus)?;
+        Ok(state.policy.lock().unwrap().clone())
+    })
+}
+
+/// Fetch the server policy now instead of waiting for the next refresh
+#[tauri::command]
+pub fn refresh_policy(state: State<'_, CaptureHandle>, token: String) -> Result<String, String> {
+    audited(&state, &token, "refresh_policy", serde_json::json!({}), || {
+        require(&state, &token, "refresh_policy", Permission::ManagePolicy)?;
+        match sync_policy(&state) {
+            Ok(Some(version)) => Ok(format!("Policy version {} applied", version)),
+            Ok(None) => Ok("Policy is up to date".into()),
+            Err(e) => {
+                state.policy.lock().unwrap().last_error = Some(e.clone());
+                Err(e)
+            }
+        }
+    })
+}
+
+/// Whether the command channel is connected and what it last ran
+#[tauri::command]
+pub fn remote_status(state: State<'_, CaptureHandle>, token: String) -> Result<RemoteStatus, String> {
+    audited_quiet(&state, &token, "remote_status", serde_json::json!({}), || {
+        require_quiet(&state, &token, "remote_status", Permission::ViewStatus)?;
+        Ok(state.remote.lock().unwrap().clone())
+    })
+}
+
+/// Uptime, worker states, upload backlog, disk usage and the last error
+#[tauri::command]
+pub fn agent_health(state: State<'_, CaptureHandle>, token: String) -> Result<Heartbeat, String> {
+    audited_quiet(&state, &token, "agent_health", serde_json::json!({}), || {
+        require_quiet(&state, &token, "agent_health", Permission::ViewStatus)?;
+        Ok(build_heartbeat(&state))
+    })
+}
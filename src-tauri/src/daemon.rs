@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::agent::{self, CaptureHandle, HEARTBEAT_PATH, LOGS_DIR};
+use crate::capture::{prepare_recording, spawn_screenshot_worker, spawn_video, VideoMode};
+use crate::heartbeat::{self, Heartbeat};
+use crate::policy::VideoPolicy;
+use crate::schedule::ScheduleConfig;
+
+pub const AGENT_CONFIG_PATH: &str = "config/agent.json";
+/// Written while the headless agent runs
+const PID_PATH: &str = "status/agent.pid";
+/// Locked by the running agent for its whole lifetime. The OS drops the
+/// lock when the process ends, even in a crash, so unlike the pid file it
+/// cannot go stale or be mistaken for another process that reuses the pid.
+/// Kept apart from the pid file because Windows locks block other readers.
+const LOCK_PATH: &str = "status/agent.lock";
+/// How long `run` waits out another process checking the lock
+const LOCK_RETRIES: u32 = 5;
+/// `stop` creates this file; the agent notices it within `STOP_POLL`
+const STOP_PATH: &str = "status/agent.stop";
+const STOP_POLL: Duration = Duration::from_secs(1);
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+// Win32 process creation flags: no console, and out of reach of the
+// parent's Ctrl+C
+#[cfg(windows)]
+const DETACHED_PROCESS: u32 = 0x0000_0008;
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+/// What the headless agent captures at startup. A server policy, once
+/// applied, takes over from these settings like it does in the app.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    pub screenshots_enabled: bool,
+    pub schedule: ScheduleConfig,
+    /// Continuous recording
+    pub video: VideoPolicy,
+}
+
+pub fn load_config(path: &Path) -> Result<AgentConfig, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AgentConfig::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Whether the agent is running and the last heartbeat it wrote
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentStatus {
+    pub pid: Option<u32>,
+    pub heartbeat: Option<Heartbeat>,
+}
+
+/// Take the agent lock at `path`; `None` while another process holds it
+fn try_lock(path: &Path) -> Result<Option<File>, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(format!("Cannot lock {}: {}", path.display(), e)),
+    }
+}
+
+/// Whether a headless agent holds the lock. Probing takes the lock for a
+/// moment, which `run` allows for.
+fn agent_running() -> bool {
+    matches!(try_lock(Path::new(LOCK_PATH)), Ok(None))
+}
+
+/// Pid of the running headless agent; a file left by one that crashed is ignored
+fn running_pid() -> Option<u32> {
+    if !agent_running() {
+        return None;
+    }
+    fs::read_to_string(PID_PATH).ok()?.trim().parse().ok()
+}
+
+fn write_pid() -> Result<(), String> {
+    let tmp = Path::new(PID_PATH).with_extension("pid.tmp");
+    fs::write(&tmp, std::process::id().to_string()).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, PID_PATH).map_err(|e| e.to_string())
+}
+
+/// Failures are reported like any worker failure rather than stopping the
+/// agent, so it still reports in and can be fixed by policy or remote command
+fn start_captures(capture: &CaptureHandle, config: &AgentConfig) {
+    if config.screenshots_enabled {
+        if let Err(e) = spawn_screenshot_worker(capture, config.schedule.clone()) {
+            eprintln!("⚠️ Screenshots not started: {}", e);
+            capture.report_error("screenshots", e);
+        }
+    }
+    if config.video.enabled {
+        let mode = VideoMode::Continuous {
+            segment_secs: config.video.segment_secs,
+        };
+        let started = prepare_recording(capture, config.video.profile.clone(), None)
+            .and_then(|target| spawn_video(capture, target, mode));
+        if let Err(e) = started {
+            eprintln!("⚠️ Recording not started: {}", e);
+            capture.report_error("video", e);
+        }
+    }
+}
+
+/// Run the agent in the foreground until `stop` fires (a signal) or
+/// `request_stop` is called from another process. Paths are relative to
+/// the working directory, as in the app.
+pub fn run(stop: &Receiver<()>) -> Result<(), String> {
+    let mut lock = None;
+    for _ in 0..LOCK_RETRIES {
+        lock = try_lock(Path::new(LOCK_PATH))?;
+        if lock.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    // Held until `run` returns
+    let Some(_lock) = lock else {
+        return Err(match fs::read_to_string(PID_PATH) {
+            Ok(pid) => format!("Agent already running (pid {})", pid.trim()),
+            Err(_) => "Agent already running".into(),
+        });
+    };
+    let config = load_config(Path::new(AGENT_CONFIG_PATH))?;
+    let _ = fs::remove_file(STOP_PATH);
+    write_pid()?;
+
+    let capture = CaptureHandle::new();
+    start_captures(&capture, &config);
+    agent::start(&capture);
+    println!("🛰️ Agent running (pid {})", std::process::id());
+
+    let reason = loop {
+        match stop.recv_timeout(STOP_POLL) {
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => break "signal",
+            Err(RecvTimeoutError::Timeout) if Path::new(STOP_PATH).exists() => break "stop request",
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    };
+    println!("🛑 Stopping agent ({})", reason);
+    agent::shutdown(&capture);
+    let _ = fs::remove_file(STOP_PATH);
+    let _ = fs::remove_file(PID_PATH);
+    Ok(())
+}
+
+/// Run the agent without a console and in its own process group, so the
+/// terminal that started it can be closed or Ctrl+C'd
+#[cfg(windows)]
+fn detach(command: &mut Command) -> &mut Command {
+    use std::os::windows::process::CommandExt;
+    command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+}
+
+#[cfg(not(windows))]
+fn detach(command: &mut Command) -> &mut Command {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0)
+}
+
+/// Launch `run` as a detached background process with its output appended
+/// to `logs/agent.log`. Returns once the new agent has written its pid.
+pub fn start_detached() -> Result<u32, String> {
+    if let Some(pid) = running_pid() {
+        return Err(format!("Agent already running (pid {})", pid));
+    }
+    let dir = std::env::current_dir().map_err(|e| e.to_string())?;
+    fs::create_dir_all(LOGS_DIR).map_err(|e| e.to_string())?;
+    let log_path = Path::new(LOGS_DIR).join("agent.log");
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| format!("Cannot open {}: {}", log_path.display(), e))?;
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let mut command = Command::new(exe);
+    command
+        .arg("run")
+        .arg("--dir")
+        .arg(&dir)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone().map_err(|e| e.to_string())?)
+        .stderr(log);
+    let mut child = detach(&mut command)
+        .spawn()
+        .map_err(|e| format!("Cannot start agent: {}", e))?;
+
+    let started = Instant::now();
+    while started.elapsed() < START_TIMEOUT {
+        if running_pid() == Some(child.id()) {
+            return Ok(child.id());
+        }
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("Agent exited during startup ({}), see {}", status, log_path.display()));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    Err(format!("Agent did not report in within {}s, see {}", START_TIMEOUT.as_secs(), log_path.display()))
+}
+
+/// Ask the running agent to shut down and wait for it to exit
+pub fn request_stop() -> Result<u32, String> {
+    let pid = running_pid().ok_or("Agent is not running")?;
+    fs::write(STOP_PATH, b"").map_err(|e| e.to_string())?;
+    let asked = Instant::now();
+    while asked.elapsed() < STOP_TIMEOUT {
+        if !agent_running() {
+            return Ok(pid);
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    Err(format!("Agent (pid {}) did not stop within {}s", pid, STOP_TIMEOUT.as_secs()))
+}
+
+pub fn status() -> Result<AgentStatus, String> {
+    Ok(AgentStatus {
+        pid: running_pid(),
+        heartbeat: heartbeat::read_local(Path::new(HEARTBEAT_PATH))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_one_holder_of_the_agent_lock() {
+        let dir = std::env::temp_dir().join(format!("agent_lock_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("status").join("agent.lock");
+
+        let held = try_lock(&path).unwrap().expect("first lock");
+        assert!(try_lock(&path).unwrap().is_none());
+        // Released with the file, as when the agent exits or crashes
+        drop(held);
+        assert!(try_lock(&path).unwrap().is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
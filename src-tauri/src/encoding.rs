@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::ffmpeg::GrabBackend;
+
+pub const DEFAULT_PROFILE: &str = "review_quality";
+/// Tried in order when a profile's encoder is missing from the ffmpeg build
+const FALLBACK_CODECS: &[&str] = &["libx264", "libopenh264", "h264_mf", "mpeg4"];
+/// Bitrate for a fallback encoder that cannot use the profile's CRF
+const FALLBACK_BITRATE_KBPS: u32 = 2000;
+
+/// What turns captured frames into a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncoderBackend {
+    /// External `ffmpeg` process grabbing the screen itself
+    Ffmpeg,
+    /// scrap frames compressed in-process to Motion-JPEG AVI; no ffmpeg needed
+    Mjpeg,
+}
+
+/// Named set of ffmpeg output settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodingProfile {
+    pub name: String,
+    /// ffmpeg encoder name, e.g. `libx264`, `libx265`, `h264_nvenc`
+    pub codec: String,
+    pub framerate: u32,
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Constant quality; takes precedence over `bitrate_kbps`
+    #[serde(default)]
+    pub crf: Option<u32>,
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+    /// Downscale to this height keeping aspect ratio; `None` keeps native size
+    #[serde(default)]
+    pub scale_height: Option<u32>,
+    pub pix_fmt: String,
+    #[serde(default)]
+    pub audio: bool,
+    /// Capture device for audio (DirectShow name on Windows, PulseAudio
+    /// source on Linux, avfoundation index on macOS)
+    #[serde(default)]
+    pub audio_device: Option<String>,
+}
+
+pub fn builtin_profiles() -> Vec<EncodingProfile> {
+    vec![
+        EncodingProfile {
+            name: "low_bandwidth".into(),
+            codec: "libx264".into(),
+            framerate: 5,
+            preset: Some("veryfast".into()),
+            crf: Some(35),
+            bitrate_kbps: None,
+            scale_height: Some(720),
+            pix_fmt: "yuv420p".into(),
+            audio: false,
+            audio_device: None,
+        },
+        EncodingProfile {
+            name: "review_quality".into(),
+            codec: "libx264".into(),
+            framerate: 15,
+            preset: Some("ultrafast".into()),
+            crf: Some(28),
+            bitrate_kbps: None,
+            scale_height: None,
+            pix_fmt: "yuv420p".into(),
+            audio: false,
+            audio_device: None,
+        },
+        EncodingProfile {
+            name: "archival".into(),
+            codec: "libx264".into(),
+            framerate: 30,
+            preset: Some("medium".into()),
+            crf: Some(20),
+            bitrate_kbps: None,
+            scale_height: None,
+            pix_fmt: "yuv420p".into(),
+            audio: false,
+            audio_device: None,
+        },
+        // On Windows `audio_device` must name a DirectShow device, set it in
+        // the profiles config
+        EncodingProfile {
+            name: "review_with_audio".into(),
+            codec: "libx264".into(),
+            framerate: 15,
+            preset: Some("ultrafast".into()),
+            crf: Some(28),
+            bitrate_kbps: None,
+            scale_height: None,
+            pix_fmt: "yuv420p".into(),
+            audio: true,
+            audio_device: None,
+        },
+    ]
+}
+
+pub fn profile_by_name(profiles: &[EncodingProfile], name: &str) -> Option<EncodingProfile> {
+    profiles.iter().find(|p| p.name == name).cloned()
+}
+
+/// The built-ins with the profiles configured in `path` laid over them: a
+/// configured profile replaces the built-in of the same name, others are
+/// added. Built-ins alone when the file does not exist yet.
+pub fn load_profiles(path: &Path) -> Result<Vec<EncodingProfile>, String> {
+    let configured: Vec<EncodingProfile> = match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| e.to_string())?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.to_string()),
+    };
+    validate_profiles(&configured)?;
+    let mut profiles = builtin_profiles();
+    for profile in configured {
+        match profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => profiles.push(profile),
+        }
+    }
+    Ok(profiles)
+}
+
+pub fn save_profiles(path: &Path, profiles: &[EncodingProfile]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+pub fn validate_profiles(profiles: &[EncodingProfile]) -> Result<(), String> {
+    for (i, p) in profiles.iter().enumerate() {
+        if p.name.trim().is_empty() {
+            return Err("Encoding profiles need a name".into());
+        }
+        if profiles[..i].iter().any(|q| q.name == p.name) {
+            return Err(format!("Encoding profile '{}' is defined twice", p.name));
+        }
+        if p.codec.trim().is_empty() || p.pix_fmt.trim().is_empty() {
+            return Err(format!("Profile '{}' needs a codec and pix_fmt", p.name));
+        }
+        if !(1..=60).contains(&p.framerate) {
+            return Err(format!("Profile '{}' framerate must be 1-60", p.name));
+        }
+    }
+    Ok(())
+}
+
+/// Names of every encoder the installed ffmpeg was built with
+pub fn probe_encoders() -> Result<Vec<String>, String> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-encoders"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("ffmpeg not found on PATH ({})", e))?;
+    if !output.status.success() {
+        return Err(format!("ffmpeg -encoders failed with {}", output.status));
+    }
+    Ok(parse_encoders(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the table printed by `ffmpeg -encoders`:
+/// a legend, a ` ------` separator, then `FLAGS name description` rows.
+pub fn parse_encoders(text: &str) -> Vec<String> {
+    text.lines()
+        .skip_while(|l| !l.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Ensure `profile` can actually run with this ffmpeg build and backend
+pub fn check_profile(profile: &EncodingProfile, backend: GrabBackend, encoders: &[String]) -> Result<(), String> {
+    if !encoders.iter().any(|e| e == &profile.codec) {
+        return Err(format!(
+            "Encoder '{}' required by profile '{}' is not available in this ffmpeg build",
+            profile.codec, profile.name
+        ));
+    }
+    if profile.audio {
+        if !encoders.iter().any(|e| e == "aac") {
+            return Err(format!("Profile '{}' records audio but the 'aac' encoder is not available", profile.name));
+        }
+        if backend == GrabBackend::Gdigrab && profile.audio_device.is_none() {
+            return Err(format!(
+                "Profile '{}' records audio; set audio_device to a DirectShow device name on Windows",
+                profile.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `profile` as it can actually run here. When its encoder is missing the
+/// first available of `FALLBACK_CODECS` takes its place, returned with a
+/// note saying so; a profile that cannot run either way is rejected.
+pub fn resolve_profile(
+    profile: &EncodingProfile,
+    backend: GrabBackend,
+    encoders: &[String],
+) -> Result<(EncodingProfile, Option<String>), String> {
+    let err = match check_profile(profile, backend, encoders) {
+        Ok(()) => return Ok((profile.clone(), None)),
+        Err(e) => e,
+    };
+    if encoders.iter().any(|e| e == &profile.codec) {
+        // The encoder is there; the problem is elsewhere, e.g. audio
+        return Err(err);
+    }
+    let Some(codec) = FALLBACK_CODECS.iter().find(|c| encoders.iter().any(|e| e == *c)) else {
+        return Err(err);
+    };
+    let mut fallback = profile.clone();
+    fallback.codec = codec.to_string();
+    // Preset and CRF are x264 options; others get a plain bitrate
+    if *codec != "libx264" {
+        fallback.preset = None;
+        fallback.crf = None;
+        fallback.bitrate_kbps = fallback.bitrate_kbps.or(Some(FALLBACK_BITRATE_KBPS));
+    }
+    check_profile(&fallback, backend, encoders)?;
+    let note = format!(
+        "Encoder '{}' for profile '{}' is not available, using '{}'",
+        profile.codec, profile.name, codec
+    );
+    Ok((fallback, Some(note)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoders(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("encoding_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn configured_profiles_override_and_extend_builtins() {
+        let path = temp_path("profiles");
+        let mut faster = profile_by_name(&builtin_profiles(), "low_bandwidth").unwrap();
+        faster.framerate = 2;
+        let mut hevc = faster.clone();
+        hevc.name = "hevc".into();
+        hevc.codec = "libx265".into();
+        save_profiles(&path, &[faster, hevc]).unwrap();
+
+        let profiles = load_profiles(&path).unwrap();
+        assert_eq!(profiles.len(), builtin_profiles().len() + 1);
+        assert_eq!(profile_by_name(&profiles, "low_bandwidth").unwrap().framerate, 2);
+        assert_eq!(profile_by_name(&profiles, "hevc").unwrap().codec, "libx265");
+        assert!(profiles.iter().any(|p| p.audio));
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(load_profiles(&temp_path("missing")).unwrap().len(), builtin_profiles().len());
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected() {
+        let base = profile_by_name(&builtin_profiles(), DEFAULT_PROFILE).unwrap();
+        assert!(validate_profiles(&[base.clone(), base.clone()]).is_err());
+        let mut zero = base.clone();
+        zero.framerate = 0;
+        assert!(validate_profiles(&[zero]).is_err());
+        let mut unnamed = base;
+        unnamed.name = " ".into();
+        assert!(validate_profiles(&[unnamed]).is_err());
+    }
+
+    #[test]
+    fn missing_encoder_falls_back() {
+        let mut profile = profile_by_name(&builtin_profiles(), DEFAULT_PROFILE).unwrap();
+        let (used, note) = resolve_profile(&profile, GrabBackend::X11grab, &encoders(&["libx264"])).unwrap();
+        assert_eq!(used.codec, "libx264");
+        assert!(note.is_none());
+
+        profile.codec = "h264_nvenc".into();
+        let (used, note) = resolve_profile(&profile, GrabBackend::X11grab, &encoders(&["mpeg4", "libopenh264"])).unwrap();
+        assert_eq!(used.codec, "libopenh264");
+        assert!(used.crf.is_none() && used.preset.is_none());
+        assert_eq!(used.bitrate_kbps, Some(FALLBACK_BITRATE_KBPS));
+        assert!(note.unwrap().contains("h264_nvenc"));
+
+        assert!(resolve_profile(&profile, GrabBackend::X11grab, &encoders(&["png"])).is_err());
+    }
+
+    #[test]
+    fn audio_problems_are_not_papered_over() {
+        let profile = profile_by_name(&builtin_profiles(), "review_with_audio").unwrap();
+        assert!(resolve_profile(&profile, GrabBackend::X11grab, &encoders(&["libx264", "aac"])).is_ok());
+        assert!(resolve_profile(&profile, GrabBackend::X11grab, &encoders(&["libx264"])).is_err());
+        // DirectShow needs a named device
+        assert!(resolve_profile(&profile, GrabBackend::Gdigrab, &encoders(&["libx264", "aac"])).is_err());
+    }
+
+    #[test]
+    fn parses_encoder_table() {
+        let text = "Encoders:\n V..... = Video\n ------\n V....D libx264              libx264 H.264\n A....D aac                  AAC\n";
+        assert_eq!(parse_encoders(text), encoders(&["libx264", "aac"]));
+    }
+}
@@ -0,0 +1,522 @@
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::encoding::EncodingProfile;
+
+/// How long ffmpeg gets to write the MP4 index after `q` before it is killed
+pub const GRACEFUL_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ffmpeg screen-grab input device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrabBackend {
+    Gdigrab,
+    X11grab,
+    Avfoundation,
+}
+
+impl GrabBackend {
+    pub fn for_platform() -> Self {
+        if cfg!(target_os = "windows") {
+            GrabBackend::Gdigrab
+        } else if cfg!(target_os = "macos") {
+            GrabBackend::Avfoundation
+        } else {
+            GrabBackend::X11grab
+        }
+    }
+}
+
+/// Region of the desktop to record, in screen pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DisplayGeometry {
+    pub width: u32,
+    pub height: u32,
+    pub offset_x: i32,
+    pub offset_y: i32,
+}
+
+impl DisplayGeometry {
+    /// libx264 with yuv420p rejects odd dimensions, so round down to even
+    fn even(&self) -> (u32, u32) {
+        (self.width & !1, self.height & !1)
+    }
+}
+
+/// Roll the output into fixed-length files with ffmpeg's segment muxer.
+/// The output path is then a strftime pattern such as `segment_%Y%m%d_%H%M%S.mp4`.
+#[derive(Debug, Clone)]
+pub struct Segmenting {
+    pub segment_secs: u64,
+    /// CSV list ffmpeg appends `file,start,end` to as each segment closes
+    pub list_path: PathBuf,
+}
+
+/// Everything needed to turn one recording into an ffmpeg argument vector
+#[derive(Debug, Clone)]
+pub struct VideoArgs {
+    pub backend: GrabBackend,
+    pub geometry: DisplayGeometry,
+    pub encoding: EncodingProfile,
+    pub draw_mouse: bool,
+    /// X11 display name such as `:0.0`; only used by `x11grab`
+    pub x11_display: String,
+    /// Stop after this many seconds; `None` records until ffmpeg is told to quit
+    pub duration_secs: Option<u64>,
+    pub segment: Option<Segmenting>,
+    /// Read packed BGRA frames of `geometry` size from stdin instead of
+    /// grabbing the screen; the caller closes stdin to finish the file
+    pub raw_input: bool,
+    pub output: PathBuf,
+}
+
+impl VideoArgs {
+    pub fn new(backend: GrabBackend, geometry: DisplayGeometry, encoding: EncodingProfile, output: PathBuf) -> Self {
+        Self {
+            backend,
+            geometry,
+            encoding,
+            draw_mouse: true,
+            x11_display: std::env::var("DISPLAY").unwrap_or_else(|_| ":0.0".into()),
+            duration_secs: None,
+            segment: None,
+            raw_input: false,
+            output,
+        }
+    }
+
+    pub fn input_args(&self) -> Vec<String> {
+        if self.raw_input {
+            let size = format!("{}x{}", self.geometry.width, self.geometry.height);
+            return to_strings(&[
+                "-f", "rawvideo",
+                "-pix_fmt", "bgra",
+                "-video_size", &size,
+                "-framerate", &self.encoding.framerate.to_string(),
+                "-i", "pipe:0",
+            ]);
+        }
+        let (w, h) = self.geometry.even();
+        let mouse = if self.draw_mouse { "1" } else { "0" };
+        let framerate = self.encoding.framerate.to_string();
+        let video_size = format!("{}x{}", w, h);
+        let offset_x = self.geometry.offset_x.to_string();
+        let offset_y = self.geometry.offset_y.to_string();
+
+        let audio_device = self.encoding.audio.then(|| self.encoding.audio_device.clone());
+
+        match self.backend {
+            GrabBackend::Gdigrab => {
+                let mut args = to_strings(&[
+                "-f", "gdigrab",
+                "-framerate", &framerate,
+                "-draw_mouse", mouse,
+                "-offset_x", &offset_x,
+                "-offset_y", &offset_y,
+                "-video_size", &video_size,
+                "-show_region", "0",
+                "-i", "desktop",
+                ]);
+                if let Some(Some(device)) = &audio_device {
+                    args.extend(to_strings(&["-f", "dshow", "-i", &format!("audio={}", device)]));
+                }
+                args
+            }
+            GrabBackend::X11grab => {
+                let input = format!("{}+{},{}", self.x11_display, offset_x, offset_y);
+                let mut args = to_strings(&[
+                    "-f", "x11grab",
+                    "-framerate", &framerate,
+                    "-draw_mouse", mouse,
+                    "-video_size", &video_size,
+                    "-i", &input,
+                ]);
+                if let Some(device) = &audio_device {
+                    let source = device.as_deref().unwrap_or("default");
+                    args.extend(to_strings(&["-f", "pulse", "-i", source]));
+                }
+                args
+            }
+            // avfoundation always grabs the whole screen at native size;
+            // audio comes from the same input as `video:audio`
+            GrabBackend::Avfoundation => {
+                let input = match &audio_device {
+                    Some(device) => format!("Capture screen 0:{}", device.as_deref().unwrap_or("0")),
+                    None => "Capture screen 0:none".into(),
+                };
+                to_strings(&[
+                    "-f", "avfoundation",
+                    "-framerate", &framerate,
+                    "-capture_cursor", mouse,
+                    "-i", &input,
+                ])
+            }
+        }
+    }
+
+    pub fn output_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+        if let Some(secs) = self.duration_secs {
+            args.extend(to_strings(&["-t", &secs.to_string()]));
+        }
+        let enc = &self.encoding;
+        if let Some(height) = enc.scale_height {
+            // -2 keeps the aspect ratio with an even width
+            args.extend(to_strings(&["-vf", &format!("scale=-2:{}", height & !1)]));
+        } else if self.backend == GrabBackend::Avfoundation || self.raw_input {
+            // Native sizes can be odd; keep x264 happy
+            args.extend(to_strings(&["-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2"]));
+        }
+        if let Some(seg) = &self.segment {
+            // Keyframe on every boundary so segments split exactly on time
+            let expr = format!("expr:gte(t,n_forced*{})", seg.segment_secs);
+            args.extend(to_strings(&["-force_key_frames", &expr]));
+        }
+        args.extend(to_strings(&["-vcodec", &enc.codec]));
+        if let Some(preset) = &enc.preset {
+            args.extend(to_strings(&["-preset", preset]));
+        }
+        if let Some(crf) = enc.crf {
+            args.extend(to_strings(&["-crf", &crf.to_string()]));
+        } else if let Some(kbps) = enc.bitrate_kbps {
+            args.extend(to_strings(&["-b:v", &format!("{}k", kbps)]));
+        }
+        args.extend(to_strings(&["-pix_fmt", &enc.pix_fmt]));
+        if enc.audio && !self.raw_input {
+            args.extend(to_strings(&["-c:a", "aac", "-b:a", "96k"]));
+        } else {
+            args.push("-an".into());
+        }
+        if let Some(seg) = &self.segment {
+            let list = seg.list_path.to_string_lossy();
+            args.extend(to_strings(&[
+                "-f", "segment",
+                "-segment_time", &seg.segment_secs.to_string(),
+                "-segment_format", "mp4",
+                "-reset_timestamps", "1",
+                "-segment_list", &list,
+                "-segment_list_type", "csv",
+                "-strftime", "1",
+            ]));
+        }
+        args.push(self.output.to_string_lossy().to_string());
+        args
+    }
+
+    /// Full argument vector, excluding the `ffmpeg` program name.
+    /// Progress reports go to stdout and only warnings/errors to stderr.
+    pub fn build(&self) -> Vec<String> {
+        let mut args = to_strings(&["-y", "-progress", "pipe:1", "-nostats", "-loglevel", "warning"]);
+        args.extend(self.input_args());
+        args.extend(self.output_args());
+        args
+    }
+}
+
+fn to_strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+/// Start ffmpeg with stdin piped so it can be asked to quit cleanly, and
+/// stdout/stderr piped for progress and error monitoring
+pub fn spawn(args: &[String]) -> io::Result<Child> {
+    Command::new("ffmpeg")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+}
+
+/// Send `q` so ffmpeg finalizes the file (an MP4 without its index is
+/// unplayable), then kill it if it has not exited within `timeout`.
+pub fn stop_gracefully(child: &mut Child, timeout: Duration) -> io::Result<ExitStatus> {
+    if let Some(status) = child.try_wait()? {
+        return Ok(status);
+    }
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // ffmpeg may already be exiting; a broken pipe here is fine
+        let _ = stdin.write_all(b"q");
+        let _ = stdin.flush();
+    }
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    eprintln!("ffmpeg did not exit within {:?}, killing it", timeout);
+    child.kill()?;
+    child.wait()
+}
+
+/// Stream facts reported by ffprobe for a finished file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub duration_secs: f64,
+}
+
+pub fn probe(path: &Path) -> Result<MediaInfo, String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=width,height,avg_frame_rate:format=duration",
+            "-of", "json",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_probe_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_probe_json(json: &str) -> Result<MediaInfo, String> {
+    let v: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let stream = v["streams"].get(0).ok_or("no video stream")?;
+    // avg_frame_rate is a fraction such as "15/1"
+    let fps = stream["avg_frame_rate"]
+        .as_str()
+        .and_then(|r| r.split_once('/'))
+        .and_then(|(n, d)| Some((n.parse::<f64>().ok()?, d.parse::<f64>().ok()?)))
+        .filter(|(_, d)| *d > 0.0)
+        .map_or(0.0, |(n, d)| n / d);
+    Ok(MediaInfo {
+        width: stream["width"].as_u64().unwrap_or(0) as u32,
+        height: stream["height"].as_u64().unwrap_or(0) as u32,
+        fps,
+        // ffprobe prints numbers in the format section as strings
+        duration_secs: v["format"]["duration"]
+            .as_str()
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(0.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> EncodingProfile {
+        EncodingProfile {
+            name: "test".into(),
+            codec: "libx264".into(),
+            framerate: 15,
+            preset: Some("ultrafast".into()),
+            crf: Some(28),
+            bitrate_kbps: None,
+            scale_height: None,
+            pix_fmt: "yuv420p".into(),
+            audio: false,
+            audio_device: None,
+        }
+    }
+
+    fn args(backend: GrabBackend) -> VideoArgs {
+        let geometry = DisplayGeometry {
+            width: 1921,
+            height: 1081,
+            offset_x: -1920,
+            offset_y: 40,
+        };
+        let mut args = VideoArgs::new(backend, geometry, profile(), PathBuf::from("out.mp4"));
+        args.x11_display = ":1.0".into();
+        args
+    }
+
+    /// Index of `flag` followed by `value` in `args`
+    fn find_pair(args: &[String], flag: &str, value: &str) -> Option<usize> {
+        args.windows(2).position(|w| w[0] == flag && w[1] == value)
+    }
+
+    #[test]
+    fn platform_backend_matches_target() {
+        let expected = if cfg!(target_os = "windows") {
+            GrabBackend::Gdigrab
+        } else if cfg!(target_os = "macos") {
+            GrabBackend::Avfoundation
+        } else {
+            GrabBackend::X11grab
+        };
+        assert_eq!(GrabBackend::for_platform(), expected);
+    }
+
+    #[test]
+    fn gdigrab_input() {
+        let mut a = args(GrabBackend::Gdigrab);
+        assert_eq!(
+            a.input_args(),
+            to_strings(&[
+                "-f", "gdigrab",
+                "-framerate", "15",
+                "-draw_mouse", "1",
+                "-offset_x", "-1920",
+                "-offset_y", "40",
+                "-video_size", "1920x1080",
+                "-show_region", "0",
+                "-i", "desktop",
+            ])
+        );
+        a.draw_mouse = false;
+        a.encoding.audio = true;
+        a.encoding.audio_device = Some("Microphone (USB)".into());
+        let input = a.input_args();
+        assert!(find_pair(&input, "-draw_mouse", "0").is_some());
+        assert!(input.ends_with(&to_strings(&["-f", "dshow", "-i", "audio=Microphone (USB)"])));
+    }
+
+    #[test]
+    fn x11grab_input() {
+        let mut a = args(GrabBackend::X11grab);
+        assert_eq!(
+            a.input_args(),
+            to_strings(&[
+                "-f", "x11grab",
+                "-framerate", "15",
+                "-draw_mouse", "1",
+                "-video_size", "1920x1080",
+                "-i", ":1.0+-1920,40",
+            ])
+        );
+        a.encoding.audio = true;
+        assert!(a.input_args().ends_with(&to_strings(&["-f", "pulse", "-i", "default"])));
+    }
+
+    #[test]
+    fn avfoundation_input() {
+        let mut a = args(GrabBackend::Avfoundation);
+        assert_eq!(
+            a.input_args(),
+            to_strings(&[
+                "-f", "avfoundation",
+                "-framerate", "15",
+                "-capture_cursor", "1",
+                "-i", "Capture screen 0:none",
+            ])
+        );
+        // Native size may be odd, so the output is always rounded to even
+        assert!(find_pair(&a.output_args(), "-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2").is_some());
+        a.encoding.audio = true;
+        a.encoding.audio_device = Some("1".into());
+        assert!(find_pair(&a.input_args(), "-i", "Capture screen 0:1").is_some());
+    }
+
+    #[test]
+    fn raw_input_reads_stdin_without_audio() {
+        let mut a = args(GrabBackend::Gdigrab);
+        a.raw_input = true;
+        a.encoding.audio = true;
+        assert_eq!(
+            a.input_args(),
+            to_strings(&[
+                "-f", "rawvideo",
+                "-pix_fmt", "bgra",
+                "-video_size", "1921x1081",
+                "-framerate", "15",
+                "-i", "pipe:0",
+            ])
+        );
+        let output = a.output_args();
+        assert!(output.contains(&"-an".to_string()));
+        assert!(!output.contains(&"aac".to_string()));
+    }
+
+    #[test]
+    fn single_file_output() {
+        let mut a = args(GrabBackend::Gdigrab);
+        a.duration_secs = Some(60);
+        assert_eq!(
+            a.output_args(),
+            to_strings(&[
+                "-t", "60",
+                "-vcodec", "libx264",
+                "-preset", "ultrafast",
+                "-crf", "28",
+                "-pix_fmt", "yuv420p",
+                "-an",
+                "out.mp4",
+            ])
+        );
+
+        a.encoding.crf = None;
+        a.encoding.bitrate_kbps = Some(800);
+        a.encoding.scale_height = Some(721);
+        a.encoding.audio = true;
+        let output = a.output_args();
+        assert!(find_pair(&output, "-b:v", "800k").is_some());
+        assert!(find_pair(&output, "-vf", "scale=-2:720").is_some());
+        assert!(find_pair(&output, "-c:a", "aac").is_some());
+        assert!(!output.contains(&"-f".to_string()));
+    }
+
+    #[test]
+    fn segmented_output() {
+        let mut a = args(GrabBackend::X11grab);
+        a.output = PathBuf::from("segment_%Y%m%d_%H%M%S.mp4");
+        a.segment = Some(Segmenting {
+            segment_secs: 300,
+            list_path: PathBuf::from("segments.csv"),
+        });
+        let output = a.output_args();
+        assert!(find_pair(&output, "-force_key_frames", "expr:gte(t,n_forced*300)").is_some());
+        assert!(!output.contains(&"-t".to_string()));
+        assert!(output.ends_with(&to_strings(&[
+            "-f", "segment",
+            "-segment_time", "300",
+            "-segment_format", "mp4",
+            "-reset_timestamps", "1",
+            "-segment_list", "segments.csv",
+            "-segment_list_type", "csv",
+            "-strftime", "1",
+            "segment_%Y%m%d_%H%M%S.mp4",
+        ])));
+    }
+
+    #[test]
+    fn build_puts_global_flags_first() {
+        let a = args(GrabBackend::Gdigrab);
+        let built = a.build();
+        assert_eq!(built[..6], to_strings(&["-y", "-progress", "pipe:1", "-nostats", "-loglevel", "warning"])[..]);
+        assert_eq!(built[6..6 + a.input_args().len()], a.input_args()[..]);
+        assert_eq!(built.last().map(String::as_str), Some("out.mp4"));
+    }
+
+    #[test]
+    fn parse_probe_fixture() {
+        let json = r#"{
+            "programs": [],
+            "streams": [{ "width": 1280, "height": 720, "avg_frame_rate": "30000/1001" }],
+            "format": { "duration": "61.533000" }
+        }"#;
+        let info = parse_probe_json(json).unwrap();
+        assert_eq!((info.width, info.height), (1280, 720));
+        assert!((info.fps - 29.97).abs() < 0.01);
+        assert!((info.duration_secs - 61.533).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_probe_incomplete() {
+        let info = parse_probe_json(r#"{"streams": [{"width": 640, "height": 480, "avg_frame_rate": "0/0"}], "format": {}}"#).unwrap();
+        assert_eq!(info.fps, 0.0);
+        assert_eq!(info.duration_secs, 0.0);
+        assert!(parse_probe_json(r#"{"streams": [], "format": {}}"#).is_err());
+        assert!(parse_probe_json("not json").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::identity::DeviceSigner;
+use crate::privacy::PauseStatus;
+use crate::supervisor::SupervisorEvent;
+use crate::worker::WorkerStatus;
+
+/// The most recent failure anywhere in the agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentError {
+    /// Subsystem, e.g. `input`, `screenshots`, `upload`
+    pub source: String,
+    pub message: String,
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerHealth {
+    pub input: WorkerStatus,
+    pub screenshots: WorkerStatus,
+    pub video: WorkerStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueDepth {
+    pub pending: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskUsage {
+    /// Bytes used by screenshots, videos and logs
+    pub used_bytes: u64,
+    pub free_bytes: Option<u64>,
+    /// Captures are held back for lack of space
+    pub paused: bool,
+    pub last_check_ms: u64,
+}
+
+/// Periodic proof of life, kept locally and sent to the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub device_id: String,
+    pub hostname: String,
+    pub user: String,
+    pub version: String,
+    pub timestamp_ms: u64,
+    pub started_ms: u64,
+    pub uptime_secs: u64,
+    pub workers: WorkerHealth,
+    pub upload_queue: QueueDepth,
+    pub disk: DiskUsage,
+    /// Disk low, privacy pause or outside work hours
+    pub captures_held: bool,
+    /// Employee privacy pause and today's remaining allowance
+    #[serde(default)]
+    pub privacy_pause: PauseStatus,
+    pub policy_version: Option<u64>,
+    pub commands_connected: bool,
+    pub last_error: Option<AgentError>,
+    /// Recent worker exits, panics and restarts, oldest first
+    #[serde(default)]
+    pub health_events: Vec<SupervisorEvent>,
+    /// When the server last accepted a heartbeat
+    pub last_sent_ms: Option<u64>,
+}
+
+/// Written atomically so a reader never sees half a file
+pub fn write_local(path: &Path, heartbeat: &Heartbeat) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(heartbeat).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+/// The last heartbeat written by a running agent, `None` if there is none yet
+pub fn read_local(path: &Path) -> Result<Option<Heartbeat>, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s)
+            .map(Some)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// `POST /heartbeat`, signed like every other device request
+pub fn send(server: &str, signer: &DeviceSigner, heartbeat: &Heartbeat, now_ms: u64) -> Result<(), String> {
+    let body = serde_json::to_vec(heartbeat).map_err(|e| e.to_string())?;
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(30)).build();
+    let mut req = agent
+        .post(&format!("{}/heartbeat", server.trim_end_matches('/')))
+        .set("Content-Type", "application/json");
+    for (name, value) in signer.headers("POST", "/heartbeat", &body, now_ms) {
+        req = req.set(name, &value);
+    }
+    match req.send_bytes(&body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, resp)) => {
+            let msg = resp.into_string().unwrap_or_default();
+            Err(format!("Server returned {}: {}", code, msg.trim()))
+        }
+        Err(e) => Err(format!("Cannot reach {}: {}", server, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::current_ts_millis;
+    use crate::supervisor::SupervisorEventKind;
+    use crate::test_support::{enrolled_device, start_server, temp_dir, test_options};
+    use crate::worker::WorkerControl;
+
+    fn heartbeat(device_id: &str) -> Heartbeat {
+        let running = WorkerControl::new();
+        running.start().unwrap();
+        Heartbeat {
+            device_id: device_id.to_string(),
+            hostname: "host".into(),
+            user: "alice".into(),
+            version: "1.2.3".into(),
+            timestamp_ms: 2_000,
+            started_ms: 1_000,
+            uptime_secs: 1,
+            workers: WorkerHealth {
+                input: running.status(),
+                screenshots: running.status(),
+                video: WorkerControl::new().status(),
+            },
+            upload_queue: QueueDepth { pending: 3, failed: 1 },
+            disk: DiskUsage {
+                used_bytes: 42,
+                free_bytes: None,
+                paused: false,
+                last_check_ms: 1_500,
+            },
+            captures_held: true,
+            privacy_pause: PauseStatus {
+                active: true,
+                paused_by: Some("alice".into()),
+                until_ms: Some(60_000),
+                used_today_ms: 900_000,
+                remaining_today_ms: 2_700_000,
+            },
+            policy_version: Some(7),
+            commands_connected: false,
+            last_error: Some(AgentError {
+                source: "upload".into(),
+                message: "Cannot reach server".into(),
+                at_ms: 1_900,
+            }),
+            health_events: vec![SupervisorEvent {
+                ts_ms: 1_800,
+                worker: "video".into(),
+                kind: SupervisorEventKind::Restarting { attempt: 2, delay_secs: 10 },
+                message: "ffmpeg exited".into(),
+            }],
+            last_sent_ms: None,
+        }
+    }
+
+    #[test]
+    fn payload_has_the_fields_the_server_reads() {
+        let json = serde_json::to_value(heartbeat("device-1")).unwrap();
+        assert_eq!(json["device_id"], "device-1");
+        assert_eq!(json["uptime_secs"], 1);
+        assert_eq!(json["workers"]["screenshots"]["state"], "running");
+        assert_eq!(json["workers"]["video"]["state"], "stopped");
+        assert_eq!(json["upload_queue"]["pending"], 3);
+        assert_eq!(json["disk"]["free_bytes"], serde_json::Value::Null);
+        assert_eq!(json["captures_held"], true);
+        assert_eq!(json["privacy_pause"]["paused_by"], "alice");
+        assert_eq!(json["privacy_pause"]["remaining_today_ms"], 2_700_000);
+        assert_eq!(json["policy_version"], 7);
+        assert_eq!(json["last_error"]["message"], "Cannot reach server");
+        // Event kinds are flattened next to the other fields
+        let event = &json["health_events"][0];
+        assert_eq!(event["kind"], "restarting");
+        assert_eq!(event["attempt"], 2);
+        assert_eq!(event["worker"], "video");
+    }
+
+    #[test]
+    fn local_copy_round_trips_and_older_files_still_load() {
+        let dir = temp_dir("heartbeat-local");
+        let path = dir.join("status").join("heartbeat.json");
+        assert!(read_local(&path).unwrap().is_none());
+        write_local(&path, &heartbeat("device-1")).unwrap();
+        let read = read_local(&path).unwrap().unwrap();
+        assert_eq!(read.upload_queue.pending, 3);
+        assert_eq!(read.privacy_pause.until_ms, Some(60_000));
+        assert_eq!(read.health_events.len(), 1);
+
+        // Written before health events and privacy pauses were reported
+        let mut old = serde_json::to_value(heartbeat("device-1")).unwrap();
+        old.as_object_mut().unwrap().remove("health_events");
+        old.as_object_mut().unwrap().remove("privacy_pause");
+        fs::write(&path, old.to_string()).unwrap();
+        let read = read_local(&path).unwrap().unwrap();
+        assert!(read.health_events.is_empty());
+        assert!(!read.privacy_pause.active);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn server_keeps_the_signed_heartbeat() {
+        let dir = temp_dir("heartbeat-send");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir, &url);
+        send(&url, &device.signer().unwrap(), &heartbeat(&device.device_id), current_ts_millis()).unwrap();
+
+        let beats: serde_json::Value = ureq::get(&format!("{}/admin/heartbeats", url)).call().unwrap().into_json().unwrap();
+        let beat = &beats[&device.device_id];
+        assert_eq!(beat["upload_queue"]["pending"], 3);
+        assert_eq!(beat["privacy_pause"]["active"], true);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,332 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Who produced a record; stamped on activity events, screenshot sidecars
+/// and recording index entries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RecordIdentity {
+    pub device_id: String,
+    /// Windows account the agent runs under, `DOMAIN\user`
+    pub user: String,
+    /// Employee the device is enrolled for, see `Enrollment::employee`
+    #[serde(default)]
+    pub employee: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enrollment {
+    /// Collection server the device registered with
+    pub server: String,
+    pub enrolled_ms: u64,
+    /// Employee the server associated with the enrollment code, if any
+    #[serde(default)]
+    pub employee: Option<String>,
+    /// Ed25519 public key, hex, that the server signs policies with
+    #[serde(default)]
+    pub policy_key: Option<String>,
+}
+
+/// Persistent device identity, created on first start
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub device_id: String,
+    /// Ed25519 secret key, hex. Requests to the server are signed with it.
+    secret_key: String,
+    pub created_ms: u64,
+    #[serde(default)]
+    pub enrollment: Option<Enrollment>,
+}
+
+/// What commands may see of the identity
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentitySummary {
+    pub device_id: String,
+    pub public_key: String,
+    pub hostname: String,
+    pub user: String,
+    pub enrollment: Option<Enrollment>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.len() != N * 2 || !s.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+/// Random version 4 UUID
+fn new_device_id() -> String {
+    let mut b = [0u8; 16];
+    OsRng.fill_bytes(&mut b);
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let h = hex(&b);
+    format!("{}-{}-{}-{}-{}", &h[0..8], &h[8..12], &h[12..16], &h[16..20], &h[20..32])
+}
+
+pub fn os_user() -> String {
+    let user = std::env::var("USERNAME").or_else(|_| std::env::var("USER")).unwrap_or_else(|_| "unknown".into());
+    match std::env::var("USERDOMAIN") {
+        Ok(domain) if !domain.is_empty() => format!("{}\\{}", domain, user),
+        _ => user,
+    }
+}
+
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "unknown".into())
+}
+
+impl DeviceIdentity {
+    /// Load the identity, creating and saving a new one on first start. A
+    /// corrupt file is moved aside to `.bad` and replaced; the device then
+    /// has to be enrolled again.
+    pub fn load_or_create(path: &Path, now_ms: u64) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => match serde_json::from_str::<Self>(&s) {
+                Ok(identity) if unhex::<32>(&identity.secret_key).is_some() => return Ok(identity),
+                _ => {
+                    eprintln!("⚠️ Invalid {}, creating a new device identity", path.display());
+                    fs::rename(path, path.with_extension("json.bad")).map_err(|e| e.to_string())?;
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        let identity = Self::generate(now_ms);
+        identity.save(path)?;
+        println!("🆔 Created device identity {}", identity.device_id);
+        Ok(identity)
+    }
+
+    /// A new identity that is not saved anywhere; for running unenrolled when
+    /// the identity file cannot be used
+    pub fn generate(now_ms: u64) -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        Self {
+            device_id: new_device_id(),
+            secret_key: hex(&secret),
+            created_ms: now_ms,
+            enrollment: None,
+        }
+    }
+
+    /// Written to a temporary file first so a crash never loses the key
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    fn signing_key(&self) -> SigningKey {
+        // Checked in `load_or_create`
+        SigningKey::from_bytes(&unhex::<32>(&self.secret_key).unwrap_or_default())
+    }
+
+    pub fn public_key(&self) -> String {
+        hex(self.signing_key().verifying_key().as_bytes())
+    }
+
+    pub fn record_identity(&self) -> RecordIdentity {
+        RecordIdentity {
+            device_id: self.device_id.clone(),
+            user: os_user(),
+            employee: self.enrollment.as_ref().and_then(|e| e.employee.clone()),
+        }
+    }
+
+    pub fn summary(&self) -> IdentitySummary {
+        IdentitySummary {
+            device_id: self.device_id.clone(),
+            public_key: self.public_key(),
+            hostname: hostname(),
+            user: os_user(),
+            enrollment: self.enrollment.clone(),
+        }
+    }
+
+    /// Signs requests for the server; `None` until the device is enrolled
+    pub fn signer(&self) -> Option<DeviceSigner> {
+        self.enrollment.as_ref().map(|_| DeviceSigner {
+            device_id: self.device_id.clone(),
+            key: self.signing_key(),
+        })
+    }
+}
+
+/// The string a request signature covers
+pub fn signing_payload(method: &str, path: &str, timestamp_ms: u64, body: &[u8]) -> String {
+    format!("{}\n{}\n{}\n{:x}", method, path, timestamp_ms, Sha256::digest(body))
+}
+
+/// Adds `X-Device-Id`, `X-Timestamp` and `X-Signature` to server requests.
+/// The signature covers method, path, timestamp and body hash, so a captured
+/// request cannot be replayed against another endpoint or with another body.
+#[derive(Clone)]
+pub struct DeviceSigner {
+    device_id: String,
+    key: SigningKey,
+}
+
+impl DeviceSigner {
+    pub fn headers(&self, method: &str, path: &str, body: &[u8], now_ms: u64) -> [(&'static str, String); 3] {
+        let signature = self.key.sign(signing_payload(method, path, now_ms, body).as_bytes());
+        [
+            ("X-Device-Id", self.device_id.clone()),
+            ("X-Timestamp", now_ms.to_string()),
+            ("X-Signature", hex(&signature.to_bytes())),
+        ]
+    }
+}
+
+/// Check a signature made with the server's policy key over `message`
+pub fn verify_server_signature(key: &str, message: &[u8], signature: &str) -> Result<(), String> {
+    let key = unhex::<32>(key)
+        .and_then(|k| VerifyingKey::from_bytes(&k).ok())
+        .ok_or("Invalid server key")?;
+    let signature = unhex::<64>(signature).ok_or("Malformed signature")?;
+    key.verify_strict(message, &Signature::from_bytes(&signature))
+        .map_err(|_| "Bad signature".to_string())
+}
+
+#[derive(Deserialize)]
+struct EnrollReply {
+    #[serde(default)]
+    employee: Option<String>,
+    #[serde(default)]
+    policy_key: Option<String>,
+}
+
+/// The policy key handed out at enrollment is trusted from then on, so it
+/// must not be open to tampering in transit. Plain http is left for a server
+/// on this machine, such as `examples/mock_server.rs`.
+fn is_local_http(server: &str) -> bool {
+    let Some(rest) = server.strip_prefix("http://") else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+/// Register the device's public key with `server` using a one-time
+/// enrollment code handed out by an administrator
+pub fn enroll(identity: &mut DeviceIdentity, server: &str, code: &str, now_ms: u64) -> Result<(), String> {
+    let server = server.trim().trim_end_matches('/');
+    if !(server.starts_with("https://") || is_local_http(server)) {
+        return Err("Server must be an https:// URL; http:// is only accepted for localhost".into());
+    }
+    if code.trim().is_empty() {
+        return Err("Enrollment code is required".into());
+    }
+    let body = serde_json::json!({
+        "enrollment_code": code.trim(),
+        "device_id": identity.device_id,
+        "public_key": identity.public_key(),
+        "hostname": hostname(),
+        "user": os_user(),
+    });
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(30)).build();
+    let reply: EnrollReply = match agent.post(&format!("{}/enroll", server)).send_json(body) {
+        Ok(resp) => resp.into_json().map_err(|e| format!("Invalid enrollment reply: {}", e))?,
+        Err(ureq::Error::Status(code, resp)) => {
+            let msg = resp.into_string().unwrap_or_default();
+            return Err(format!("Enrollment refused ({}): {}", code, msg.trim()));
+        }
+        Err(e) => return Err(format!("Cannot reach {}: {}", server, e)),
+    };
+    identity.enrollment = Some(Enrollment {
+        server: server.to_string(),
+        enrolled_ms: now_ms,
+        employee: reply.employee,
+        policy_key: reply.policy_key,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::current_ts_millis;
+    use crate::test_support::{server_key, start_server, temp_dir, test_options, Options, ENROLL_CODE};
+    use crate::upload::{UploadClient, UploadKind, UploadQueue};
+
+    /// Upload a few bytes, which the server only accepts with a known device signature
+    fn signed_upload(url: &str, signer: DeviceSigner, dir: &Path) -> Result<(), String> {
+        let queue = UploadQueue::new(dir.join("queue"));
+        let mut item =
+            queue.enqueue_bytes(UploadKind::Screenshot, "probe.png", b"probe", serde_json::Value::Null, current_ts_millis())?;
+        UploadClient::new(url, signer).upload(&queue, &mut item, 1024).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn enrollment_round_trip() {
+        let dir = temp_dir("enroll");
+        let data = dir.join("server");
+        let url = start_server(&data, Options { employee: Some("E-1001".into()), ..test_options() });
+        let path = dir.join("device.json");
+        let mut identity = DeviceIdentity::load_or_create(&path, current_ts_millis()).unwrap();
+        assert!(identity.signer().is_none());
+
+        // Plain http only reaches a server on this machine
+        for remote in ["http://collector.example.com", "http://127.0.0.1@collector.example.com", "ftp://127.0.0.1"] {
+            let err = enroll(&mut identity, remote, ENROLL_CODE, current_ts_millis()).unwrap_err();
+            assert!(err.contains("https://"), "{}: {}", remote, err);
+        }
+        let err = enroll(&mut identity, &url, "WRONG-CODE", current_ts_millis()).unwrap_err();
+        assert!(err.contains("403"), "{}", err);
+        assert!(identity.enrollment.is_none());
+
+        enroll(&mut identity, &format!("{}/", url), ENROLL_CODE, current_ts_millis()).unwrap();
+        let enrollment = identity.enrollment.clone().unwrap();
+        assert_eq!(enrollment.server, url);
+        assert_eq!(enrollment.employee.as_deref(), Some("E-1001"));
+        assert_eq!(identity.record_identity().employee.as_deref(), Some("E-1001"));
+        let public = hex(server_key(&data).verifying_key().as_bytes());
+        assert_eq!(enrollment.policy_key.as_deref(), Some(public.as_str()));
+
+        // The server now accepts requests signed with the device key
+        identity.save(&path).unwrap();
+        let reloaded = DeviceIdentity::load_or_create(&path, current_ts_millis()).unwrap();
+        assert_eq!(reloaded.device_id, identity.device_id);
+        signed_upload(&url, reloaded.signer().unwrap(), &dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unenrolled_devices_are_refused() {
+        let dir = temp_dir("enroll-refused");
+        let url = start_server(&dir.join("server"), test_options());
+        let mut stranger = DeviceIdentity::load_or_create(&dir.join("stranger.json"), current_ts_millis()).unwrap();
+        // Enrolled with another server, so it has a signer the mock does not know
+        let other = start_server(&dir.join("other"), test_options());
+        enroll(&mut stranger, &other, ENROLL_CODE, current_ts_millis()).unwrap();
+
+        let err = signed_upload(&url, stranger.signer().unwrap(), &dir).unwrap_err();
+        assert!(err.contains("401"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
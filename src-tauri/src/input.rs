@@ -0,0 +1,317 @@
+use chrono::Local;
+use rdev::{listen, Event, EventType, Key, Button};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::{fs, thread};
+use std::time::Instant;
+
+use crate::agent::CaptureHandle;
+use crate::capture::get_active_window_info;
+use crate::current_ts_millis;
+use crate::upload::UploadKind;
+
+/// activity.log is rotated to activity_<timestamp>.log past this size
+const LOG_ROTATE_BYTES: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Metrics {
+    pub kpm: u64,
+    pub char_count: u64,
+    pub backspace_count: u64,
+    pub enter_count: u64,
+    pub copy_count: u64,
+    pub paste_count: u64,
+    pub mods: ModStats,
+    pub nav_keys: NavKeys,
+    pub function_keys: FunctionKeys,
+    pub mouse: MouseStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ModStats {
+    pub alt: u64,
+    pub shift: u64,
+    pub ctrl: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct NavKeys {
+    pub pgup_pgdn: u64,
+    pub arrows: u64,
+    pub home_end: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FunctionKeys {
+    pub f1_f12: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MouseStats {
+    pub left_clicks: u64,
+    pub right_clicks: u64,
+    pub middle_clicks: u64,
+    pub scrolls: u64,
+    pub moves: u64,
+    pub double_clicks: u64,
+    pub drags: u64,
+}
+
+impl Metrics {
+    /// Counters accumulated between `earlier` and `self`
+    pub(crate) fn since(&self, earlier: &Metrics) -> Metrics {
+        Metrics {
+            kpm: self.kpm.saturating_sub(earlier.kpm),
+            char_count: self.char_count.saturating_sub(earlier.char_count),
+            backspace_count: self.backspace_count.saturating_sub(earlier.backspace_count),
+            enter_count: self.enter_count.saturating_sub(earlier.enter_count),
+            copy_count: self.copy_count.saturating_sub(earlier.copy_count),
+            paste_count: self.paste_count.saturating_sub(earlier.paste_count),
+            mods: ModStats {
+                alt: self.mods.alt.saturating_sub(earlier.mods.alt),
+                shift: self.mods.shift.saturating_sub(earlier.mods.shift),
+                ctrl: self.mods.ctrl.saturating_sub(earlier.mods.ctrl),
+            },
+            nav_keys: NavKeys {
+                pgup_pgdn: self.nav_keys.pgup_pgdn.saturating_sub(earlier.nav_keys.pgup_pgdn),
+                arrows: self.nav_keys.arrows.saturating_sub(earlier.nav_keys.arrows),
+                home_end: self.nav_keys.home_end.saturating_sub(earlier.nav_keys.home_end),
+            },
+            function_keys: FunctionKeys {
+                f1_f12: self.function_keys.f1_f12.saturating_sub(earlier.function_keys.f1_f12),
+            },
+            mouse: MouseStats {
+                left_clicks: self.mouse.left_clicks.saturating_sub(earlier.mouse.left_clicks),
+                right_clicks: self.mouse.right_clicks.saturating_sub(earlier.mouse.right_clicks),
+                middle_clicks: self.mouse.middle_clicks.saturating_sub(earlier.mouse.middle_clicks),
+                scrolls: self.mouse.scrolls.saturating_sub(earlier.mouse.scrolls),
+                moves: self.mouse.moves.saturating_sub(earlier.mouse.moves),
+                double_clicks: self.mouse.double_clicks.saturating_sub(earlier.mouse.double_clicks),
+                drags: self.mouse.drags.saturating_sub(earlier.mouse.drags),
+            },
+        }
+    }
+}
+
+/// Move the full log aside and continue in a fresh file. Rotated logs are
+/// what the storage manager deletes when the logs quota is exceeded.
+fn rotate_log(file: &mut std::fs::File, log_path: &std::path::Path) {
+    let rotated = log_path.with_file_name(format!("activity_{}.log", Local::now().format("%Y%m%d_%H%M%S")));
+    // Windows cannot rename a file that is open without delete sharing
+    let placeholder = match OpenOptions::new().create(true).append(true).open(log_path.with_extension("rotating")) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("Failed to rotate activity.log: {}", e);
+            return;
+        }
+    };
+    let _ = std::mem::replace(file, placeholder);
+    if let Err(e) = fs::rename(log_path, &rotated) {
+        eprintln!("Failed to rotate activity.log: {}", e);
+    }
+    match OpenOptions::new().create(true).append(true).open(log_path) {
+        Ok(f) => *file = f,
+        Err(e) => eprintln!("Failed to reopen activity.log: {}", e),
+    }
+    let _ = fs::remove_file(log_path.with_extension("rotating"));
+}
+
+/// Input listener with throttling and batching
+pub(crate) fn spawn_input_listener(capture_handle: CaptureHandle, logs_dir: &std::path::Path) {
+    if let Err(e) = std::fs::create_dir_all(logs_dir) {
+        eprintln!("Failed to create logs dir: {}", e);
+    }
+
+    let log_path = logs_dir.join("activity.log");
+    let last_ts = capture_handle.last_input_ts.clone();
+    let queue = capture_handle.activity_queue.clone();
+    let file_lock = capture_handle.log_file_lock.clone();
+    let shared_metrics = capture_handle.input_metrics.clone();
+    let upload_config = capture_handle.upload_config.clone();
+    let activity_batch = capture_handle.activity_batch.clone();
+    // Read for every record so enrolling or re-enrolling takes effect at once
+    let device_identity = capture_handle.identity.clone();
+    let input = capture_handle.input.clone();
+    let _ = input.start();
+    let join_handle = capture_handle.input_join_handle.clone();
+
+    let handle = thread::spawn(move || {
+        let mut file = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path) {
+                Ok(f) => f,
+                Err(e) => {
+                    let msg = format!("Cannot open activity.log: {}", e);
+                    capture_handle.report_error("input", msg.clone());
+                    capture_handle.input.fail(msg);
+                    return;
+                }
+            };
+
+        let mut ctrl_pressed = false;
+        let mut mouse_pressed = false;
+        let mut last_click_time = 0u64;
+        let mut last_click_button = Button::Left;
+        
+        // Throttling for mouse moves to prevent system overload
+        let mut last_mouse_log = Instant::now();
+        let mut last_log_time = Instant::now();
+        const MOUSE_MOVE_THROTTLE_MS: u128 = 100; // Only log mouse moves every 100ms
+        const LOG_INTERVAL_MS: u128 = 500; // Batch writes every 500ms
+        
+        let mut pending_log = false;
+
+        let push_event = move |q: &Arc<Mutex<VecDeque<String>>>,
+                          file_lock: &Arc<Mutex<()>>,
+                          file: &mut std::fs::File,
+                          json: String| {
+            // Update queue
+            if let Ok(mut guard) = q.lock() {
+                guard.push_back(json.clone());
+                if guard.len() > 200 {
+                    guard.pop_front();
+                }
+            }
+            if upload_config.lock().unwrap().wants(UploadKind::Activity) {
+                activity_batch.lock().unwrap().push(json.clone());
+            }
+            input.progress();
+            
+            // Write to file with error handling
+            if let Ok(_fl) = file_lock.lock() {
+                if let Err(e) = writeln!(file, "{}", json) {
+                    eprintln!("Failed to write to log: {}", e);
+                }
+                let _ = file.flush();
+                if file.metadata().is_ok_and(|m| m.len() > LOG_ROTATE_BYTES) {
+                    rotate_log(file, &log_path);
+                }
+            }
+        };
+
+        let callback = move |event: Event| {
+            let ts = current_ts_millis();
+            last_ts.store(ts, Ordering::SeqCst);
+            let mut metrics = shared_metrics.lock().unwrap();
+
+            let mut should_log = true;
+
+            match event.event_type {
+                EventType::KeyPress(key) => match key {
+                    Key::ControlLeft | Key::ControlRight => {
+                        ctrl_pressed = true;
+                        metrics.mods.ctrl += 1;
+                    }
+                    Key::Alt | Key::AltGr => metrics.mods.alt += 1,
+                    Key::ShiftLeft | Key::ShiftRight => metrics.mods.shift += 1,
+                    Key::Return => metrics.enter_count += 1,
+                    Key::Backspace => metrics.backspace_count += 1,
+                    Key::LeftArrow | Key::RightArrow | Key::UpArrow | Key::DownArrow => {
+                        metrics.nav_keys.arrows += 1;
+                    }
+                    Key::Home | Key::End => metrics.nav_keys.home_end += 1,
+                    Key::PageUp | Key::PageDown => metrics.nav_keys.pgup_pgdn += 1,
+                    Key::F1 | Key::F2 | Key::F3 | Key::F4 | Key::F5 | Key::F6
+                    | Key::F7 | Key::F8 | Key::F9 | Key::F10 | Key::F11 | Key::F12 => {
+                        metrics.function_keys.f1_f12 += 1;
+                    }
+                    Key::KeyC if ctrl_pressed => metrics.copy_count += 1,
+                    Key::KeyV if ctrl_pressed => metrics.paste_count += 1,
+                    _ => metrics.char_count += 1,
+                },
+                EventType::KeyRelease(key) => {
+                    if matches!(key, Key::ControlLeft | Key::ControlRight) {
+                        ctrl_pressed = false;
+                    }
+                    should_log = false; // Don't log key releases to reduce noise
+                }
+                EventType::ButtonPress(button) => {
+                    mouse_pressed = true;
+                    
+                    if ts - last_click_time < 500 && button == last_click_button {
+                        metrics.mouse.double_clicks += 1;
+                    }
+                    
+                    last_click_time = ts;
+                    last_click_button = button;
+                    
+                    match button {
+                        Button::Left => metrics.mouse.left_clicks += 1,
+                        Button::Right => metrics.mouse.right_clicks += 1,
+                        Button::Middle => metrics.mouse.middle_clicks += 1,
+                        _ => {}
+                    }
+                }
+                EventType::ButtonRelease(_) => {
+                    mouse_pressed = false;
+                    should_log = false; // Don't log button releases
+                }
+                EventType::MouseMove { .. } => {
+                    metrics.mouse.moves += 1;
+                    
+                    if mouse_pressed {
+                        metrics.mouse.drags += 1;
+                    }
+                    
+                    // Throttle mouse move logging to prevent system overload
+                    let now = Instant::now();
+                    if now.duration_since(last_mouse_log).as_millis() < MOUSE_MOVE_THROTTLE_MS {
+                        should_log = false;
+                    } else {
+                        last_mouse_log = now;
+                        pending_log = true;
+                        should_log = false; // Will log in batch
+                    }
+                }
+                EventType::Wheel { .. } => {
+                    metrics.mouse.scrolls += 1;
+                }
+            }
+
+            // Batch logging to reduce I/O
+            if should_log {
+                pending_log = true;
+            }
+
+            // Only write to log every LOG_INTERVAL_MS or on important events
+            let now = Instant::now();
+            if pending_log && (should_log || now.duration_since(last_log_time).as_millis() >= LOG_INTERVAL_MS) {
+                // Logged without window fields when the foreground window
+                // is unknown, e.g. where window tracking is not implemented
+                let window = get_active_window_info();
+                let identity = device_identity.lock().unwrap().record_identity();
+                let json = serde_json::json!({
+                    "app_name": window.as_ref().map(|w| &w.0),
+                    "window_title": window.as_ref().map(|w| &w.2),
+                    "process_name": window.as_ref().map(|w| &w.1),
+                    "pid": window.as_ref().map(|w| w.3),
+                    "timestamp": Local::now().to_rfc3339(),
+                    "device_id": identity.device_id,
+                    "user": identity.user,
+                    "employee": identity.employee,
+                    "metrics": *metrics
+                })
+                .to_string();
+
+                push_event(&queue, &file_lock, &mut file, json);
+                pending_log = false;
+                last_log_time = now;
+            }
+        };
+
+        match listen(callback) {
+            Ok(()) => capture_handle.input.finish(),
+            Err(e) => {
+                let msg = format!("rdev error: {:?}", e);
+                capture_handle.report_error("input", msg.clone());
+                capture_handle.input.fail(msg);
+            }
+        }
+    });
+    *join_handle.lock().unwrap() = Some(handle);
+}
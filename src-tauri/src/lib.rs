@@ -1,14 +1,107 @@
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+pub mod agent;
+pub mod audit;
+pub mod capture;
+mod commands;
+pub mod daemon;
+pub mod encoding;
+pub mod ffmpeg;
+pub mod heartbeat;
+pub mod identity;
+pub mod input;
+pub mod mjpeg;
+pub mod motion;
+pub mod policy;
+pub mod privacy;
+pub mod recording_index;
+pub mod redaction;
+pub mod remote;
+pub mod roles;
+pub mod schedule;
+pub mod screenshot_meta;
+pub mod sessions;
+pub mod storage;
+pub mod supervisor;
+pub mod thumbnails;
+pub mod upload;
+pub mod users;
+pub mod video;
+pub mod video_health;
+pub mod worker;
+
+#[cfg(test)]
+mod test_support;
+
+use agent::CaptureHandle;
+
+pub(crate) fn current_ts_millis() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
 }
 
+/// The desktop app: monitoring plus the management UI. See
+/// `src/bin/spectosoft-agent.rs` for running without a window.
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let capture_handle = CaptureHandle::new();
+    agent::start(&capture_handle);
+
     tauri::Builder::default()
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
+        .manage(capture_handle)
+        .invoke_handler(tauri::generate_handler![
+            commands::login,
+            commands::logout,
+            commands::current_session,
+            commands::start_capture,
+            commands::stop_capture,
+            commands::capture_status,
+            commands::capture_state,
+            commands::is_idle,
+            commands::get_recent_activity,
+            commands::clear_activity,
+            commands::start_video_capture,
+            commands::stop_video_capture,
+            commands::start_continuous_video,
+            commands::start_motion_video,
+            commands::list_recordings,
+            commands::recording_health,
+            commands::list_encoding_profiles,
+            commands::set_encoding_profiles,
+            commands::locate_recording,
+            commands::extract_video_frame,
+            commands::video_thumbnails,
+            commands::storage_status,
+            commands::get_storage_quota,
+            commands::set_storage_quota,
+            commands::upload_status,
+            commands::get_upload_config,
+            commands::set_upload_config,
+            commands::device_identity,
+            commands::enroll_device,
+            commands::policy_status,
+            commands::refresh_policy,
+            commands::remote_status,
+            commands::agent_health,
+            commands::privacy_pause_status,
+            commands::auth_setup_required,
+            commands::setup_admin,
+            commands::create_user,
+            commands::change_password,
+            commands::reset_password,
+            commands::list_users,
+            commands::set_user_role,
+            commands::pause_capture,
+            commands::resume_capture,
+            commands::query_audit_log,
+            commands::list_screenshots,
+            commands::get_redaction_rules,
+            commands::set_redaction_rules,
+        ])
         .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .expect("error running tauri");
 }
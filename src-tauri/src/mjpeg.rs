@@ -0,0 +1,258 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{ColorType, RgbImage};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Plain AVI (without OpenDML extensions) tops out at 1 GiB; roll before that
+pub const MAX_AVI_BYTES: u64 = 1000 * 1024 * 1024;
+pub const JPEG_QUALITY: u8 = 75;
+
+// Byte offsets of the fields patched once the frame count is known. They
+// follow from the fixed header layout written by `write_header`.
+const RIFF_SIZE_AT: u64 = 4;
+const AVIH_TOTAL_FRAMES_AT: u64 = 48;
+const AVIH_BUFFER_SIZE_AT: u64 = 60;
+const STRH_LENGTH_AT: u64 = 140;
+const STRH_BUFFER_SIZE_AT: u64 = 144;
+const MOVI_SIZE_AT: u64 = 216;
+/// Offset of the `movi` fourcc; idx1 offsets are relative to it
+const MOVI_START: u64 = 220;
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+/// Motion-JPEG in an AVI container, written entirely in-process. Every frame
+/// is a keyframe, so a file cut short by a crash is still mostly playable.
+pub struct AviWriter {
+    out: BufWriter<File>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// (offset from `movi`, size) of every frame chunk, for idx1
+    index: Vec<(u32, u32)>,
+    pos: u64,
+    largest_frame: u32,
+}
+
+impl AviWriter {
+    pub fn create(path: &Path, width: u32, height: u32, fps: u32) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write_header(&mut out, width, height, fps.max(1))?;
+        Ok(Self {
+            out,
+            width,
+            height,
+            fps: fps.max(1),
+            index: Vec::new(),
+            pos: MOVI_START + 4,
+            largest_frame: 0,
+        })
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.index.len() as u32
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.frames() as f64 / self.fps as f64
+    }
+
+    pub fn write_jpeg(&mut self, jpeg: &[u8]) -> io::Result<()> {
+        let len = jpeg.len() as u32;
+        self.out.write_all(b"00dc")?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(jpeg)?;
+        // RIFF chunks are word aligned
+        let pad = len % 2;
+        if pad == 1 {
+            self.out.write_all(&[0])?;
+        }
+        self.index.push(((self.pos - MOVI_START) as u32, len));
+        self.pos += 8 + len as u64 + pad as u64;
+        self.largest_frame = self.largest_frame.max(len);
+        Ok(())
+    }
+
+    /// Append idx1 and patch the sizes and frame counts in the header
+    pub fn finish(mut self) -> io::Result<()> {
+        let movi_size = (self.pos - MOVI_START) as u32;
+
+        self.out.write_all(b"idx1")?;
+        self.out.write_all(&(self.index.len() as u32 * 16).to_le_bytes())?;
+        for (offset, size) in &self.index {
+            self.out.write_all(b"00dc")?;
+            self.out.write_all(&AVIIF_KEYFRAME.to_le_bytes())?;
+            self.out.write_all(&offset.to_le_bytes())?;
+            self.out.write_all(&size.to_le_bytes())?;
+        }
+        let total = self.pos + 8 + self.index.len() as u64 * 16;
+
+        let frames = self.frames();
+        let buffer = self.largest_frame + 8;
+        for (at, value) in [
+            (RIFF_SIZE_AT, (total - 8) as u32),
+            (AVIH_TOTAL_FRAMES_AT, frames),
+            (AVIH_BUFFER_SIZE_AT, buffer),
+            (STRH_LENGTH_AT, frames),
+            (STRH_BUFFER_SIZE_AT, buffer),
+            (MOVI_SIZE_AT, movi_size),
+        ] {
+            self.out.seek(SeekFrom::Start(at))?;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        self.out.flush()
+    }
+}
+
+fn write_header(out: &mut impl Write, width: u32, height: u32, fps: u32) -> io::Result<()> {
+    let mut h: Vec<u8> = Vec::with_capacity(MOVI_START as usize + 4);
+    let u32le = |h: &mut Vec<u8>, v: u32| h.extend_from_slice(&v.to_le_bytes());
+    let u16le = |h: &mut Vec<u8>, v: u16| h.extend_from_slice(&v.to_le_bytes());
+
+    h.extend_from_slice(b"RIFF");
+    u32le(&mut h, 0); // patched
+    h.extend_from_slice(b"AVI ");
+
+    h.extend_from_slice(b"LIST");
+    u32le(&mut h, 192);
+    h.extend_from_slice(b"hdrl");
+
+    // MainAVIHeader
+    h.extend_from_slice(b"avih");
+    u32le(&mut h, 56);
+    u32le(&mut h, 1_000_000 / fps); // microseconds per frame
+    u32le(&mut h, 0); // max bytes per sec
+    u32le(&mut h, 0); // padding granularity
+    u32le(&mut h, AVIF_HASINDEX);
+    u32le(&mut h, 0); // total frames, patched
+    u32le(&mut h, 0); // initial frames
+    u32le(&mut h, 1); // streams
+    u32le(&mut h, 0); // suggested buffer size, patched
+    u32le(&mut h, width);
+    u32le(&mut h, height);
+    h.extend_from_slice(&[0; 16]);
+
+    h.extend_from_slice(b"LIST");
+    u32le(&mut h, 116);
+    h.extend_from_slice(b"strl");
+
+    // AVIStreamHeader
+    h.extend_from_slice(b"strh");
+    u32le(&mut h, 56);
+    h.extend_from_slice(b"vids");
+    h.extend_from_slice(b"MJPG");
+    u32le(&mut h, 0); // flags
+    u16le(&mut h, 0); // priority
+    u16le(&mut h, 0); // language
+    u32le(&mut h, 0); // initial frames
+    u32le(&mut h, 1); // scale
+    u32le(&mut h, fps); // rate; fps = rate / scale
+    u32le(&mut h, 0); // start
+    u32le(&mut h, 0); // length, patched
+    u32le(&mut h, 0); // suggested buffer size, patched
+    u32le(&mut h, u32::MAX); // quality: driver default
+    u32le(&mut h, 0); // sample size
+    u16le(&mut h, 0);
+    u16le(&mut h, 0);
+    u16le(&mut h, width as u16);
+    u16le(&mut h, height as u16);
+
+    // BITMAPINFOHEADER
+    h.extend_from_slice(b"strf");
+    u32le(&mut h, 40);
+    u32le(&mut h, 40);
+    u32le(&mut h, width);
+    u32le(&mut h, height);
+    u16le(&mut h, 1); // planes
+    u16le(&mut h, 24); // bit count
+    h.extend_from_slice(b"MJPG");
+    u32le(&mut h, width * height * 3);
+    h.extend_from_slice(&[0; 16]);
+
+    h.extend_from_slice(b"LIST");
+    u32le(&mut h, 0); // patched
+    h.extend_from_slice(b"movi");
+
+    debug_assert_eq!(h.len() as u64, MOVI_START + 4);
+    out.write_all(&h)
+}
+
+/// Compress one packed BGRA frame, optionally downscaled to `scale_height`.
+/// Returns the JPEG with its final dimensions.
+pub fn encode_bgra(
+    frame: &[u8],
+    width: u32,
+    height: u32,
+    scale_height: Option<u32>,
+    quality: u8,
+) -> Result<(Vec<u8>, u32, u32), String> {
+    let rgb: Vec<u8> = frame
+        .chunks_exact(4)
+        .take((width * height) as usize)
+        .flat_map(|px| [px[2], px[1], px[0]])
+        .collect();
+    let mut img = RgbImage::from_raw(width, height, rgb).ok_or("Frame smaller than its dimensions")?;
+    if let Some(target) = scale_height.filter(|&t| t > 0 && t < height) {
+        let target_w = (width as u64 * target as u64 / height as u64) as u32;
+        img = imageops::resize(&img, target_w & !1, target & !1, FilterType::Triangle);
+    }
+
+    let (w, h) = img.dimensions();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, quality)
+        .encode(img.as_raw(), w, h, ColorType::Rgb8)
+        .map_err(|e| e.to_string())?;
+    Ok((jpeg, w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn u32_at(bytes: &[u8], at: u64) -> u32 {
+        let at = at as usize;
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn finish_patches_sizes_and_frame_counts() {
+        let path = std::env::temp_dir().join(format!("mjpeg_avi_{}.avi", std::process::id()));
+        let mut writer = AviWriter::create(&path, 64, 48, 10).unwrap();
+        // One odd-sized frame to exercise the word padding
+        writer.write_jpeg(&[0xAA; 101]).unwrap();
+        writer.write_jpeg(&[0xBB; 200]).unwrap();
+        assert_eq!(writer.frames(), 2);
+        assert_eq!(writer.duration_secs(), 0.2);
+        writer.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let movi_data = (8 + 101 + 1) + (8 + 200);
+        let idx1 = 8 + 2 * 16;
+        assert_eq!(bytes.len() as u64, MOVI_START + 4 + movi_data + idx1);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, RIFF_SIZE_AT) as usize, bytes.len() - 8);
+        assert_eq!(u32_at(&bytes, AVIH_TOTAL_FRAMES_AT), 2);
+        assert_eq!(u32_at(&bytes, AVIH_BUFFER_SIZE_AT), 208);
+        assert_eq!(u32_at(&bytes, STRH_LENGTH_AT), 2);
+        assert_eq!(u32_at(&bytes, STRH_BUFFER_SIZE_AT), 208);
+        assert_eq!(&bytes[MOVI_START as usize..MOVI_START as usize + 4], b"movi");
+        assert_eq!(u32_at(&bytes, MOVI_SIZE_AT) as u64, 4 + movi_data);
+
+        // idx1 follows movi and points at both chunks relative to `movi`
+        let idx = (MOVI_START + 4 + movi_data) as usize;
+        assert_eq!(&bytes[idx..idx + 4], b"idx1");
+        assert_eq!(u32_at(&bytes, idx as u64 + 16), 4);
+        assert_eq!(u32_at(&bytes, idx as u64 + 20), 101);
+        assert_eq!(u32_at(&bytes, idx as u64 + 32), 4 + 8 + 102);
+        assert_eq!(u32_at(&bytes, idx as u64 + 36), 200);
+    }
+}
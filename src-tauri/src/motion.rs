@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Compare every Nth pixel in both directions; plenty to spot a moving window
+const SAMPLE_STRIDE: usize = 8;
+/// Per-channel difference below this is treated as noise (cursor blink, dithering)
+const PIXEL_THRESHOLD: u8 = 24;
+const MAX_FPS: u32 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionConfig {
+    /// Frames sampled and encoded per second
+    pub fps: u32,
+    /// Seconds of frames kept in memory and written before the trigger
+    pub pre_roll_secs: u32,
+    /// Stop the clip after this long without change or input
+    pub quiet_secs: u64,
+    /// Percentage of sampled pixels that must change to count as motion
+    pub threshold_pct: f32,
+    /// Keyboard/mouse input keeps a clip going even on an unchanged screen
+    pub input_is_activity: bool,
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            fps: 5,
+            pre_roll_secs: 2,
+            quiet_secs: 10,
+            threshold_pct: 0.5,
+            input_is_activity: true,
+        }
+    }
+}
+
+impl MotionConfig {
+    /// `fps` limited to what the sampler actually runs at
+    pub fn effective_fps(&self) -> u32 {
+        self.fps.clamp(1, MAX_FPS)
+    }
+
+    pub fn pre_roll_frames(&self) -> usize {
+        (self.pre_roll_secs * self.effective_fps()) as usize
+    }
+
+    pub fn frame_interval_ms(&self) -> u64 {
+        1000 / self.effective_fps() as u64
+    }
+}
+
+/// Percentage of sampled pixels that differ between two packed BGRA frames
+pub fn changed_pct(prev: &[u8], cur: &[u8], width: usize, height: usize) -> f32 {
+    if prev.len() != cur.len() || prev.len() < width * height * 4 {
+        return 100.0;
+    }
+    let mut sampled = 0u32;
+    let mut changed = 0u32;
+    for y in (0..height).step_by(SAMPLE_STRIDE) {
+        for x in (0..width).step_by(SAMPLE_STRIDE) {
+            let i = (y * width + x) * 4;
+            sampled += 1;
+            if (0..3).any(|c| prev[i + c].abs_diff(cur[i + c]) > PIXEL_THRESHOLD) {
+                changed += 1;
+            }
+        }
+    }
+    if sampled == 0 {
+        return 0.0;
+    }
+    changed as f32 * 100.0 / sampled as f32
+}
+
+/// Decides from per-frame change and input timestamps whether a clip should
+/// be recording right now
+pub struct MotionDetector {
+    config: MotionConfig,
+    last_activity_ms: Option<u64>,
+    last_input_seen: u64,
+}
+
+impl MotionDetector {
+    pub fn new(config: MotionConfig, last_input_ts: u64) -> Self {
+        Self {
+            config,
+            last_activity_ms: None,
+            last_input_seen: last_input_ts,
+        }
+    }
+
+    pub fn config(&self) -> &MotionConfig {
+        &self.config
+    }
+
+    /// Feed one sample; true while activity was seen within `quiet_secs`
+    pub fn update(&mut self, now_ms: u64, changed_pct: f32, last_input_ts: u64) -> bool {
+        let input = self.config.input_is_activity && last_input_ts > self.last_input_seen;
+        self.last_input_seen = last_input_ts;
+        if input || changed_pct >= self.config.threshold_pct {
+            self.last_activity_ms = Some(now_ms);
+        }
+        self.last_activity_ms
+            .is_some_and(|t| now_ms.saturating_sub(t) < self.config.quiet_secs * 1000)
+    }
+}
+
+/// The frames from the last `pre_roll_secs`, oldest first, written ahead of
+/// the frame that triggers a clip
+pub struct PreRoll {
+    frames: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl PreRoll {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity + 1),
+            capacity,
+        }
+    }
+
+    /// Keep a copy of `frame`, dropping the oldest once full
+    pub fn push(&mut self, frame: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.frames.push_back(frame.to_vec());
+        if self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Hand out the buffered frames, oldest first, leaving it empty
+    pub fn drain(&mut self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.frames.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_roll_follows_the_clamped_rate() {
+        for (fps, effective) in [(0, 1), (5, 5), (30, 30), (120, 30)] {
+            let config = MotionConfig {
+                fps,
+                pre_roll_secs: 2,
+                ..MotionConfig::default()
+            };
+            assert_eq!(config.effective_fps(), effective);
+            assert_eq!(config.pre_roll_frames(), 2 * effective as usize);
+            assert_eq!(config.frame_interval_ms(), 1000 / effective as u64);
+        }
+    }
+
+    const W: usize = 64;
+    const H: usize = 48;
+
+    /// A `W` x `H` BGRA frame in one flat grey
+    fn flat(value: u8) -> Vec<u8> {
+        vec![value; W * H * 4]
+    }
+
+    #[test]
+    fn changed_pct_counts_sampled_pixels_over_the_threshold() {
+        let prev = flat(100);
+        assert_eq!(changed_pct(&prev, &prev, W, H), 0.0);
+        // Below the noise threshold everywhere
+        assert_eq!(changed_pct(&prev, &flat(100 + PIXEL_THRESHOLD), W, H), 0.0);
+        assert_eq!(changed_pct(&prev, &flat(200), W, H), 100.0);
+
+        // Left half of the screen changes: half of the sampled columns
+        let mut cur = prev.clone();
+        for y in 0..H {
+            for x in 0..W / 2 {
+                cur[(y * W + x) * 4] = 255;
+            }
+        }
+        assert_eq!(changed_pct(&prev, &cur, W, H), 50.0);
+
+        // A change between sample points goes unnoticed
+        let mut cur = prev.clone();
+        cur[(W + 1) * 4] = 255;
+        assert_eq!(changed_pct(&prev, &cur, W, H), 0.0);
+
+        // A resolution change counts as a full change
+        assert_eq!(changed_pct(&prev, &prev[..W * 4], W, H), 100.0);
+    }
+
+    #[test]
+    fn detector_holds_for_quiet_secs_after_activity() {
+        let config = MotionConfig {
+            quiet_secs: 10,
+            threshold_pct: 1.0,
+            input_is_activity: true,
+            ..MotionConfig::default()
+        };
+        let mut detector = MotionDetector::new(config, 500);
+        assert!(!detector.update(1_000, 0.5, 500));
+        assert!(detector.update(2_000, 1.0, 500));
+        assert!(detector.update(11_999, 0.0, 500));
+        assert!(!detector.update(12_000, 0.0, 500));
+
+        // New input keeps it going on an unchanged screen
+        assert!(detector.update(20_000, 0.0, 19_000));
+        assert!(detector.update(29_999, 0.0, 19_000));
+        assert!(!detector.update(30_000, 0.0, 19_000));
+    }
+
+    #[test]
+    fn input_is_ignored_unless_enabled() {
+        let config = MotionConfig {
+            input_is_activity: false,
+            ..MotionConfig::default()
+        };
+        let mut detector = MotionDetector::new(config, 0);
+        assert!(!detector.update(1_000, 0.0, 900));
+        assert!(detector.update(2_000, 50.0, 1_900));
+    }
+
+    #[test]
+    fn pre_roll_keeps_the_latest_frames_in_order() {
+        let mut pre_roll = PreRoll::new(3);
+        for n in 0..5u8 {
+            pre_roll.push(&[n]);
+        }
+        assert_eq!(pre_roll.len(), 3);
+        assert_eq!(pre_roll.drain().collect::<Vec<_>>(), [vec![2], vec![3], vec![4]]);
+        assert!(pre_roll.is_empty());
+
+        let mut none = PreRoll::new(0);
+        none.push(&[1]);
+        assert!(none.is_empty());
+    }
+
+}
@@ -0,0 +1,316 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::identity::{self, DeviceSigner};
+use crate::redaction::{RedactionPolicy, RedactionRule};
+use crate::schedule::ScheduleConfig;
+
+/// Captures are only taken inside these hours, in local time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkHours {
+    /// 1 = Monday ... 7 = Sunday
+    pub days: Vec<u32>,
+    /// `HH:MM`
+    pub start: String,
+    /// `HH:MM`; earlier than `start` for a shift that runs past midnight
+    pub end: String,
+}
+
+impl WorkHours {
+    fn parse(time: &str) -> Result<NaiveTime, String> {
+        NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("Invalid work hours time '{}', expected HH:MM", time))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.days.iter().any(|d| !(1..=7).contains(d)) {
+            return Err("Work days must be 1 (Monday) to 7 (Sunday)".into());
+        }
+        Self::parse(&self.start)?;
+        Self::parse(&self.end)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let (Ok(start), Ok(end)) = (Self::parse(&self.start), Self::parse(&self.end)) else {
+            return true;
+        };
+        let time = at.time();
+        let day = at.weekday().number_from_monday();
+        if start <= end {
+            self.days.contains(&day) && time >= start && time < end
+        } else {
+            // An overnight shift belongs to the day it started on
+            let previous = if day == 1 { 7 } else { day - 1 };
+            (self.days.contains(&day) && time >= start) || (self.days.contains(&previous) && time < end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoPolicy {
+    /// Record continuously while the policy is in effect
+    pub enabled: bool,
+    pub segment_secs: u64,
+    /// Encoding profile name; the default profile when unset
+    pub profile: Option<String>,
+}
+
+impl Default for VideoPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            segment_secs: 300,
+            profile: None,
+        }
+    }
+}
+
+/// Monitoring settings pushed by the collection server. Idle thresholds
+/// live in `schedule` (`idle_threshold_secs`, `skip_while_idle`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitoringPolicy {
+    /// Only a policy with a higher version replaces the current one, so an
+    /// older signed document cannot be replayed to roll settings back
+    pub version: u64,
+    pub issued_ms: u64,
+    /// When set, the policy is only valid on this device
+    pub device_id: Option<String>,
+    pub screenshots_enabled: bool,
+    pub schedule: ScheduleConfig,
+    pub video: VideoPolicy,
+    pub redaction_rules: Vec<RedactionRule>,
+    /// No restriction when unset
+    pub work_hours: Option<WorkHours>,
+    /// How often the agent asks the server for a newer policy
+    pub refresh_secs: u64,
+}
+
+impl Default for MonitoringPolicy {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            issued_ms: 0,
+            device_id: None,
+            screenshots_enabled: true,
+            schedule: ScheduleConfig::default(),
+            video: VideoPolicy::default(),
+            redaction_rules: Vec::new(),
+            work_hours: None,
+            refresh_secs: DEFAULT_REFRESH_SECS,
+        }
+    }
+}
+
+pub const DEFAULT_REFRESH_SECS: u64 = 300;
+pub const MIN_REFRESH_SECS: u64 = 30;
+
+impl MonitoringPolicy {
+    fn validate(&self) -> Result<(), String> {
+        if self.schedule.interval_secs == 0 {
+            return Err("Screenshot interval must be at least 1 second".into());
+        }
+        if self.video.enabled && self.video.segment_secs == 0 {
+            return Err("Video segments must be at least 1 second".into());
+        }
+        if let Some(hours) = &self.work_hours {
+            hours.validate()?;
+        }
+        RedactionPolicy::new(self.redaction_rules.clone()).map(|_| ())
+    }
+
+    pub fn refresh(&self) -> Duration {
+        Duration::from_secs(self.refresh_secs.max(MIN_REFRESH_SECS))
+    }
+}
+
+/// The document as the server sent it. `policy` is the JSON text the
+/// signature covers; it is kept verbatim so the signature can be checked
+/// again whenever the cached copy is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPolicy {
+    pub policy: String,
+    /// Ed25519 signature over `policy`, hex
+    pub signature: String,
+}
+
+impl SignedPolicy {
+    /// Check the signature against the key received at enrollment and parse
+    /// the policy. Nothing in an unverified document is looked at.
+    pub fn verify(&self, server_key: &str, device_id: &str) -> Result<MonitoringPolicy, String> {
+        identity::verify_server_signature(server_key, self.policy.as_bytes(), &self.signature)
+            .map_err(|e| format!("Policy rejected: {}", e))?;
+        let policy: MonitoringPolicy =
+            serde_json::from_str(&self.policy).map_err(|e| format!("Policy rejected: invalid document: {}", e))?;
+        if policy.device_id.as_deref().is_some_and(|id| id != device_id) {
+            return Err("Policy rejected: issued for another device".into());
+        }
+        policy.validate().map_err(|e| format!("Policy rejected: {}", e))?;
+        Ok(policy)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicySource {
+    Server,
+    /// Loaded from the local copy at startup, e.g. while offline
+    Cache,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PolicyStatus {
+    /// `None` while the agent runs on local settings
+    pub policy: Option<MonitoringPolicy>,
+    pub source: Option<PolicySource>,
+    pub applied_ms: Option<u64>,
+    pub last_fetch_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl PolicyStatus {
+    pub fn version(&self) -> Option<u64> {
+        self.policy.as_ref().map(|p| p.version)
+    }
+
+    /// Whether `policy` may replace the one in effect: only a higher version
+    /// does, so an older signed document cannot roll settings back
+    pub fn accepts(&self, policy: &MonitoringPolicy) -> bool {
+        self.version().is_none_or(|v| policy.version > v)
+    }
+
+    pub fn work_hours(&self) -> Option<&WorkHours> {
+        self.policy.as_ref().and_then(|p| p.work_hours.as_ref())
+    }
+}
+
+pub fn load_cached(path: &Path) -> Result<Option<SignedPolicy>, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s)
+            .map(Some)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Only verified policies are cached; written atomically
+pub fn save_cached(path: &Path, signed: &SignedPolicy) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(signed).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+/// Ask the server for this device's policy; `None` when it has none
+pub fn fetch(server: &str, signer: &DeviceSigner, now_ms: u64) -> Result<Option<SignedPolicy>, String> {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(30)).build();
+    let mut req = agent.get(&format!("{}/policy", server.trim_end_matches('/')));
+    for (name, value) in signer.headers("GET", "/policy", &[], now_ms) {
+        req = req.set(name, &value);
+    }
+    match req.call() {
+        Ok(resp) => resp
+            .into_json()
+            .map(Some)
+            .map_err(|e| format!("Invalid policy reply: {}", e)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(ureq::Error::Status(code, resp)) => {
+            let msg = resp.into_string().unwrap_or_default();
+            Err(format!("Server returned {}: {}", code, msg.trim()))
+        }
+        Err(e) => Err(format!("Cannot reach {}: {}", server, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::hex;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const DEVICE: &str = "device-1";
+
+    fn signed(key: &SigningKey, policy: &str) -> SignedPolicy {
+        SignedPolicy {
+            policy: policy.to_string(),
+            signature: hex(&key.sign(policy.as_bytes()).to_bytes()),
+        }
+    }
+
+    fn public(key: &SigningKey) -> String {
+        hex(key.verifying_key().as_bytes())
+    }
+
+    #[test]
+    fn verify_accepts_a_signed_policy_for_this_device() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let doc = signed(&key, r#"{"version":3,"device_id":"device-1","screenshots_enabled":false}"#);
+        let policy = doc.verify(&public(&key), DEVICE).unwrap();
+        assert_eq!(policy.version, 3);
+        assert!(!policy.screenshots_enabled);
+        // A policy without a device id is valid for every device
+        assert!(signed(&key, r#"{"version":3}"#).verify(&public(&key), "other").is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_tampering_wrong_keys_and_other_devices() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let doc = signed(&key, r#"{"version":3,"screenshots_enabled":false}"#);
+
+        let tampered = SignedPolicy {
+            policy: doc.policy.replace("false", "true"),
+            ..doc.clone()
+        };
+        assert_eq!(tampered.verify(&public(&key), DEVICE).unwrap_err(), "Policy rejected: Bad signature");
+
+        let other = SigningKey::from_bytes(&[8; 32]);
+        assert_eq!(doc.verify(&public(&other), DEVICE).unwrap_err(), "Policy rejected: Bad signature");
+        assert_eq!(doc.verify("not hex", DEVICE).unwrap_err(), "Policy rejected: Invalid server key");
+
+        for signature in ["", "zz", &doc.signature[..64], &format!("{}00", doc.signature)] {
+            let malformed = SignedPolicy {
+                signature: signature.to_string(),
+                ..doc.clone()
+            };
+            assert_eq!(
+                malformed.verify(&public(&key), DEVICE).unwrap_err(),
+                "Policy rejected: Malformed signature",
+                "{:?}",
+                signature
+            );
+        }
+
+        let elsewhere = signed(&key, r#"{"version":3,"device_id":"device-2"}"#);
+        let err = elsewhere.verify(&public(&key), DEVICE).unwrap_err();
+        assert_eq!(err, "Policy rejected: issued for another device");
+
+        // Signed but unusable
+        let invalid = signed(&key, r#"{"version":3,"schedule":{"interval_secs":0}}"#);
+        assert!(invalid.verify(&public(&key), DEVICE).is_err());
+        let garbage = signed(&key, "not json");
+        assert!(garbage.verify(&public(&key), DEVICE).unwrap_err().contains("invalid document"));
+    }
+
+    #[test]
+    fn only_a_higher_version_replaces_the_policy_in_effect() {
+        let version = |v| MonitoringPolicy {
+            version: v,
+            ..Default::default()
+        };
+        let mut status = PolicyStatus::default();
+        assert!(status.accepts(&version(0)));
+
+        status.policy = Some(version(5));
+        assert!(!status.accepts(&version(4)));
+        assert!(!status.accepts(&version(5)));
+        assert!(status.accepts(&version(6)));
+    }
+}
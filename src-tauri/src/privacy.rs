@@ -0,0 +1,211 @@
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Longest single privacy pause
+pub const MAX_PAUSE_MINS: u64 = 60;
+/// Total privacy pause allowed per calendar day
+pub const DAILY_BUDGET_MINS: u64 = 60;
+const MINUTE_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ActivePause {
+    by: String,
+    started_ms: u64,
+    until_ms: u64,
+}
+
+/// Where a privacy pause stands, for the UI and the heartbeat
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PauseStatus {
+    pub active: bool,
+    pub paused_by: Option<String>,
+    pub until_ms: Option<u64>,
+    pub used_today_ms: u64,
+    pub remaining_today_ms: u64,
+}
+
+/// Employee-initiated pauses, limited to `DAILY_BUDGET_MINS` a day. A pause
+/// is charged in full when it starts and the unused part is given back when
+/// it is resumed early. Persisted, so restarting the agent neither ends a
+/// pause nor refills the budget.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivacyPause {
+    /// Local date the budget was last charged, as YYYY-MM-DD
+    day: Option<String>,
+    used_ms: u64,
+    active: Option<ActivePause>,
+}
+
+fn day_of(ms: u64) -> Option<String> {
+    Local.timestamp_millis_opt(ms as i64).single().map(|t| t.format("%Y-%m-%d").to_string())
+}
+
+impl PrivacyPause {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    /// Start a new day's budget and forget a pause that has run out
+    fn roll(&mut self, now_ms: u64) {
+        let today = day_of(now_ms);
+        if self.day != today {
+            self.day = today;
+            self.used_ms = 0;
+        }
+        if self.active.as_ref().is_some_and(|p| p.until_ms <= now_ms) {
+            self.active = None;
+        }
+    }
+
+    /// End the running pause, giving back what is left of it if it was
+    /// charged to today
+    fn end(&mut self, now_ms: u64) -> bool {
+        let Some(pause) = self.active.take() else { return false };
+        if day_of(pause.started_ms) == self.day {
+            self.used_ms = self.used_ms.saturating_sub(pause.until_ms.saturating_sub(now_ms));
+        }
+        true
+    }
+
+    /// Pause for `minutes`, replacing a running pause. Returns when it ends.
+    pub fn pause(&mut self, by: &str, minutes: u64, now_ms: u64) -> Result<u64, String> {
+        if minutes == 0 || minutes > MAX_PAUSE_MINS {
+            return Err(format!("Pause must be 1-{} minutes", MAX_PAUSE_MINS));
+        }
+        self.roll(now_ms);
+        let previous = self.clone();
+        self.end(now_ms);
+        let remaining = (DAILY_BUDGET_MINS * MINUTE_MS).saturating_sub(self.used_ms);
+        let wanted = minutes * MINUTE_MS;
+        if wanted > remaining {
+            *self = previous;
+            return Err(format!(
+                "Only {} of {} minutes of privacy pause left today",
+                remaining / MINUTE_MS,
+                DAILY_BUDGET_MINS
+            ));
+        }
+        self.used_ms += wanted;
+        let until_ms = now_ms + wanted;
+        self.active = Some(ActivePause {
+            by: by.to_string(),
+            started_ms: now_ms,
+            until_ms,
+        });
+        Ok(until_ms)
+    }
+
+    /// End a running pause early. False if none was running.
+    pub fn resume(&mut self, now_ms: u64) -> bool {
+        self.roll(now_ms);
+        self.end(now_ms)
+    }
+
+    pub fn is_active(&self, now_ms: u64) -> bool {
+        self.active.as_ref().is_some_and(|p| p.until_ms > now_ms)
+    }
+
+    pub fn status(&self, now_ms: u64) -> PauseStatus {
+        let used_today_ms = if self.day == day_of(now_ms) { self.used_ms } else { 0 };
+        let active = self.active.as_ref().filter(|p| p.until_ms > now_ms);
+        PauseStatus {
+            active: active.is_some(),
+            paused_by: active.map(|p| p.by.clone()),
+            until_ms: active.map(|p| p.until_ms),
+            used_today_ms,
+            remaining_today_ms: (DAILY_BUDGET_MINS * MINUTE_MS).saturating_sub(used_today_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local noon, so a few hours either way stay on the same day
+    fn noon() -> u64 {
+        let today = Local::now().date_naive().and_hms_opt(12, 0, 0).unwrap();
+        Local.from_local_datetime(&today).earliest().unwrap().timestamp_millis() as u64
+    }
+
+    #[test]
+    fn pauses_draw_on_the_daily_budget() {
+        let t0 = noon();
+        let mut pause = PrivacyPause::default();
+        assert!(pause.pause("alice", 0, t0).is_err());
+        assert!(pause.pause("alice", MAX_PAUSE_MINS + 1, t0).is_err());
+
+        assert_eq!(pause.pause("alice", 40, t0).unwrap(), t0 + 40 * MINUTE_MS);
+        assert!(pause.is_active(t0 + 39 * MINUTE_MS));
+        let status = pause.status(t0);
+        assert_eq!(status.paused_by.as_deref(), Some("alice"));
+        assert_eq!(status.remaining_today_ms, 20 * MINUTE_MS);
+
+        // Expired pauses stay charged
+        assert!(!pause.is_active(t0 + 40 * MINUTE_MS));
+        let later = t0 + 60 * MINUTE_MS;
+        let err = pause.pause("alice", 30, later).unwrap_err();
+        assert!(err.contains("Only 20 of 60 minutes"), "{}", err);
+        assert!(!pause.is_active(later));
+        pause.pause("alice", 20, later).unwrap();
+        assert!(pause.pause("alice", 1, later + 20 * MINUTE_MS).is_err());
+        assert_eq!(pause.status(later).remaining_today_ms, 0);
+    }
+
+    #[test]
+    fn resuming_early_gives_back_the_rest() {
+        let t0 = noon();
+        let mut pause = PrivacyPause::default();
+        pause.pause("alice", 30, t0).unwrap();
+        assert!(pause.resume(t0 + 10 * MINUTE_MS));
+        assert!(!pause.resume(t0 + 10 * MINUTE_MS));
+        assert!(!pause.is_active(t0 + 11 * MINUTE_MS));
+        assert_eq!(pause.status(t0 + 11 * MINUTE_MS).used_today_ms, 10 * MINUTE_MS);
+
+        // Replacing a running pause refunds it first
+        pause.pause("alice", 30, t0 + 20 * MINUTE_MS).unwrap();
+        pause.pause("alice", 45, t0 + 25 * MINUTE_MS).unwrap();
+        // 10 + 5 of the replaced pause + 45
+        assert_eq!(pause.status(t0 + 25 * MINUTE_MS).used_today_ms, 60 * MINUTE_MS);
+
+        // A refused replacement leaves the running pause alone
+        assert!(pause.pause("alice", 60, t0 + 26 * MINUTE_MS).is_err());
+        assert_eq!(pause.status(t0 + 26 * MINUTE_MS).until_ms, Some(t0 + 70 * MINUTE_MS));
+        assert_eq!(pause.status(t0 + 26 * MINUTE_MS).used_today_ms, 60 * MINUTE_MS);
+    }
+
+    #[test]
+    fn budget_refills_the_next_day_and_survives_a_restart() {
+        let t0 = noon();
+        let mut pause = PrivacyPause::default();
+        pause.pause("alice", 60, t0).unwrap();
+
+        let path = std::env::temp_dir().join(format!("privacy_pause_{}.json", std::process::id()));
+        pause.save(&path).unwrap();
+        let restored = PrivacyPause::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(restored.is_active(t0 + MINUTE_MS));
+        assert_eq!(restored.status(t0).remaining_today_ms, 0);
+
+        let tomorrow = t0 + 24 * 60 * MINUTE_MS;
+        let mut pause = restored;
+        assert_eq!(pause.status(tomorrow).remaining_today_ms, DAILY_BUDGET_MINS * MINUTE_MS);
+        pause.pause("alice", 60, tomorrow).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::ffmpeg::MediaInfo;
+use crate::identity::RecordIdentity;
+
+/// Append-only index of finished video files, one JSON object per line
+pub const INDEX_FILE: &str = "recordings.jsonl";
+
+/// Held while the index is written, so a prune never drops a line being appended
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingEntry {
+    /// File name relative to the video directory
+    pub file: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub start: String,
+    pub end: String,
+    /// Filled from ffprobe once the file is closed
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub fps: Option<f64>,
+    #[serde(default)]
+    pub duration_secs: Option<f64>,
+    /// Device and user that recorded the clip
+    #[serde(default)]
+    pub identity: Option<RecordIdentity>,
+}
+
+impl RecordingEntry {
+    pub fn new(file: String, start_ms: u64, end_ms: u64) -> Self {
+        Self {
+            file,
+            start_ms,
+            end_ms,
+            start: rfc3339(start_ms),
+            end: rfc3339(end_ms),
+            width: None,
+            height: None,
+            fps: None,
+            duration_secs: None,
+            identity: None,
+        }
+    }
+
+    pub fn set_media(&mut self, info: MediaInfo) {
+        self.width = Some(info.width);
+        self.height = Some(info.height);
+        self.fps = Some(info.fps);
+        self.duration_secs = Some(info.duration_secs);
+    }
+}
+
+/// A moment in time resolved to a file and a position inside it
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipLocation {
+    pub path: String,
+    pub offset_secs: f64,
+    pub entry: RecordingEntry,
+}
+
+/// Find the recording covering `ts_ms`. Overlaps (e.g. a restart) resolve to
+/// the latest-starting file. The offset is clamped to the probed duration.
+pub fn locate(dir: &Path, entries: &[RecordingEntry], ts_ms: u64) -> Option<ClipLocation> {
+    let entry = entries
+        .iter()
+        .filter(|e| e.start_ms <= ts_ms && ts_ms < e.end_ms)
+        .max_by_key(|e| e.start_ms)?;
+    let mut offset_secs = (ts_ms - entry.start_ms) as f64 / 1000.0;
+    if let Some(duration) = entry.duration_secs {
+        offset_secs = offset_secs.min(duration);
+    }
+    Some(ClipLocation {
+        path: dir.join(&entry.file).to_string_lossy().to_string(),
+        offset_secs,
+        entry: entry.clone(),
+    })
+}
+
+fn rfc3339(ms: u64) -> String {
+    Local
+        .timestamp_millis_opt(ms as i64)
+        .single()
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default()
+}
+
+pub fn append(dir: &Path, entry: &RecordingEntry) -> Result<(), String> {
+    let json = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(INDEX_FILE))
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", json).map_err(|e| e.to_string())
+}
+
+/// Every indexed recording, oldest first. Unparseable lines are skipped.
+pub fn load(dir: &Path) -> Vec<RecordingEntry> {
+    let content = fs::read_to_string(dir.join(INDEX_FILE)).unwrap_or_default();
+    let mut entries: Vec<RecordingEntry> = content
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect();
+    entries.sort_by_key(|e| e.start_ms);
+    entries
+}
+
+/// Drop entries whose file no longer exists, e.g. after storage quotas
+/// removed it. Lines that do not parse are kept. Returns the entries dropped.
+pub fn prune_missing(dir: &Path) -> Result<usize, String> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let path = dir.join(INDEX_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut kept = String::with_capacity(content.len());
+    let mut dropped = 0;
+    for line in content.lines() {
+        match serde_json::from_str::<RecordingEntry>(line) {
+            Ok(entry) if !dir.join(&entry.file).exists() => dropped += 1,
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+    if dropped > 0 {
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp, kept).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    }
+    Ok(dropped)
+}
+
+/// Tails the CSV segment list written by ffmpeg's segment muxer
+/// (`file,start_secs,end_secs` per finished segment) and turns new lines
+/// into wall-clock index entries.
+pub struct SegmentListReader {
+    path: PathBuf,
+    consumed: usize,
+    recording_start_ms: u64,
+}
+
+impl SegmentListReader {
+    pub fn new(path: PathBuf, recording_start_ms: u64) -> Self {
+        Self {
+            path,
+            consumed: 0,
+            recording_start_ms,
+        }
+    }
+
+    /// Segments finished since the previous call
+    pub fn poll(&mut self) -> Vec<RecordingEntry> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(_) => return vec![],
+        };
+        // A line without its newline is still being written
+        let complete: Vec<&str> = content.split_inclusive('\n').filter(|l| l.ends_with('\n')).collect();
+        let fresh = complete
+            .iter()
+            .skip(self.consumed)
+            .filter_map(|l| parse_segment_line(l.trim_end(), self.recording_start_ms))
+            .collect();
+        self.consumed = complete.len();
+        fresh
+    }
+}
+
+fn parse_segment_line(line: &str, recording_start_ms: u64) -> Option<RecordingEntry> {
+    let mut parts = line.rsplitn(3, ',');
+    let end: f64 = parts.next()?.parse().ok()?;
+    let start: f64 = parts.next()?.parse().ok()?;
+    let file = Path::new(parts.next()?.trim_matches('"'))
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    Some(RecordingEntry::new(
+        file,
+        recording_start_ms + (start * 1000.0) as u64,
+        recording_start_ms + (end * 1000.0) as u64,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recording_index_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn segment_lines_map_to_wall_clock() {
+        let entry = parse_segment_line("/videos/segment_20240101_120000.mp4,10.000000,70.500000", 1_000_000).unwrap();
+        assert_eq!(entry.file, "segment_20240101_120000.mp4");
+        assert_eq!((entry.start_ms, entry.end_ms), (1_010_000, 1_070_500));
+
+        // Quoted names and commas inside the path
+        let entry = parse_segment_line("\"/rec,1/segment_a.mp4\",0.0,5.0", 0).unwrap();
+        assert_eq!(entry.file, "segment_a.mp4");
+
+        assert!(parse_segment_line("segment_a.mp4,abc,5.0", 0).is_none());
+        assert!(parse_segment_line("segment_a.mp4,5.0", 0).is_none());
+        assert!(parse_segment_line("", 0).is_none());
+    }
+
+    #[test]
+    fn segment_list_reader_returns_each_finished_line_once() {
+        let dir = temp_dir("segments");
+        let list = dir.join("segments.csv");
+        let mut reader = SegmentListReader::new(list.clone(), 5_000);
+        assert!(reader.poll().is_empty());
+
+        fs::write(&list, "segment_a.mp4,0.0,60.0\nsegment_b.mp4,60.0,12").unwrap();
+        let fresh = reader.poll();
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh[0].file, "segment_a.mp4");
+        assert_eq!((fresh[0].start_ms, fresh[0].end_ms), (5_000, 65_000));
+
+        // The partial line is picked up once ffmpeg finishes writing it
+        fs::write(&list, "segment_a.mp4,0.0,60.0\nsegment_b.mp4,60.0,120.0\n").unwrap();
+        let fresh = reader.poll();
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh[0].file, "segment_b.mp4");
+        assert!(reader.poll().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn locate_resolves_timestamps_to_a_file_and_offset() {
+        let dir = Path::new("/videos");
+        let mut first = RecordingEntry::new("capture_a.mp4".into(), 10_000, 70_000);
+        first.duration_secs = Some(59.5);
+        // A restart overlapping the end of the first clip
+        let second = RecordingEntry::new("capture_b.mp4".into(), 65_000, 125_000);
+        let entries = [first, second];
+
+        let hit = locate(dir, &entries, 40_000).unwrap();
+        assert_eq!(hit.entry.file, "capture_a.mp4");
+        assert_eq!(hit.path, dir.join("capture_a.mp4").to_string_lossy());
+        assert_eq!(hit.offset_secs, 30.0);
+
+        let hit = locate(dir, &entries, 66_000).unwrap();
+        assert_eq!(hit.entry.file, "capture_b.mp4");
+        assert_eq!(hit.offset_secs, 1.0);
+
+        // Clamped to the probed duration
+        let only_first = &entries[..1];
+        assert_eq!(locate(dir, only_first, 69_900).unwrap().offset_secs, 59.5);
+
+        // End is exclusive, and gaps resolve to nothing
+        assert!(locate(dir, &entries, 125_000).is_none());
+        assert!(locate(dir, &entries, 5_000).is_none());
+    }
+
+}
@@ -0,0 +1,419 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactAction {
+    /// Fill the window rectangle with black
+    Blackout,
+    /// Mosaic the window rectangle so text is unreadable
+    Blur,
+    /// Drop the whole screenshot while this window is in the foreground
+    SkipCapture,
+}
+
+/// A window matches when every condition that is set matches.
+/// A rule with neither `process` nor `title_regex` never matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionRule {
+    pub name: String,
+    /// Executable name, compared case-insensitively (e.g. `KeePass.exe`)
+    #[serde(default)]
+    pub process: Option<String>,
+    /// Regex tested against the window title
+    #[serde(default)]
+    pub title_regex: Option<String>,
+    pub action: RedactAction,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowInfo {
+    pub process_name: String,
+    pub title: String,
+    pub rect: Rect,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RedactionPlan {
+    /// Rule name that vetoed the capture
+    pub skip: Option<String>,
+    pub regions: Vec<(Rect, RedactAction, String)>,
+}
+
+const BLUR_BLOCK: usize = 16;
+
+pub struct RedactionPolicy {
+    rules: Vec<RedactionRule>,
+    title_patterns: Vec<Option<Regex>>,
+}
+
+impl RedactionPolicy {
+    pub fn new(rules: Vec<RedactionRule>) -> Result<Self, String> {
+        let title_patterns = rules
+            .iter()
+            .map(|r| {
+                r.title_regex
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|e| format!("Invalid title_regex in rule '{}': {}", r.name, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { rules, title_patterns })
+    }
+
+    pub fn rules(&self) -> &[RedactionRule] {
+        &self.rules
+    }
+
+    fn matching_rule(&self, window: &WindowInfo) -> Option<&RedactionRule> {
+        self.rules.iter().zip(&self.title_patterns).find_map(|(rule, pattern)| {
+            if rule.process.is_none() && pattern.is_none() {
+                return None;
+            }
+            let process_ok = rule
+                .process
+                .as_ref()
+                .is_none_or(|p| p.eq_ignore_ascii_case(&window.process_name));
+            let title_ok = pattern.as_ref().is_none_or(|re| re.is_match(&window.title));
+            (process_ok && title_ok).then_some(rule)
+        })
+    }
+
+    /// Decide what to do with a frame given the foreground window and every
+    /// visible top-level window. A `SkipCapture` window that is merely visible
+    /// in the background is blacked out rather than vetoing the capture.
+    pub fn plan(&self, foreground: Option<&WindowInfo>, windows: &[WindowInfo]) -> RedactionPlan {
+        let mut plan = RedactionPlan::default();
+
+        if let Some(rule) = foreground.and_then(|fg| self.matching_rule(fg)) {
+            if rule.action == RedactAction::SkipCapture {
+                plan.skip = Some(rule.name.clone());
+                return plan;
+            }
+        }
+
+        for window in windows {
+            if let Some(rule) = self.matching_rule(window) {
+                let action = match rule.action {
+                    RedactAction::SkipCapture => RedactAction::Blackout,
+                    other => other,
+                };
+                plan.regions.push((window.rect, action, rule.name.clone()));
+            }
+        }
+        plan
+    }
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self::new(default_rules()).expect("built-in redaction rules are valid")
+    }
+}
+
+pub fn default_rules() -> Vec<RedactionRule> {
+    let skip_process = |name: &str, exe: &str| RedactionRule {
+        name: name.into(),
+        process: Some(exe.into()),
+        title_regex: None,
+        action: RedactAction::SkipCapture,
+    };
+    vec![
+        skip_process("KeePass", "KeePass.exe"),
+        skip_process("KeePassXC", "KeePassXC.exe"),
+        skip_process("1Password", "1Password.exe"),
+        skip_process("Bitwarden", "Bitwarden.exe"),
+        RedactionRule {
+            name: "Banking".into(),
+            process: None,
+            title_regex: Some(r"(?i)\b(net ?banking|online banking|bank)\b".into()),
+            action: RedactAction::Blur,
+        },
+        RedactionRule {
+            name: "HR portal".into(),
+            process: None,
+            title_regex: Some(r"(?i)\b(payroll|workday|successfactors|bamboohr)\b".into()),
+            action: RedactAction::Blackout,
+        },
+    ]
+}
+
+/// Rules from `path`, or the built-in set when the file does not exist yet
+pub fn load_rules(path: &Path) -> Result<Vec<RedactionRule>, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| e.to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(default_rules()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn save_rules(path: &Path, rules: &[RedactionRule]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(rules).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Clip a screen rectangle to a `width` x `height` frame anchored at (0, 0).
+/// Returns `(x0, y0, x1, y1)` with exclusive ends, or `None` if nothing is left.
+fn clip(rect: Rect, width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+    let x0 = rect.left.max(0) as usize;
+    let y0 = rect.top.max(0) as usize;
+    let x1 = (rect.right.max(0) as usize).min(width);
+    let y1 = (rect.bottom.max(0) as usize).min(height);
+    (x0 < x1 && y0 < y1).then_some((x0, y0, x1, y1))
+}
+
+/// Paint the plan's regions onto a tightly packed 4-bytes-per-pixel frame.
+/// Returns the names of the rules that touched at least one pixel.
+pub fn apply(frame: &mut [u8], width: usize, height: usize, plan: &RedactionPlan) -> Vec<String> {
+    let mut applied = Vec::new();
+    for (rect, action, rule) in &plan.regions {
+        let Some(area) = clip(*rect, width, height) else { continue };
+        match action {
+            RedactAction::Blur => pixelate(frame, width, area, BLUR_BLOCK),
+            _ => blackout(frame, width, area),
+        }
+        if !applied.contains(rule) {
+            applied.push(rule.clone());
+        }
+    }
+    applied
+}
+
+/// Like `apply`, for a video frame. A recording cannot drop a frame without
+/// breaking its timing, so a vetoed frame is blacked out entirely instead.
+pub fn apply_to_video(frame: &mut [u8], width: usize, height: usize, plan: &RedactionPlan) -> Vec<String> {
+    if let Some(rule) = &plan.skip {
+        blackout(frame, width, (0, 0, width, height));
+        return vec![rule.clone()];
+    }
+    apply(frame, width, height, plan)
+}
+
+fn blackout(frame: &mut [u8], width: usize, (x0, y0, x1, y1): (usize, usize, usize, usize)) {
+    for y in y0..y1 {
+        let row = &mut frame[(y * width + x0) * 4..(y * width + x1) * 4];
+        for px in row.chunks_exact_mut(4) {
+            px[0] = 0;
+            px[1] = 0;
+            px[2] = 0;
+        }
+    }
+}
+
+/// Replace each `block` x `block` tile with its average colour
+fn pixelate(frame: &mut [u8], width: usize, (x0, y0, x1, y1): (usize, usize, usize, usize), block: usize) {
+    for by in (y0..y1).step_by(block) {
+        for bx in (x0..x1).step_by(block) {
+            let (ey, ex) = ((by + block).min(y1), (bx + block).min(x1));
+            let mut sum = [0u64; 3];
+            for y in by..ey {
+                for x in bx..ex {
+                    let i = (y * width + x) * 4;
+                    for c in 0..3 {
+                        sum[c] += frame[i + c] as u64;
+                    }
+                }
+            }
+            let n = ((ey - by) * (ex - bx)) as u64;
+            let avg = sum.map(|s| (s / n) as u8);
+            for y in by..ey {
+                for x in bx..ex {
+                    let i = (y * width + x) * 4;
+                    frame[i..i + 3].copy_from_slice(&avg);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 64;
+    const H: usize = 48;
+
+    /// Every pixel gets a distinct colour so masking is visible anywhere
+    fn frame() -> Vec<u8> {
+        let mut f = vec![0u8; W * H * 4];
+        for y in 0..H {
+            for x in 0..W {
+                let i = (y * W + x) * 4;
+                f[i..i + 4].copy_from_slice(&[(x * 4) as u8, (y * 5) as u8, ((x + y) * 2 + 1) as u8, 255]);
+            }
+        }
+        f
+    }
+
+    fn pixel(f: &[u8], x: usize, y: usize) -> &[u8] {
+        &f[(y * W + x) * 4..(y * W + x) * 4 + 4]
+    }
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+        Rect { left, top, right, bottom }
+    }
+
+    fn window(process: &str, title: &str, rect: Rect) -> WindowInfo {
+        WindowInfo {
+            process_name: process.into(),
+            title: title.into(),
+            rect,
+        }
+    }
+
+    fn rule(name: &str, process: Option<&str>, title_regex: Option<&str>, action: RedactAction) -> RedactionRule {
+        RedactionRule {
+            name: name.into(),
+            process: process.map(Into::into),
+            title_regex: title_regex.map(Into::into),
+            action,
+        }
+    }
+
+    fn inside(x: usize, y: usize, (x0, y0, x1, y1): (usize, usize, usize, usize)) -> bool {
+        x >= x0 && x < x1 && y >= y0 && y < y1
+    }
+
+    /// Pixels outside `area` must be untouched
+    fn assert_outside_unchanged(before: &[u8], after: &[u8], area: (usize, usize, usize, usize)) {
+        for y in 0..H {
+            for x in 0..W {
+                if !inside(x, y, area) {
+                    assert_eq!(pixel(before, x, y), pixel(after, x, y), "pixel ({}, {}) changed", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blackout_masks_only_the_window() {
+        let policy = RedactionPolicy::new(vec![rule("Vault", Some("vault.exe"), None, RedactAction::Blackout)]).unwrap();
+        let windows = [
+            window("VAULT.EXE", "Secrets", rect(10, 8, 30, 20)),
+            window("notepad.exe", "notes", rect(0, 0, W as i32, H as i32)),
+        ];
+        let plan = policy.plan(Some(&windows[1]), &windows);
+        assert_eq!(plan.regions.len(), 1);
+
+        let before = frame();
+        let mut after = before.clone();
+        assert_eq!(apply(&mut after, W, H, &plan), vec!["Vault".to_string()]);
+        for y in 8..20 {
+            for x in 10..30 {
+                assert_eq!(pixel(&after, x, y), &[0, 0, 0, 255]);
+            }
+        }
+        assert_outside_unchanged(&before, &after, (10, 8, 30, 20));
+    }
+
+    #[test]
+    fn blur_pixelates_only_the_window() {
+        let policy = RedactionPolicy::new(vec![rule("Bank", None, Some("(?i)bank"), RedactAction::Blur)]).unwrap();
+        let windows = [window("chrome.exe", "My Bank - Chrome", rect(8, 4, 40, 36))];
+        let plan = policy.plan(None, &windows);
+
+        let before = frame();
+        let mut after = before.clone();
+        apply(&mut after, W, H, &plan);
+        // Each 16x16 tile from the region's corner is one flat colour
+        for (bx, by) in [(8, 4), (24, 4), (8, 20), (24, 20)] {
+            let first = pixel(&after, bx, by).to_vec();
+            for y in by..by + BLUR_BLOCK {
+                for x in bx..bx + BLUR_BLOCK {
+                    assert_eq!(pixel(&after, x, y), &first[..]);
+                }
+            }
+        }
+        assert_ne!(pixel(&after, 8, 4), pixel(&before, 8, 4));
+        assert_outside_unchanged(&before, &after, (8, 4, 40, 36));
+    }
+
+    #[test]
+    fn skip_capture_vetoes_in_front_and_blacks_out_behind() {
+        let policy = RedactionPolicy::new(vec![rule("KeePass", Some("KeePass.exe"), None, RedactAction::SkipCapture)]).unwrap();
+        let keepass = window("KeePass.exe", "Database", rect(0, 0, 20, 20));
+        let editor = window("code.exe", "main.rs", rect(20, 0, 64, 48));
+
+        let plan = policy.plan(Some(&keepass), &[keepass.clone(), editor.clone()]);
+        assert_eq!(plan.skip.as_deref(), Some("KeePass"));
+        assert!(plan.regions.is_empty());
+
+        let plan = policy.plan(Some(&editor), &[keepass, editor.clone()]);
+        assert!(plan.skip.is_none());
+        let before = frame();
+        let mut after = before.clone();
+        apply(&mut after, W, H, &plan);
+        assert_eq!(pixel(&after, 0, 0), &[0, 0, 0, 255]);
+        assert_eq!(pixel(&after, 19, 19), &[0, 0, 0, 255]);
+        assert_outside_unchanged(&before, &after, (0, 0, 20, 20));
+    }
+
+    #[test]
+    fn regions_are_clipped_at_the_frame_edge() {
+        let policy = RedactionPolicy::new(vec![
+            rule("Left", Some("left.exe"), None, RedactAction::Blackout),
+            rule("Right", Some("right.exe"), None, RedactAction::Blur),
+            rule("Offscreen", Some("gone.exe"), None, RedactAction::Blackout),
+        ])
+        .unwrap();
+        let windows = [
+            window("left.exe", "", rect(-30, -10, 12, 10)),
+            window("right.exe", "", rect(50, 30, 200, 100)),
+            window("gone.exe", "", rect(100, 100, 300, 300)),
+        ];
+        let plan = policy.plan(None, &windows);
+        assert_eq!(plan.regions.len(), 3);
+
+        let before = frame();
+        let mut after = before.clone();
+        let applied = apply(&mut after, W, H, &plan);
+        // A window entirely off the frame touches no pixels
+        assert_eq!(applied, vec!["Left".to_string(), "Right".to_string()]);
+        for y in 0..10 {
+            for x in 0..12 {
+                assert_eq!(pixel(&after, x, y), &[0, 0, 0, 255]);
+            }
+        }
+        assert_ne!(pixel(&after, W - 1, H - 1), pixel(&before, W - 1, H - 1));
+        for y in 0..H {
+            for x in 0..W {
+                if !inside(x, y, (0, 0, 12, 10)) && !inside(x, y, (50, 30, W, H)) {
+                    assert_eq!(pixel(&before, x, y), pixel(&after, x, y), "pixel ({}, {}) changed", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vetoed_video_frame_is_blacked_out_entirely() {
+        let policy = RedactionPolicy::new(vec![rule("Vault", Some("KeePass.exe"), None, RedactAction::SkipCapture)]).unwrap();
+        let vault = window("keepass.exe", "Database", rect(8, 8, 24, 24));
+        let plan = policy.plan(Some(&vault), std::slice::from_ref(&vault));
+
+        let mut f = frame();
+        assert_eq!(apply_to_video(&mut f, W, H, &plan), vec!["Vault".to_string()]);
+        assert!(f.chunks_exact(4).all(|px| px[..3] == [0, 0, 0]));
+    }
+
+    #[test]
+    fn rule_without_conditions_never_matches() {
+        let policy = RedactionPolicy::new(vec![rule("Empty", None, None, RedactAction::Blackout)]).unwrap();
+        let w = window("any.exe", "anything", rect(0, 0, 10, 10));
+        assert!(policy.plan(Some(&w), std::slice::from_ref(&w)).regions.is_empty());
+        assert!(RedactionPolicy::new(vec![rule("Bad", None, Some("("), RedactAction::Blur)]).is_err());
+    }
+}
@@ -0,0 +1,581 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::encoding::EncoderBackend;
+use crate::identity::{self, DeviceSigner};
+use crate::schedule::ScheduleConfig;
+
+/// How long the server may hold a poll open before answering with no commands
+pub const POLL_WAIT_SECS: u64 = 25;
+pub const DEFAULT_LOG_LINES: usize = 200;
+pub const MAX_LOG_LINES: usize = 2000;
+const MAX_ID_LEN: usize = 128;
+/// Longest a command may stay valid. Ids are remembered until their command
+/// expires, so this also bounds how many are kept.
+pub const MAX_COMMAND_LIFETIME_MS: u64 = 15 * 60_000;
+/// How far ahead of the agent's clock the server's may run
+const MAX_CLOCK_SKEW_MS: u64 = 5 * 60_000;
+
+/// What an administrator can ask the agent to do. Each maps onto the logic
+/// behind the local command of the same name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteAction {
+    StartCapture {
+        interval_secs: u64,
+        #[serde(default)]
+        schedule: Option<ScheduleConfig>,
+    },
+    StopCapture,
+    StartVideoCapture {
+        interval_secs: u64,
+        duration_secs: u64,
+        #[serde(default)]
+        profile: Option<String>,
+        #[serde(default)]
+        backend: Option<EncoderBackend>,
+    },
+    StopVideoCapture,
+    /// Take one screenshot now, outside the schedule
+    Screenshot,
+    /// The last lines of a local log
+    PullLogs {
+        #[serde(default)]
+        log: LogName,
+        #[serde(default)]
+        lines: Option<usize>,
+    },
+}
+
+impl RemoteAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RemoteAction::StartCapture { .. } => "start_capture",
+            RemoteAction::StopCapture => "stop_capture",
+            RemoteAction::StartVideoCapture { .. } => "start_video_capture",
+            RemoteAction::StopVideoCapture => "stop_video_capture",
+            RemoteAction::Screenshot => "screenshot",
+            RemoteAction::PullLogs { .. } => "pull_logs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogName {
+    #[default]
+    Activity,
+    AccessDenied,
+    Audit,
+}
+
+/// A command as issued by the server. `action` stays raw until the
+/// signature has been checked, so an unknown action can still be answered.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemoteCommand {
+    pub id: String,
+    pub device_id: String,
+    #[serde(default)]
+    pub issued_by: Option<String>,
+    pub issued_ms: u64,
+    /// Commands that reach the agent after this are refused
+    pub expires_ms: u64,
+    pub action: Value,
+}
+
+/// Ids end up in the ack and result URLs, so only characters that need no
+/// escaping in a path segment are accepted
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_LEN && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Commands are signed with the same server key as policies; `command` is
+/// the JSON text the signature covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedCommand {
+    pub command: String,
+    /// Ed25519 signature over `command`, hex
+    pub signature: String,
+}
+
+impl SignedCommand {
+    pub fn verify(&self, server_key: &str, device_id: &str, now_ms: u64) -> Result<RemoteCommand, String> {
+        identity::verify_server_signature(server_key, self.command.as_bytes(), &self.signature)
+            .map_err(|e| format!("Command rejected: {}", e))?;
+        let command: RemoteCommand =
+            serde_json::from_str(&self.command).map_err(|e| format!("Command rejected: invalid document: {}", e))?;
+        if !valid_id(&command.id) {
+            return Err("Command rejected: invalid id".into());
+        }
+        if command.device_id != device_id {
+            return Err("Command rejected: issued for another device".into());
+        }
+        if command.expires_ms <= now_ms {
+            return Err("Command rejected: expired".into());
+        }
+        // Otherwise a command could be replayed long after its id was forgotten
+        if command.issued_ms > now_ms + MAX_CLOCK_SKEW_MS
+            || command.expires_ms.saturating_sub(command.issued_ms) > MAX_COMMAND_LIFETIME_MS
+        {
+            return Err("Command rejected: validity window too long".into());
+        }
+        Ok(command)
+    }
+
+    /// The id of a command that failed verification, so the rejection can
+    /// still be reported and the server stops redelivering it. `None` for an
+    /// id that cannot be put in a URL.
+    pub fn unverified_id(&self) -> Option<String> {
+        let command = serde_json::from_str::<Value>(&self.command).ok()?;
+        command["id"].as_str().filter(|id| valid_id(id)).map(str::to_string)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandResult {
+    pub ok: bool,
+    pub output: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub finished_ms: u64,
+}
+
+impl CommandResult {
+    pub fn new(result: Result<Value, String>, finished_ms: u64) -> Self {
+        match result {
+            Ok(output) => Self {
+                ok: true,
+                output,
+                error: None,
+                finished_ms,
+            },
+            Err(e) => Self {
+                ok: false,
+                output: Value::Null,
+                error: Some(e),
+                finished_ms,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RemoteStatus {
+    /// The last poll reached the server
+    pub connected: bool,
+    pub last_poll_ms: Option<u64>,
+    pub executed: u64,
+    pub last_command: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeenId {
+    id: String,
+    expires_ms: u64,
+}
+
+/// Ids of commands that were run, kept until the command expires. Persisted,
+/// so a restart does not reopen the door to replays.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecentIds {
+    ids: VecDeque<SeenId>,
+}
+
+impl RecentIds {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    /// `false` if `command` was already seen. Ids of expired commands are
+    /// dropped, since those commands are refused anyway.
+    fn insert(&mut self, command: &RemoteCommand, now_ms: u64) -> bool {
+        self.ids.retain(|seen| seen.expires_ms > now_ms);
+        if self.ids.iter().any(|seen| seen.id == command.id) {
+            return false;
+        }
+        self.ids.push_back(SeenId {
+            id: command.id.clone(),
+            expires_ms: command.expires_ms,
+        });
+        true
+    }
+}
+
+/// What to do with one delivered command
+#[derive(Debug)]
+pub enum Received {
+    /// Verified and not seen before: acknowledge and run it
+    Run(RemoteCommand),
+    /// Failed verification. With an id the refusal is reported so the server
+    /// stops redelivering it; the id is not remembered, since anyone can put
+    /// it in an unsigned document.
+    Rejected { id: Option<String>, error: String },
+    /// Already handled; redeliveries and replays are dropped silently
+    Duplicate,
+}
+
+/// Verify `signed` for this device and drop ids seen before. Only verified
+/// commands change `recent`.
+pub fn receive(signed: &SignedCommand, server_key: &str, device_id: &str, now_ms: u64, recent: &mut RecentIds) -> Received {
+    match signed.verify(server_key, device_id, now_ms) {
+        Ok(command) if recent.insert(&command, now_ms) => Received::Run(command),
+        Ok(_) => Received::Duplicate,
+        Err(error) => Received::Rejected {
+            id: signed.unverified_id(),
+            error,
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct PollReply {
+    #[serde(default)]
+    commands: Vec<SignedCommand>,
+}
+
+/// Long-poll client for `GET /commands`. Every request carries the device
+/// signature, like uploads.
+pub struct CommandChannel {
+    agent: ureq::Agent,
+    server: String,
+    signer: DeviceSigner,
+}
+
+impl CommandChannel {
+    pub fn new(server: &str, signer: DeviceSigner) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            // Longer than the server may hold a poll open
+            .timeout_read(Duration::from_secs(POLL_WAIT_SECS + 30))
+            .timeout_write(Duration::from_secs(30))
+            .build();
+        Self {
+            agent,
+            server: server.trim().trim_end_matches('/').to_string(),
+            signer,
+        }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    fn send(&self, method: &str, path: &str, body: &[u8]) -> Result<ureq::Response, String> {
+        let mut req = self.agent.request(method, &format!("{}{}", self.server, path));
+        for (name, value) in self.signer.headers(method, path, body, crate::current_ts_millis()) {
+            req = req.set(name, &value);
+        }
+        let result = if body.is_empty() {
+            req.call()
+        } else {
+            req.set("Content-Type", "application/json").send_bytes(body)
+        };
+        match result {
+            Ok(resp) => Ok(resp),
+            Err(ureq::Error::Status(code, resp)) => {
+                let msg = resp.into_string().unwrap_or_default();
+                Err(format!("Server returned {}: {}", code, msg.trim()))
+            }
+            Err(e) => Err(format!("Cannot reach {}: {}", self.server, e)),
+        }
+    }
+
+    /// Wait up to `wait_secs` for commands addressed to this device
+    pub fn poll(&self, wait_secs: u64) -> Result<Vec<SignedCommand>, String> {
+        let reply: PollReply = self
+            .send("GET", &format!("/commands?wait={}", wait_secs), &[])?
+            .into_json()
+            .map_err(|e| format!("Invalid command reply: {}", e))?;
+        Ok(reply.commands)
+    }
+
+    /// Tell the server the command arrived, before it runs
+    pub fn ack(&self, id: &str) -> Result<(), String> {
+        self.send("POST", &format!("/commands/{}/ack", id), &[]).map(|_| ())
+    }
+
+    pub fn report(&self, id: &str, result: &CommandResult) -> Result<(), String> {
+        let body = serde_json::to_vec(result).map_err(|e| e.to_string())?;
+        self.send("POST", &format!("/commands/{}/result", id), &body).map(|_| ())
+    }
+}
+
+/// The last `lines` lines of a text file. Only the end of the file is read,
+/// so this stays cheap on a large activity log.
+pub fn tail_lines(path: &Path, lines: usize) -> Result<Vec<String>, String> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let window = (lines as u64 * 2048).min(len);
+    file.seek(SeekFrom::Start(len - window)).map_err(|e| e.to_string())?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&buf);
+    let mut all: Vec<&str> = text.lines().collect();
+    // The first line is probably cut off unless the whole file was read
+    if window < len && !all.is_empty() {
+        all.remove(0);
+    }
+    let start = all.len().saturating_sub(lines);
+    Ok(all[start..].iter().map(|l| l.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::current_ts_millis;
+    use crate::identity::DeviceIdentity;
+    use crate::test_support::{enrolled_device, hex, server_key, start_server, temp_dir, test_options};
+    use ed25519_dalek::Signer;
+    use serde_json::json;
+    use std::fs;
+
+    /// `POST /admin/commands`; returns the command id
+    fn queue(url: &str, body: Value) -> String {
+        let reply: Value = ureq::post(&format!("{}/admin/commands", url)).send_json(body).unwrap().into_json().unwrap();
+        reply["id"].as_str().unwrap().to_string()
+    }
+
+    fn policy_key(identity: &DeviceIdentity) -> String {
+        identity.enrollment.as_ref().and_then(|e| e.policy_key.clone()).unwrap()
+    }
+
+    fn poll_one(channel: &CommandChannel) -> SignedCommand {
+        let mut commands = channel.poll(0).unwrap();
+        assert_eq!(commands.len(), 1);
+        commands.remove(0)
+    }
+
+    #[test]
+    fn replayed_commands_run_once() {
+        let dir = temp_dir("remote-replay");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir, &url);
+        let channel = CommandChannel::new(&url, device.signer().unwrap());
+        let id = queue(&url, json!({ "action": { "type": "screenshot" } }));
+        let mut recent = RecentIds::default();
+
+        let first = poll_one(&channel);
+        match receive(&first, &policy_key(&device), &device.device_id, current_ts_millis(), &mut recent) {
+            Received::Run(command) => assert_eq!(command.id, id),
+            other => panic!("expected the command to run, got {:?}", other),
+        }
+        // Not acknowledged, so the server sends it again
+        let again = poll_one(&channel);
+        assert!(matches!(
+            receive(&again, &policy_key(&device), &device.device_id, current_ts_millis(), &mut recent),
+            Received::Duplicate
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expired_commands_are_rejected() {
+        let dir = temp_dir("remote-expired");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir, &url);
+        let channel = CommandChannel::new(&url, device.signer().unwrap());
+        let id = queue(&url, json!({ "action": { "type": "screenshot" }, "ttl_secs": 0 }));
+
+        let signed = poll_one(&channel);
+        match receive(&signed, &policy_key(&device), &device.device_id, current_ts_millis(), &mut RecentIds::default()) {
+            Received::Rejected { id: rejected, error } => {
+                assert_eq!(rejected.as_deref(), Some(id.as_str()));
+                assert!(error.contains("expired"), "{}", error);
+            }
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commands_for_another_device_are_rejected() {
+        let dir = temp_dir("remote-device");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir.join("a"), &url);
+        let other = enrolled_device(&dir.join("b"), &url);
+        queue(&url, json!({ "device_id": other.device_id, "action": { "type": "stop_capture" } }));
+
+        // The server only hands it to the device it was issued for...
+        assert!(CommandChannel::new(&url, device.signer().unwrap()).poll(0).unwrap().is_empty());
+        // ...and a copy delivered to this device anyway is refused
+        let signed = poll_one(&CommandChannel::new(&url, other.signer().unwrap()));
+        match receive(&signed, &policy_key(&device), &device.device_id, current_ts_millis(), &mut RecentIds::default()) {
+            Received::Rejected { error, .. } => assert!(error.contains("another device"), "{}", error),
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn tampered_commands_are_rejected() {
+        let dir = temp_dir("remote-tampered");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir, &url);
+        queue(&url, json!({ "action": { "type": "stop_capture" } }));
+
+        let mut signed = poll_one(&CommandChannel::new(&url, device.signer().unwrap()));
+        signed.command = signed.command.replace("stop_capture", "screenshot");
+        match receive(&signed, &policy_key(&device), &device.device_id, current_ts_millis(), &mut RecentIds::default()) {
+            Received::Rejected { error, .. } => assert!(error.contains("Bad signature"), "{}", error),
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// A command signed with the mock server's key
+    fn signed_command(data: &std::path::Path, command: Value) -> SignedCommand {
+        let command = command.to_string();
+        let signature = hex(&server_key(data).sign(command.as_bytes()).to_bytes());
+        SignedCommand { command, signature }
+    }
+
+    #[test]
+    fn rejected_commands_do_not_block_their_id() {
+        let dir = temp_dir("remote-rejected-id");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir, &url);
+        let channel = CommandChannel::new(&url, device.signer().unwrap());
+        let id = queue(&url, json!({ "action": { "type": "screenshot" } }));
+        let genuine = poll_one(&channel);
+        let mut recent = RecentIds::default();
+
+        // A forgery carrying the id of a real command is refused every time
+        // and leaves the real one runnable
+        let forged = SignedCommand {
+            command: genuine.command.replace("screenshot", "stop_capture"),
+            ..genuine.clone()
+        };
+        for _ in 0..2 {
+            match receive(&forged, &policy_key(&device), &device.device_id, current_ts_millis(), &mut recent) {
+                Received::Rejected { id: rejected, .. } => assert_eq!(rejected.as_deref(), Some(id.as_str())),
+                other => panic!("expected a rejection, got {:?}", other),
+            }
+        }
+        assert!(recent.ids.is_empty());
+        assert!(matches!(
+            receive(&genuine, &policy_key(&device), &device.device_id, current_ts_millis(), &mut recent),
+            Received::Run(_)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commands_valid_for_too_long_are_refused() {
+        let dir = temp_dir("remote-lifetime");
+        let data = dir.join("server");
+        let url = start_server(&data, test_options());
+        let device = enrolled_device(&dir, &url);
+        let now = current_ts_millis();
+        let command = |id: &str, issued_ms: u64, expires_ms: u64| {
+            signed_command(
+                &data,
+                json!({
+                    "id": id,
+                    "device_id": device.device_id,
+                    "issued_ms": issued_ms,
+                    "expires_ms": expires_ms,
+                    "action": { "type": "screenshot" },
+                }),
+            )
+        };
+        let mut recent = RecentIds::default();
+        let mut check = |signed: SignedCommand| receive(&signed, &policy_key(&device), &device.device_id, now, &mut recent);
+
+        assert!(matches!(check(command("a", now, now + MAX_COMMAND_LIFETIME_MS)), Received::Run(_)));
+        match check(command("b", now, now + MAX_COMMAND_LIFETIME_MS + 1)) {
+            Received::Rejected { error, .. } => assert!(error.contains("validity window"), "{}", error),
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        // Issued far in the future to stretch the window
+        let later = now + 24 * 3600 * 1000;
+        assert!(matches!(check(command("c", later, later + 60_000)), Received::Rejected { .. }));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn handled_ids_survive_a_restart_until_they_expire() {
+        let dir = temp_dir("remote-ids-saved");
+        let url = start_server(&dir.join("server"), test_options());
+        let device = enrolled_device(&dir, &url);
+        let channel = CommandChannel::new(&url, device.signer().unwrap());
+        queue(&url, json!({ "action": { "type": "screenshot" }, "ttl_secs": 60 }));
+        let signed = poll_one(&channel);
+        let now = current_ts_millis();
+
+        let mut recent = RecentIds::default();
+        assert!(matches!(receive(&signed, &policy_key(&device), &device.device_id, now, &mut recent), Received::Run(_)));
+        let path = dir.join("remote_ids.json");
+        recent.save(&path).unwrap();
+
+        let mut restored = RecentIds::load(&path).unwrap();
+        assert!(matches!(
+            receive(&signed, &policy_key(&device), &device.device_id, now, &mut restored),
+            Received::Duplicate
+        ));
+        // Forgotten once the command has expired
+        let other = signed_command(
+            &dir.join("server"),
+            json!({
+                "id": "later",
+                "device_id": device.device_id,
+                "issued_ms": now + 61_000,
+                "expires_ms": now + 120_000,
+                "action": { "type": "screenshot" },
+            }),
+        );
+        receive(&other, &policy_key(&device), &device.device_id, now + 61_000, &mut restored);
+        let kept: Vec<_> = restored.ids.iter().map(|seen| seen.id.as_str()).collect();
+        assert_eq!(kept, ["later"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ids_that_do_not_fit_a_url_path_are_refused() {
+        let dir = temp_dir("remote-id");
+        let data = dir.join("server");
+        let url = start_server(&data, test_options());
+        let device = enrolled_device(&dir, &url);
+        let now = current_ts_millis();
+        let signed = signed_command(
+            &data,
+            json!({
+                "id": "../../policy",
+                "device_id": device.device_id,
+                "issued_ms": now,
+                "expires_ms": now + 60_000,
+                "action": { "type": "screenshot" },
+            }),
+        );
+
+        match receive(&signed, &policy_key(&device), &device.device_id, now, &mut RecentIds::default()) {
+            // No id to report the refusal under either
+            Received::Rejected { id: None, error } => assert!(error.contains("invalid id"), "{}", error),
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
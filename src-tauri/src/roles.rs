@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// The monitored user: sees status and may pause briefly for privacy
+    #[default]
+    Employee,
+    /// Runs and reviews monitoring
+    Manager,
+    /// Also manages users, capture policy and history
+    Admin,
+}
+
+/// What a command needs; each role holds every permission of the roles below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ViewStatus,
+    PauseForPrivacy,
+    ControlCapture,
+    ViewActivity,
+    DeleteHistory,
+    ManagePolicy,
+    ManageUsers,
+    ViewAudit,
+}
+
+impl Permission {
+    pub fn min_role(self) -> Role {
+        match self {
+            Permission::ViewStatus | Permission::PauseForPrivacy => Role::Employee,
+            Permission::ControlCapture | Permission::ViewActivity => Role::Manager,
+            Permission::DeleteHistory
+            | Permission::ManagePolicy
+            | Permission::ManageUsers
+            | Permission::ViewAudit => Role::Admin,
+        }
+    }
+}
+
+impl Role {
+    pub fn allows(self, permission: Permission) -> bool {
+        self >= permission.min_role()
+    }
+}
+
+#[derive(Serialize)]
+struct DeniedAttempt<'a> {
+    timestamp: String,
+    command: &'a str,
+    /// `None` when the caller had no valid session
+    username: Option<&'a str>,
+    role: Option<Role>,
+    permission: Permission,
+    reason: &'a str,
+}
+
+/// Append a refused command to the denial log (JSON lines)
+pub fn log_denied(
+    path: &Path,
+    command: &str,
+    username: Option<&str>,
+    role: Option<Role>,
+    permission: Permission,
+    reason: &str,
+) {
+    eprintln!(
+        "⛔ Denied {} for {} ({})",
+        command,
+        username.unwrap_or("<no session>"),
+        reason
+    );
+    let entry = DeniedAttempt {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        command,
+        username,
+        role,
+        permission,
+        reason,
+    };
+    let written = serde_json::to_string(&entry)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| e.to_string())?;
+            writeln!(file, "{}", json).map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        eprintln!("Failed to record denied attempt: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn permission_table() {
+        use Permission::*;
+        // (permission, employee, manager, admin)
+        let table = [
+            (ViewStatus, true, true, true),
+            (PauseForPrivacy, true, true, true),
+            (ControlCapture, false, true, true),
+            (ViewActivity, false, true, true),
+            (DeleteHistory, false, false, true),
+            (ManagePolicy, false, false, true),
+            (ManageUsers, false, false, true),
+            (ViewAudit, false, false, true),
+        ];
+        for (permission, employee, manager, admin) in table {
+            assert_eq!(Role::Employee.allows(permission), employee, "employee {:?}", permission);
+            assert_eq!(Role::Manager.allows(permission), manager, "manager {:?}", permission);
+            assert_eq!(Role::Admin.allows(permission), admin, "admin {:?}", permission);
+        }
+    }
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(Role::Employee < Role::Manager && Role::Manager < Role::Admin);
+        assert_eq!(Role::default(), Role::Employee);
+        let role: Role = serde_json::from_str("\"manager\"").unwrap();
+        assert_eq!(role, Role::Manager);
+    }
+
+    #[test]
+    fn denied_attempts_are_appended_as_json_lines() {
+        let dir = std::env::temp_dir().join(format!("roles_denied_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("logs").join("denied.jsonl");
+
+        log_denied(&path, "clear_history", Some("bob"), Some(Role::Employee), Permission::DeleteHistory, "insufficient role");
+        log_denied(&path, "set_policy", None, None, Permission::ManagePolicy, "Not logged in");
+
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["command"], "clear_history");
+        assert_eq!(lines[0]["username"], "bob");
+        assert_eq!(lines[0]["role"], "employee");
+        assert_eq!(lines[0]["permission"], "delete_history");
+        assert!(lines[1]["username"].is_null() && lines[1]["role"].is_null());
+        assert_eq!(lines[1]["reason"], "Not logged in");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
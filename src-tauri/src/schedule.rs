@@ -0,0 +1,236 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Why a screenshot was taken
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureTrigger {
+    /// Regular (optionally jittered) interval
+    Scheduled,
+    /// Foreground application changed
+    AppChange,
+    /// First input after an idle period
+    IdleReturn,
+    /// Requested by an administrator through the command channel
+    OnDemand,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    pub interval_secs: u64,
+    /// Each scheduled capture lands uniformly within `interval_secs ± jitter_secs`
+    pub jitter_secs: u64,
+    pub on_app_change: bool,
+    pub on_idle_return: bool,
+    pub skip_while_idle: bool,
+    pub idle_threshold_secs: u64,
+    /// Minimum spacing between event-triggered captures
+    pub min_gap_secs: u64,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            jitter_secs: 0,
+            on_app_change: false,
+            on_idle_return: false,
+            skip_while_idle: false,
+            idle_threshold_secs: 15,
+            min_gap_secs: 5,
+        }
+    }
+}
+
+pub struct Scheduler {
+    config: ScheduleConfig,
+    next_due: Instant,
+    last_capture: Option<Instant>,
+    last_app: Option<String>,
+    was_idle: bool,
+    pending: Option<CaptureTrigger>,
+    rng: StdRng,
+}
+
+impl Scheduler {
+    /// The first scheduled capture is due immediately
+    pub fn new(config: ScheduleConfig, now: Instant) -> Self {
+        Self::with_rng(config, now, StdRng::from_entropy())
+    }
+
+    /// Like `new`, drawing the jitter from `rng`
+    pub fn with_rng(config: ScheduleConfig, now: Instant, rng: StdRng) -> Self {
+        Self {
+            config,
+            next_due: now,
+            last_capture: None,
+            last_app: None,
+            was_idle: false,
+            pending: None,
+            rng,
+        }
+    }
+
+    pub fn config(&self) -> &ScheduleConfig {
+        &self.config
+    }
+
+    /// Feed the current foreground app and idle state; returns the trigger
+    /// if a capture should happen now.
+    pub fn poll(&mut self, now: Instant, foreground_app: Option<&str>, idle: bool) -> Option<CaptureTrigger> {
+        if let (Some(prev), Some(cur)) = (&self.last_app, foreground_app) {
+            if prev != cur && self.config.on_app_change && self.pending.is_none() {
+                self.pending = Some(CaptureTrigger::AppChange);
+            }
+        }
+        if foreground_app.is_some() {
+            self.last_app = foreground_app.map(str::to_string);
+        }
+        if self.was_idle && !idle && self.config.on_idle_return {
+            // Returning from idle outranks an app switch made on the way back
+            self.pending = Some(CaptureTrigger::IdleReturn);
+        }
+        self.was_idle = idle;
+
+        if idle && self.config.skip_while_idle {
+            return None;
+        }
+
+        // Event triggers wait out `min_gap_secs` instead of being dropped
+        let gap_ok = self
+            .last_capture
+            .is_none_or(|t| now.duration_since(t) >= Duration::from_secs(self.config.min_gap_secs));
+        if gap_ok {
+            if let Some(trigger) = self.pending {
+                return Some(trigger);
+            }
+        }
+        if now >= self.next_due {
+            return Some(CaptureTrigger::Scheduled);
+        }
+        None
+    }
+
+    /// Record a capture (or a deliberately skipped one) and pick the next
+    /// scheduled time.
+    pub fn reschedule(&mut self, now: Instant) {
+        self.last_capture = Some(now);
+        self.pending = None;
+        self.next_due = now + self.next_delay();
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let interval = self.config.interval_secs.max(1) * 1000;
+        let jitter = (self.config.jitter_secs * 1000).min(interval - 1000);
+        let millis = if jitter == 0 {
+            interval
+        } else {
+            self.rng.gen_range(interval - jitter..=interval + jitter)
+        };
+        Duration::from_millis(millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ScheduleConfig {
+        ScheduleConfig {
+            interval_secs: 60,
+            jitter_secs: 0,
+            on_app_change: true,
+            on_idle_return: true,
+            skip_while_idle: false,
+            idle_threshold_secs: 15,
+            min_gap_secs: 5,
+        }
+    }
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn scheduled_captures_follow_the_interval() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(config(), t0);
+        assert_eq!(s.poll(t0, Some("code.exe"), false), Some(CaptureTrigger::Scheduled));
+        s.reschedule(t0);
+
+        assert_eq!(s.poll(t0 + secs(59), Some("code.exe"), false), None);
+        assert_eq!(s.poll(t0 + secs(60), Some("code.exe"), false), Some(CaptureTrigger::Scheduled));
+    }
+
+    #[test]
+    fn app_change_waits_out_the_min_gap() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(config(), t0);
+        s.poll(t0, Some("code.exe"), false);
+        s.reschedule(t0);
+
+        // Switched 2s after the last capture: held, not dropped
+        assert_eq!(s.poll(t0 + secs(2), Some("chrome.exe"), false), None);
+        assert_eq!(s.poll(t0 + secs(4), Some("chrome.exe"), false), None);
+        assert_eq!(s.poll(t0 + secs(5), Some("chrome.exe"), false), Some(CaptureTrigger::AppChange));
+        s.reschedule(t0 + secs(5));
+        assert_eq!(s.poll(t0 + secs(20), Some("chrome.exe"), false), None);
+    }
+
+    #[test]
+    fn app_change_is_ignored_unless_enabled() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(ScheduleConfig { on_app_change: false, ..config() }, t0);
+        s.poll(t0, Some("code.exe"), false);
+        s.reschedule(t0);
+        assert_eq!(s.poll(t0 + secs(10), Some("chrome.exe"), false), None);
+        // An unknown foreground app does not count as a switch
+        let mut s = Scheduler::new(config(), t0);
+        s.poll(t0, Some("code.exe"), false);
+        s.reschedule(t0);
+        assert_eq!(s.poll(t0 + secs(10), None, false), None);
+        assert_eq!(s.poll(t0 + secs(11), Some("code.exe"), false), None);
+    }
+
+    #[test]
+    fn idle_return_outranks_app_change() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(config(), t0);
+        s.poll(t0, Some("code.exe"), false);
+        s.reschedule(t0);
+
+        assert_eq!(s.poll(t0 + secs(10), Some("code.exe"), true), None);
+        assert_eq!(s.poll(t0 + secs(30), Some("chrome.exe"), false), Some(CaptureTrigger::IdleReturn));
+    }
+
+    #[test]
+    fn skip_while_idle_holds_scheduled_captures() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(ScheduleConfig { skip_while_idle: true, ..config() }, t0);
+        s.poll(t0, Some("code.exe"), false);
+        s.reschedule(t0);
+
+        assert_eq!(s.poll(t0 + secs(120), Some("code.exe"), true), None);
+        assert_eq!(s.poll(t0 + secs(121), Some("code.exe"), false), Some(CaptureTrigger::IdleReturn));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds_and_follows_the_rng() {
+        let t0 = Instant::now();
+        let jittered = ScheduleConfig { jitter_secs: 10, ..config() };
+        let mut a = Scheduler::with_rng(jittered.clone(), t0, StdRng::seed_from_u64(7));
+        let mut b = Scheduler::with_rng(jittered, t0, StdRng::seed_from_u64(7));
+
+        let delays: Vec<Duration> = (0..50).map(|_| a.next_delay()).collect();
+        assert!(delays.iter().all(|d| (secs(50)..=secs(70)).contains(d)));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+        assert!(delays.iter().all(|d| *d == b.next_delay()));
+
+        // Jitter never pushes the delay to zero
+        let mut wide = Scheduler::with_rng(ScheduleConfig { interval_secs: 5, jitter_secs: 30, ..config() }, t0, StdRng::seed_from_u64(1));
+        assert!((0..50).all(|_| wide.next_delay() >= secs(1)));
+    }
+}
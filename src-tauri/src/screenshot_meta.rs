@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::identity::RecordIdentity;
+use crate::input::Metrics;
+use crate::schedule::CaptureTrigger;
+
+/// Foreground window at the moment of capture
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ForegroundWindow {
+    pub app_name: String,
+    pub process_name: String,
+    pub window_title: String,
+    pub pid: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DisplayInfo {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct IdleState {
+    pub idle: bool,
+    pub idle_ms: u64,
+    pub threshold_secs: u64,
+}
+
+/// Sidecar record written next to every `screenshot_<millis>.png`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ScreenshotMeta {
+    pub file: String,
+    pub timestamp: String,
+    pub timestamp_ms: i64,
+    pub foreground: Option<ForegroundWindow>,
+    pub display: DisplayInfo,
+    pub idle: IdleState,
+    /// What caused this capture
+    #[serde(default)]
+    pub trigger: Option<CaptureTrigger>,
+    /// Input counters accumulated since the previous capture
+    pub input_since_last: Metrics,
+    pub sha256: String,
+    /// Redaction rules that blacked out or blurred part of this capture
+    #[serde(default)]
+    pub redactions: Vec<String>,
+    /// Re-encoded at reduced resolution by the storage manager
+    #[serde(default)]
+    pub downsampled: bool,
+    /// Device and user that took the capture
+    #[serde(default)]
+    pub identity: Option<RecordIdentity>,
+}
+
+/// `screenshot_123.png` -> `screenshot_123.json`
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    image_path.with_extension("json")
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let bytes = fs::read(path)?;
+    Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+pub fn write_sidecar(image_path: &Path, meta: &ScreenshotMeta) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(meta).map_err(|e| e.to_string())?;
+    fs::write(sidecar_path(image_path), json).map_err(|e| e.to_string())
+}
+
+/// Load sidecars from `dir`, newest first, optionally keeping only captures
+/// whose foreground app or process name contains `app` (case-insensitive).
+pub fn list_sidecars(dir: &Path, app: Option<&str>, limit: usize) -> Vec<ScreenshotMeta> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return vec![],
+    };
+    let needle = app.map(|a| a.to_lowercase());
+
+    let mut metas: Vec<ScreenshotMeta> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|p| fs::read_to_string(&p).ok())
+        .filter_map(|s| serde_json::from_str::<ScreenshotMeta>(&s).ok())
+        .filter(|m| match (&needle, &m.foreground) {
+            (None, _) => true,
+            (Some(n), Some(fg)) => {
+                fg.app_name.to_lowercase().contains(n.as_str())
+                    || fg.process_name.to_lowercase().contains(n.as_str())
+            }
+            (Some(_), None) => false,
+        })
+        .collect();
+
+    metas.sort_by_key(|m| std::cmp::Reverse(m.timestamp_ms));
+    metas.truncate(limit);
+    metas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(file: &str, timestamp_ms: i64, app: &str) -> ScreenshotMeta {
+        ScreenshotMeta {
+            file: file.into(),
+            timestamp: "2026-01-02T03:04:05.678+01:00".into(),
+            timestamp_ms,
+            foreground: Some(ForegroundWindow {
+                app_name: app.into(),
+                process_name: format!("{}.exe", app),
+                window_title: "Inbox".into(),
+                pid: 42,
+            }),
+            display: DisplayInfo {
+                name: ":0".into(),
+                width: 1920,
+                height: 1080,
+            },
+            idle: IdleState {
+                idle: true,
+                idle_ms: 90_000,
+                threshold_secs: 60,
+            },
+            trigger: Some(CaptureTrigger::AppChange),
+            input_since_last: Metrics {
+                kpm: 12,
+                char_count: 3,
+                ..Metrics::default()
+            },
+            sha256: "ab".repeat(32),
+            redactions: vec!["password managers".into()],
+            downsampled: false,
+            identity: Some(RecordIdentity {
+                device_id: "device-1".into(),
+                user: "DOMAIN\\alice".into(),
+                employee: Some("E-1001".into()),
+            }),
+        }
+    }
+
+    #[test]
+    fn sidecars_round_trip() {
+        let dir = std::env::temp_dir().join(format!("screenshot_meta_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let older = meta("screenshot_1000.png", 1000, "Outlook");
+        let newer = meta("screenshot_2000.png", 2000, "Chrome");
+        for m in [&older, &newer] {
+            let image = dir.join(&m.file);
+            fs::write(&image, b"png").unwrap();
+            write_sidecar(&image, m).unwrap();
+        }
+        assert!(dir.join("screenshot_1000.json").exists());
+
+        let all = list_sidecars(&dir, None, 10);
+        assert_eq!(all.len(), 2);
+        // Newest first, every field as written
+        assert_eq!(serde_json::to_value(&all[0]).unwrap(), serde_json::to_value(&newer).unwrap());
+        assert_eq!(serde_json::to_value(&all[1]).unwrap(), serde_json::to_value(&older).unwrap());
+
+        let outlook = list_sidecars(&dir, Some("outlook"), 10);
+        assert_eq!(outlook.len(), 1);
+        assert_eq!(outlook[0].file, "screenshot_1000.png");
+        assert_eq!(list_sidecars(&dir, None, 1).len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
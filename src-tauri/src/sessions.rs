@@ -0,0 +1,165 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::roles::Role;
+
+/// Sessions end after this long without an interactive command
+pub const IDLE_TIMEOUT_MS: u64 = 15 * 60 * 1000;
+/// Hard cap regardless of activity
+pub const MAX_LIFETIME_MS: u64 = 12 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    #[serde(skip)]
+    pub token: String,
+    pub username: String,
+    pub role: Role,
+    /// Only `change_password` and `logout` are allowed until this is cleared
+    pub must_change_password: bool,
+    pub created_ms: u64,
+    pub last_seen_ms: u64,
+    pub expires_ms: u64,
+}
+
+/// Opaque bearer tokens for logged-in users, kept in memory only so a
+/// restart logs everyone out
+#[derive(Debug, Default)]
+pub struct SessionManager {
+    sessions: HashMap<String, Session>,
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl SessionManager {
+    pub fn issue(&mut self, username: &str, role: Role, must_change_password: bool, now_ms: u64) -> Session {
+        self.purge(now_ms);
+        let session = Session {
+            token: new_token(),
+            username: username.to_string(),
+            role,
+            must_change_password,
+            created_ms: now_ms,
+            last_seen_ms: now_ms,
+            expires_ms: now_ms + MAX_LIFETIME_MS,
+        };
+        self.sessions.insert(session.token.clone(), session.clone());
+        session
+    }
+
+    /// Look up a live session. `touch` counts the call as user activity;
+    /// background polling passes false so an unattended window still times out.
+    pub fn validate(&mut self, token: &str, now_ms: u64, touch: bool) -> Result<Session, String> {
+        let session = self.sessions.get_mut(token).ok_or("Not logged in")?;
+        if now_ms >= session.expires_ms || now_ms.saturating_sub(session.last_seen_ms) >= IDLE_TIMEOUT_MS {
+            self.sessions.remove(token);
+            return Err("Session expired, please log in again".into());
+        }
+        if touch {
+            session.last_seen_ms = now_ms;
+        }
+        Ok(session.clone())
+    }
+
+    /// Who holds `token`, for attribution only; does not count as activity
+    pub fn username(&self, token: &str, now_ms: u64) -> Option<String> {
+        self.sessions
+            .get(token)
+            .filter(|s| now_ms < s.expires_ms && now_ms.saturating_sub(s.last_seen_ms) < IDLE_TIMEOUT_MS)
+            .map(|s| s.username.clone())
+    }
+
+    pub fn revoke(&mut self, token: &str) -> bool {
+        self.sessions.remove(token).is_some()
+    }
+
+    /// End every session of `username`, e.g. after a password reset
+    pub fn revoke_user(&mut self, username: &str) {
+        self.sessions.retain(|_, s| !s.username.eq_ignore_ascii_case(username));
+    }
+
+    pub fn password_changed(&mut self, token: &str) {
+        if let Some(s) = self.sessions.get_mut(token) {
+            s.must_change_password = false;
+        }
+    }
+
+    fn purge(&mut self, now_ms: u64) {
+        self.sessions
+            .retain(|_, s| now_ms < s.expires_ms && now_ms.saturating_sub(s.last_seen_ms) < IDLE_TIMEOUT_MS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: u64 = 1_700_000_000_000;
+    const MINUTE: u64 = 60 * 1000;
+
+    #[test]
+    fn idle_sessions_expire_after_fifteen_minutes() {
+        let mut sessions = SessionManager::default();
+        let token = sessions.issue("alice", Role::Admin, false, T0).token;
+        assert_eq!(token.len(), 64);
+
+        assert!(sessions.validate(&token, T0 + 14 * MINUTE, true).is_ok());
+        // Touched at 14 minutes, so alive until 29
+        assert!(sessions.validate(&token, T0 + 28 * MINUTE, true).is_ok());
+        assert_eq!(sessions.username(&token, T0 + 42 * MINUTE).as_deref(), Some("alice"));
+        assert!(sessions.username(&token, T0 + 43 * MINUTE).is_none());
+
+        let err = sessions.validate(&token, T0 + 43 * MINUTE, true).unwrap_err();
+        assert!(err.contains("expired"));
+        // Gone for good, even if the clock went backwards
+        assert_eq!(sessions.validate(&token, T0 + 29 * MINUTE, true).unwrap_err(), "Not logged in");
+    }
+
+    #[test]
+    fn sessions_end_after_twelve_hours_despite_activity() {
+        let mut sessions = SessionManager::default();
+        let token = sessions.issue("alice", Role::Admin, false, T0).token;
+        let mut now = T0;
+        while now + 10 * MINUTE < T0 + MAX_LIFETIME_MS {
+            now += 10 * MINUTE;
+            assert!(sessions.validate(&token, now, true).is_ok());
+        }
+        assert!(sessions.validate(&token, T0 + MAX_LIFETIME_MS - 1, true).is_ok());
+        assert!(sessions.validate(&token, T0 + MAX_LIFETIME_MS, true).is_err());
+    }
+
+    #[test]
+    fn polling_does_not_extend_a_session() {
+        let mut sessions = SessionManager::default();
+        let token = sessions.issue("alice", Role::Admin, false, T0).token;
+        for minute in 1..15 {
+            let session = sessions.validate(&token, T0 + minute * MINUTE, false).unwrap();
+            assert_eq!(session.last_seen_ms, T0);
+        }
+        assert!(sessions.validate(&token, T0 + 15 * MINUTE, false).is_err());
+    }
+
+    #[test]
+    fn revoking_ends_sessions() {
+        let mut sessions = SessionManager::default();
+        let a = sessions.issue("alice", Role::Admin, true, T0).token;
+        let b = sessions.issue("Alice", Role::Admin, false, T0).token;
+        let c = sessions.issue("bob", Role::Employee, false, T0).token;
+        assert_ne!(a, b);
+
+        assert!(sessions.validate(&a, T0, true).unwrap().must_change_password);
+        sessions.password_changed(&a);
+        assert!(!sessions.validate(&a, T0, true).unwrap().must_change_password);
+
+        assert!(sessions.revoke(&c));
+        assert!(!sessions.revoke(&c));
+        sessions.revoke_user("ALICE");
+        assert!(sessions.validate(&a, T0, true).is_err());
+        assert!(sessions.validate(&b, T0, true).is_err());
+    }
+}
@@ -0,0 +1,730 @@
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::recording_index;
+use crate::screenshot_meta::{self, ScreenshotMeta};
+use crate::upload;
+
+const MB: u64 = 1024 * 1024;
+/// Files touched this recently may still be open for writing and are never removed
+const RECENT_GRACE: Duration = Duration::from_secs(120);
+const DOWNSAMPLE_JPEG_QUALITY: u8 = 70;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Screenshots,
+    Videos,
+    Logs,
+    /// Upload queue items, spooled copies and dead letters
+    UploadQueue,
+    /// Counted but never deleted; the audit trail must stay complete
+    Audit,
+}
+
+/// Limits in MiB; `None` disables a quota
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub screenshots_mb: Option<u64>,
+    pub videos_mb: Option<u64>,
+    pub logs_mb: Option<u64>,
+    /// Spooled copies and dead letters; pending items themselves are kept
+    pub upload_queue_mb: Option<u64>,
+    pub total_mb: Option<u64>,
+    /// Over quota, re-encode old screenshots at half size before deleting any
+    pub downsample_screenshots: bool,
+    /// Screenshots newer than this stay at full resolution
+    pub full_res_hours: u64,
+    /// Delete the oldest artifacts until at least this much disk is free
+    pub min_free_mb: u64,
+    /// Below this much free disk, captures pause until space is reclaimed
+    pub critical_free_mb: u64,
+    pub check_interval_secs: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            screenshots_mb: Some(5 * 1024),
+            videos_mb: Some(20 * 1024),
+            logs_mb: Some(512),
+            upload_queue_mb: Some(1024),
+            total_mb: Some(30 * 1024),
+            downsample_screenshots: true,
+            full_res_hours: 24,
+            min_free_mb: 2048,
+            critical_free_mb: 500,
+            check_interval_secs: 60,
+        }
+    }
+}
+
+impl QuotaConfig {
+    fn limit(&self, category: Category) -> Option<u64> {
+        match category {
+            Category::Screenshots => self.screenshots_mb,
+            Category::Videos => self.videos_mb,
+            Category::Logs => self.logs_mb,
+            Category::UploadQueue => self.upload_queue_mb,
+            Category::Audit => None,
+        }
+        .map(|mb| mb * MB)
+    }
+}
+
+pub fn load_config(path: &Path) -> Result<QuotaConfig, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(QuotaConfig::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn save_config(path: &Path, config: &QuotaConfig) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Where screenshots and recordings are written. Read at startup, so a
+/// change takes effect at the next start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureDirs {
+    pub screenshots: PathBuf,
+    pub videos: PathBuf,
+}
+
+impl Default for CaptureDirs {
+    fn default() -> Self {
+        // Windows installs keep captures on the data drive; elsewhere they
+        // sit next to the logs and config
+        if cfg!(windows) {
+            Self {
+                screenshots: PathBuf::from("D:\\SpectosoftCaptures\\Screenshots"),
+                videos: PathBuf::from("D:\\SpectosoftCaptures\\Videos"),
+            }
+        } else {
+            Self {
+                screenshots: PathBuf::from("captures/screenshots"),
+                videos: PathBuf::from("captures/videos"),
+            }
+        }
+    }
+}
+
+pub fn load_capture_dirs(path: &Path) -> Result<CaptureDirs, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CaptureDirs::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Directories holding each category's files
+#[derive(Debug, Clone)]
+pub struct StorageRoots {
+    pub screenshots: PathBuf,
+    pub videos: PathBuf,
+    pub logs: PathBuf,
+    pub upload_queue: PathBuf,
+    pub audit: PathBuf,
+}
+
+/// One deletable unit: a capture plus the files that only make sense with it
+/// (screenshot sidecar, video thumbnails)
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub category: Category,
+    pub path: PathBuf,
+    pub related: Vec<PathBuf>,
+    pub bytes: u64,
+    pub modified_ms: u64,
+    /// False for files in use, e.g. the live activity log
+    pub removable: bool,
+}
+
+impl Artifact {
+    fn remove(&self) -> std::io::Result<()> {
+        for p in &self.related {
+            let _ = if p.is_dir() { fs::remove_dir_all(p) } else { fs::remove_file(p) };
+        }
+        fs::remove_file(&self.path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryUsage {
+    pub category: Category,
+    pub bytes: u64,
+    pub files: usize,
+    pub quota_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct StorageStatus {
+    pub usage: Vec<CategoryUsage>,
+    pub total_bytes: u64,
+    pub free_bytes: Option<u64>,
+    /// Captures are held back because free space is below `critical_free_mb`
+    pub paused: bool,
+    pub warning: Option<String>,
+    pub last_check_ms: u64,
+    /// Totals since startup
+    pub deleted_files: u64,
+    pub downsampled_files: u64,
+    pub reclaimed_bytes: u64,
+}
+
+fn modified_ms(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| match e.metadata() {
+                    Ok(m) if m.is_dir() => dir_size(&e.path()),
+                    Ok(m) => m.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn is_recent(modified_ms: u64) -> bool {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+    now.saturating_sub(modified_ms) < RECENT_GRACE.as_millis() as u64
+}
+
+/// Regular files directly inside `dir`
+fn files_in(dir: &Path) -> Vec<(PathBuf, fs::Metadata)> {
+    let Ok(entries) = fs::read_dir(dir) else { return vec![] };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| Some((e.path(), e.metadata().ok()?)))
+        .filter(|(_, m)| m.is_file())
+        .collect()
+}
+
+/// Every artifact of `category`, oldest first
+pub fn scan(category: Category, roots: &StorageRoots) -> Vec<Artifact> {
+    let mut artifacts = match category {
+        Category::Screenshots => scan_captures(category, &roots.screenshots),
+        Category::Videos => scan_captures(category, &roots.videos),
+        Category::Logs => scan_captures(category, &roots.logs),
+        Category::UploadQueue => {
+            // Pending items are the queue's own state. A spooled copy or a
+            // dead letter may go; the upload worker drops an item whose
+            // file vanished.
+            let queue = &roots.upload_queue;
+            let pending = files_in(queue).into_iter().map(|(path, meta)| (path, meta, false));
+            let spooled = files_in(&queue.join(upload::DATA_DIR))
+                .into_iter()
+                .chain(files_in(&queue.join(upload::FAILED_DIR)))
+                .map(|(path, meta)| (path, meta, true));
+            pending
+                .chain(spooled)
+                .map(|(path, meta, deletable)| plain_artifact(category, path, &meta, deletable))
+                .collect()
+        }
+        // See `AuditLog`: rotated files are kept for good
+        Category::Audit => files_in(&roots.audit)
+            .into_iter()
+            .map(|(path, meta)| plain_artifact(category, path, &meta, false))
+            .collect(),
+    };
+    artifacts.sort_by_key(|a| a.modified_ms);
+    artifacts
+}
+
+fn plain_artifact(category: Category, path: PathBuf, meta: &fs::Metadata, deletable: bool) -> Artifact {
+    let modified = modified_ms(meta);
+    Artifact {
+        category,
+        path,
+        related: vec![],
+        bytes: meta.len(),
+        modified_ms: modified,
+        removable: deletable && !is_recent(modified),
+    }
+}
+
+/// Screenshots, videos or logs in `dir`, each with the files that go with it
+fn scan_captures(category: Category, dir: &Path) -> Vec<Artifact> {
+    files_in(dir)
+        .into_iter()
+        .filter_map(|(path, meta)| {
+            let ext = path.extension()?.to_string_lossy().to_lowercase();
+            let related: Vec<PathBuf> = match (category, ext.as_str()) {
+                (Category::Screenshots, "png" | "jpg") => vec![screenshot_meta::sidecar_path(&path)],
+                (Category::Videos, "mp4" | "avi") => {
+                    vec![dir.join(crate::thumbnails::THUMB_DIR).join(path.file_stem()?)]
+                }
+                (Category::Logs, "log" | "jsonl") => vec![],
+                _ => return None,
+            };
+            let related: Vec<PathBuf> = related.into_iter().filter(|p| p.exists()).collect();
+            let related_bytes: u64 = related
+                .iter()
+                .map(|p| if p.is_dir() { dir_size(p) } else { fs::metadata(p).map_or(0, |m| m.len()) })
+                .sum();
+            let modified = modified_ms(&meta);
+            // The live log is appended to forever; the listener rotates it
+            let live_log = category == Category::Logs && path.file_name()? == "activity.log";
+            Some(Artifact {
+                category,
+                bytes: meta.len() + related_bytes,
+                modified_ms: modified,
+                removable: !live_log && !is_recent(modified),
+                path,
+                related,
+            })
+        })
+        .collect()
+}
+
+/// Halve a screenshot's resolution and store it as JPEG, keeping its sidecar
+/// in step. Returns the bytes saved.
+fn downsample_screenshot(artifact: &mut Artifact) -> Result<u64, String> {
+    let sidecar = screenshot_meta::sidecar_path(&artifact.path);
+    let mut meta: ScreenshotMeta = serde_json::from_str(&fs::read_to_string(&sidecar).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+    let img = image::open(&artifact.path).map_err(|e| e.to_string())?;
+    let small = img.resize(img.width() / 2, img.height() / 2, FilterType::Triangle).to_rgb8();
+
+    let jpg = artifact.path.with_extension("jpg");
+    let mut out = fs::File::create(&jpg).map_err(|e| e.to_string())?;
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, DOWNSAMPLE_JPEG_QUALITY)
+        .encode_image(&small)
+        .map_err(|e| e.to_string())?;
+    drop(out);
+
+    meta.file = jpg.file_name().unwrap_or_default().to_string_lossy().to_string();
+    meta.sha256 = screenshot_meta::sha256_file(&jpg).map_err(|e| e.to_string())?;
+    meta.downsampled = true;
+    screenshot_meta::write_sidecar(&jpg, &meta)?;
+    // Keep the capture's age, so it is still the oldest when pruning and not
+    // mistaken for a file in use
+    let modified = UNIX_EPOCH + Duration::from_millis(artifact.modified_ms);
+    for path in [&jpg, &sidecar] {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|f| f.set_modified(modified))
+            .map_err(|e| e.to_string())?;
+    }
+    fs::remove_file(&artifact.path).map_err(|e| e.to_string())?;
+
+    let before = artifact.bytes;
+    artifact.path = jpg;
+    artifact.bytes = fs::metadata(&artifact.path).map_or(0, |m| m.len())
+        + fs::metadata(&sidecar).map_or(0, |m| m.len());
+    Ok(before.saturating_sub(artifact.bytes))
+}
+
+fn usage_of(artifacts: &[Artifact]) -> u64 {
+    artifacts.iter().map(|a| a.bytes).sum()
+}
+
+/// Delete removable artifacts oldest first until `used` drops to `target`.
+/// Returns the bytes freed.
+fn delete_oldest(artifacts: &mut Vec<Artifact>, mut used: u64, target: u64, status: &mut StorageStatus) -> u64 {
+    let mut freed = 0;
+    let mut i = 0;
+    while used > target && i < artifacts.len() {
+        if !artifacts[i].removable {
+            i += 1;
+            continue;
+        }
+        let victim = artifacts.remove(i);
+        match victim.remove() {
+            Ok(()) => {
+                println!("🧹 Removed {} ({} bytes)", victim.path.display(), victim.bytes);
+                used = used.saturating_sub(victim.bytes);
+                freed += victim.bytes;
+                status.deleted_files += 1;
+            }
+            Err(e) => eprintln!("Failed to remove {}: {}", victim.path.display(), e),
+        }
+    }
+    status.reclaimed_bytes += freed;
+    freed
+}
+
+/// One pass of quota enforcement over all categories, then the free-space
+/// check. `free_space` is re-queried after reclaiming.
+pub fn enforce(
+    config: &QuotaConfig,
+    roots: &StorageRoots,
+    free_space: impl Fn() -> Option<u64>,
+    status: &mut StorageStatus,
+) {
+    let categories = [
+        Category::Screenshots,
+        Category::Videos,
+        Category::Logs,
+        Category::UploadQueue,
+        Category::Audit,
+    ];
+    let mut all: Vec<Vec<Artifact>> = categories.iter().map(|c| scan(*c, roots)).collect();
+    let deleted_before = status.deleted_files;
+
+    for (category, artifacts) in categories.iter().zip(all.iter_mut()) {
+        let Some(limit) = config.limit(*category) else { continue };
+        let mut used = usage_of(artifacts);
+        if used <= limit {
+            continue;
+        }
+        if *category == Category::Screenshots && config.downsample_screenshots {
+            let cutoff = SystemTime::now()
+                .checked_sub(Duration::from_secs(config.full_res_hours * 3600))
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_millis() as u64);
+            for artifact in artifacts.iter_mut() {
+                if used <= limit || artifact.modified_ms > cutoff {
+                    break;
+                }
+                if !artifact.removable || artifact.path.extension().is_some_and(|e| e == "jpg") {
+                    continue;
+                }
+                match downsample_screenshot(artifact) {
+                    Ok(saved) => {
+                        used = used.saturating_sub(saved);
+                        status.downsampled_files += 1;
+                        status.reclaimed_bytes += saved;
+                    }
+                    Err(e) => eprintln!("Failed to downsample {}: {}", artifact.path.display(), e),
+                }
+            }
+        }
+        delete_oldest(artifacts, used, limit, status);
+    }
+
+    // Total quota and low disk both take from whatever is oldest overall
+    let mut merged: Vec<Artifact> = all.into_iter().flatten().collect();
+    merged.sort_by_key(|a| a.modified_ms);
+    if let Some(total) = config.total_mb.map(|mb| mb * MB) {
+        let used = usage_of(&merged);
+        delete_oldest(&mut merged, used, total, status);
+    }
+    if let Some(free) = free_space() {
+        let wanted = config.min_free_mb * MB;
+        if free < wanted {
+            let used = usage_of(&merged);
+            delete_oldest(&mut merged, used, used.saturating_sub(wanted - free), status);
+        }
+    }
+
+    // Removed videos must not be offered by `locate_recording` any more
+    if status.deleted_files > deleted_before {
+        match recording_index::prune_missing(&roots.videos) {
+            Ok(0) => {}
+            Ok(n) => println!("🧹 Dropped {} removed recordings from the index", n),
+            Err(e) => eprintln!("Failed to prune recording index: {}", e),
+        }
+    }
+
+    status.usage = categories
+        .iter()
+        .map(|c| {
+            let of: Vec<&Artifact> = merged.iter().filter(|a| a.category == *c).collect();
+            CategoryUsage {
+                category: *c,
+                bytes: of.iter().map(|a| a.bytes).sum(),
+                files: of.len(),
+                quota_bytes: config.limit(*c),
+            }
+        })
+        .collect();
+    status.total_bytes = usage_of(&merged);
+    status.free_bytes = free_space();
+    status.paused = status.free_bytes.is_some_and(|f| f < config.critical_free_mb * MB);
+    status.warning = status.paused.then(|| {
+        format!(
+            "Disk almost full ({} MB free, {} MB required); captures are paused",
+            status.free_bytes.unwrap_or(0) / MB,
+            config.critical_free_mb
+        )
+    });
+}
+
+/// Bytes available to this user on the volume holding `path`
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use windows::core::HSTRING;
+    use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let mut available = 0u64;
+    let dir = HSTRING::from(path);
+    unsafe { GetDiskFreeSpaceExW(&dir, Some(&mut available as *mut u64), None, None) }.ok()?;
+    Some(available)
+}
+
+#[cfg(not(windows))]
+pub fn free_space(path: &Path) -> Option<u64> {
+    // POSIX `df -Pk` prints "Filesystem 1024-blocks Used Available ..."
+    let output = std::process::Command::new("df").arg("-Pk").arg(path).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let kb: u64 = text.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Metrics;
+    use crate::recording_index::{self, RecordingEntry};
+    use crate::screenshot_meta::{DisplayInfo, IdleState};
+    use image::{Rgb, RgbImage};
+
+    fn set_age(path: &Path, age_secs: u64) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_secs)).unwrap();
+    }
+
+    fn write_old(path: &Path, bytes: usize, age_secs: u64) {
+        fs::write(path, vec![0u8; bytes]).unwrap();
+        set_age(path, age_secs);
+    }
+
+    fn make_roots(name: &str) -> (PathBuf, StorageRoots) {
+        let root = std::env::temp_dir().join(format!("storage_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let roots = StorageRoots {
+            screenshots: root.join("shots"),
+            videos: root.join("videos"),
+            logs: root.join("logs"),
+            upload_queue: root.join("upload_queue"),
+            audit: root.join("audit"),
+        };
+        for dir in [&roots.screenshots, &roots.videos, &roots.logs, &roots.upload_queue, &roots.audit] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        (root, roots)
+    }
+
+    /// A noisy (barely compressible) 600x600 PNG of about 1 MiB plus its sidecar
+    fn write_screenshot(dir: &Path, name: &str, age_secs: u64) -> PathBuf {
+        let mut seed = name.bytes().fold(17u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+        let img = RgbImage::from_fn(600, 600, |_, _| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let [r, g, b, _] = seed.to_le_bytes();
+            Rgb([r, g, b])
+        });
+        let path = dir.join(name);
+        img.save(&path).unwrap();
+        let meta = ScreenshotMeta {
+            file: name.into(),
+            timestamp: String::new(),
+            timestamp_ms: 0,
+            foreground: None,
+            display: DisplayInfo {
+                name: "main".into(),
+                width: 600,
+                height: 600,
+            },
+            idle: IdleState {
+                idle: false,
+                idle_ms: 0,
+                threshold_secs: 60,
+            },
+            trigger: None,
+            input_since_last: Metrics::default(),
+            sha256: screenshot_meta::sha256_file(&path).unwrap(),
+            redactions: vec![],
+            downsampled: false,
+            identity: None,
+        };
+        screenshot_meta::write_sidecar(&path, &meta).unwrap();
+        set_age(&path, age_secs);
+        set_age(&screenshot_meta::sidecar_path(&path), age_secs);
+        path
+    }
+
+    /// Only quotas set by the test apply
+    fn no_quotas() -> QuotaConfig {
+        QuotaConfig {
+            screenshots_mb: None,
+            videos_mb: None,
+            logs_mb: None,
+            upload_queue_mb: None,
+            total_mb: None,
+            min_free_mb: 0,
+            critical_free_mb: 0,
+            ..QuotaConfig::default()
+        }
+    }
+
+    #[test]
+    fn oldest_screenshots_are_downsampled_first() {
+        let (root, roots) = make_roots("downsample");
+        let oldest = write_screenshot(&roots.screenshots, "screenshot_1.png", 72 * 3600);
+        let older = write_screenshot(&roots.screenshots, "screenshot_2.png", 48 * 3600);
+        let recent = write_screenshot(&roots.screenshots, "screenshot_3.png", 3600);
+        let used = usage_of(&scan(Category::Screenshots, &roots));
+        assert!(used > 3 * MB && used < 4 * MB, "{}", used);
+
+        let config = QuotaConfig {
+            screenshots_mb: Some(3),
+            downsample_screenshots: true,
+            full_res_hours: 24,
+            ..no_quotas()
+        };
+        let mut status = StorageStatus::default();
+        enforce(&config, &roots, || None, &mut status);
+
+        // Shrinking the oldest one was enough; nothing was deleted
+        assert_eq!(status.downsampled_files, 1);
+        assert_eq!(status.deleted_files, 0);
+        assert!(!oldest.exists());
+        let jpg = oldest.with_extension("jpg");
+        let meta: ScreenshotMeta = serde_json::from_str(&fs::read_to_string(screenshot_meta::sidecar_path(&jpg)).unwrap()).unwrap();
+        assert!(meta.downsampled);
+        assert_eq!(meta.file, "screenshot_1.jpg");
+        assert_eq!(meta.sha256, screenshot_meta::sha256_file(&jpg).unwrap());
+        assert_eq!(image::image_dimensions(&jpg).unwrap(), (300, 300));
+        assert!(older.exists() && recent.exists());
+
+        // Screenshots within `full_res_hours` are deleted, never downsampled
+        let config = QuotaConfig {
+            screenshots_mb: Some(2),
+            full_res_hours: 100,
+            ..config
+        };
+        enforce(&config, &roots, || None, &mut status);
+        assert_eq!(status.downsampled_files, 1);
+        assert!(!jpg.exists() && !older.exists());
+        assert!(!screenshot_meta::sidecar_path(&older).exists());
+        assert!(recent.exists());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn quota_prunes_oldest_first_and_spares_files_in_use() {
+        let (root, roots) = make_roots("quota");
+        write_old(&roots.logs.join("activity_1.log"), MB as usize, 3 * 3600);
+        write_old(&roots.logs.join("activity_2.log"), MB as usize, 2 * 3600);
+        write_old(&roots.logs.join("activity_3.log"), MB as usize, 3600);
+        write_old(&roots.logs.join("activity.log"), MB as usize, 4 * 3600);
+        // Possibly still being written
+        fs::write(roots.logs.join("activity_4.log"), vec![0u8; MB as usize]).unwrap();
+
+        let mut status = StorageStatus::default();
+        let config = QuotaConfig {
+            logs_mb: Some(4),
+            ..no_quotas()
+        };
+        enforce(&config, &roots, || None, &mut status);
+        assert!(!roots.logs.join("activity_1.log").exists());
+        assert!(roots.logs.join("activity_2.log").exists());
+        assert_eq!(status.deleted_files, 1);
+
+        // Under quota nothing else goes
+        enforce(&config, &roots, || None, &mut status);
+        assert_eq!(status.deleted_files, 1);
+
+        let config = QuotaConfig {
+            logs_mb: Some(0),
+            ..no_quotas()
+        };
+        enforce(&config, &roots, || None, &mut status);
+        assert!(roots.logs.join("activity.log").exists());
+        assert!(roots.logs.join("activity_4.log").exists());
+        assert!(!roots.logs.join("activity_3.log").exists());
+        assert_eq!(status.reclaimed_bytes, 3 * MB);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn captures_pause_below_the_critical_threshold() {
+        let (root, roots) = make_roots("critical");
+        write_old(&roots.logs.join("activity_1.log"), MB as usize, 3600);
+        let config = QuotaConfig {
+            min_free_mb: 1000,
+            critical_free_mb: 500,
+            ..no_quotas()
+        };
+        let mut status = StorageStatus::default();
+
+        enforce(&config, &roots, || Some(400 * MB), &mut status);
+        assert!(status.paused);
+        assert!(status.warning.as_deref().unwrap().contains("400 MB free"));
+        // Low disk also reclaims the oldest files
+        assert!(!roots.logs.join("activity_1.log").exists());
+
+        enforce(&config, &roots, || Some(600 * MB), &mut status);
+        assert!(!status.paused);
+        assert!(status.warning.is_none());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn upload_queue_and_audit_count_towards_the_total() {
+        let (root, roots) = make_roots("spool");
+        let queue = &roots.upload_queue;
+        fs::create_dir_all(queue.join(upload::DATA_DIR)).unwrap();
+        fs::create_dir_all(queue.join(upload::FAILED_DIR)).unwrap();
+        write_old(&queue.join("0001_item.json"), 1000, 7200);
+        write_old(&queue.join(upload::DATA_DIR).join("0001_activity.jsonl"), 2000, 7200);
+        write_old(&queue.join(upload::FAILED_DIR).join("0000_item.json"), 3000, 7200);
+        write_old(&roots.audit.join("audit_20240101_000000.jsonl"), 4000, 7200);
+        write_old(&roots.audit.join("audit.jsonl"), 5000, 7200);
+
+        let mut status = StorageStatus::default();
+        enforce(&no_quotas(), &roots, || None, &mut status);
+        assert_eq!(status.total_bytes, 15_000);
+        let bytes_of = |category| status.usage.iter().find(|u| u.category == category).unwrap().bytes;
+        assert_eq!(bytes_of(Category::UploadQueue), 6000);
+        assert_eq!(bytes_of(Category::Audit), 9000);
+
+        // Over the total, spooled copies and dead letters go; pending items and the audit trail stay
+        let config = QuotaConfig {
+            total_mb: Some(0),
+            ..no_quotas()
+        };
+        enforce(&config, &roots, || None, &mut status);
+        assert_eq!(status.deleted_files, 2);
+        assert!(queue.join("0001_item.json").exists());
+        assert!(roots.audit.join("audit_20240101_000000.jsonl").exists());
+        assert!(roots.audit.join("audit.jsonl").exists());
+        assert_eq!(status.total_bytes, 10_000);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn deleted_videos_leave_the_index() {
+        let (root, roots) = make_roots("prune");
+        write_old(&roots.videos.join("old.mp4"), 2 * MB as usize, 7200);
+        write_old(&roots.videos.join("new.mp4"), MB as usize, 3600);
+        recording_index::append(&roots.videos, &RecordingEntry::new("old.mp4".into(), 1_000, 2_000)).unwrap();
+        recording_index::append(&roots.videos, &RecordingEntry::new("new.mp4".into(), 2_000, 3_000)).unwrap();
+
+        let config = QuotaConfig {
+            videos_mb: Some(2),
+            ..QuotaConfig::default()
+        };
+        let mut status = StorageStatus::default();
+        enforce(&config, &roots, || None, &mut status);
+
+        assert!(!roots.videos.join("old.mp4").exists());
+        assert!(roots.videos.join("new.mp4").exists());
+        let files: Vec<String> = recording_index::load(&roots.videos).into_iter().map(|e| e.file).collect();
+        assert_eq!(files, vec!["new.mp4".to_string()]);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
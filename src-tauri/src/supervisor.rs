@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::current_ts_millis;
+use crate::worker::{Backoff, WorkerControl, WorkerState};
+
+const MAX_EVENTS: usize = 100;
+/// A worker that stays up this long counts as healthy and its backoff resets
+pub const HEALTHY_RUN_MS: u64 = 5 * 60_000;
+/// Consecutive failures after which a worker is reported as persistently failing
+pub const PERSISTENT_FAILURES: u32 = 5;
+const RESTART_BASE: Duration = Duration::from_secs(2);
+const RESTART_MAX: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum SupervisorEventKind {
+    /// A thread that should run for the app's lifetime returned
+    Exited,
+    Panicked,
+    /// A capture worker gave up with an error
+    Failed,
+    Restarting { attempt: u32, delay_secs: u64 },
+    Restarted { attempt: u32 },
+    /// `PERSISTENT_FAILURES` failures in a row without a healthy run;
+    /// restarts continue at the maximum backoff
+    PersistentFailure { failures: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorEvent {
+    pub ts_ms: u64,
+    pub worker: String,
+    #[serde(flatten)]
+    pub kind: SupervisorEventKind,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SupervisorLog {
+    events: VecDeque<SupervisorEvent>,
+}
+
+impl SupervisorLog {
+    pub fn record(&mut self, worker: &str, kind: SupervisorEventKind, message: impl Into<String>) {
+        let message = message.into();
+        eprintln!("🛟 {} {:?}: {}", worker, kind, message);
+        self.events.push_back(SupervisorEvent {
+            ts_ms: current_ts_millis(),
+            worker: worker.to_string(),
+            kind,
+            message,
+        });
+        if self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    /// The last `limit` events, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<SupervisorEvent> {
+        let start = self.events.len().saturating_sub(limit);
+        self.events.iter().skip(start).cloned().collect()
+    }
+}
+
+/// Restart bookkeeping for one worker
+pub struct RestartTracker {
+    backoff: Backoff,
+    /// Failures since the last healthy run
+    failures: u32,
+    started_ms: u64,
+    retry_at_ms: Option<u64>,
+}
+
+impl RestartTracker {
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            backoff: Backoff::new(RESTART_BASE, RESTART_MAX),
+            failures: 0,
+            started_ms: now_ms,
+            retry_at_ms: None,
+        }
+    }
+
+    /// Note a failure and schedule the restart. Returns the delay and
+    /// whether the worker has now failed persistently.
+    pub fn failed(&mut self, now_ms: u64) -> (Duration, bool) {
+        if now_ms.saturating_sub(self.started_ms) >= HEALTHY_RUN_MS {
+            self.failures = 0;
+            self.backoff.reset();
+        }
+        self.failures += 1;
+        let delay = self.backoff.next_delay();
+        self.retry_at_ms = Some(now_ms + delay.as_millis() as u64);
+        (delay, self.failures == PERSISTENT_FAILURES)
+    }
+
+    pub fn due(&self, now_ms: u64) -> bool {
+        self.retry_at_ms.is_some_and(|t| now_ms >= t)
+    }
+
+    pub fn pending(&self) -> bool {
+        self.retry_at_ms.is_some()
+    }
+
+    pub fn restarted(&mut self, now_ms: u64) {
+        self.retry_at_ms = None;
+        self.started_ms = now_ms;
+    }
+
+    /// Nothing to restart any more, e.g. the worker was stopped on purpose
+    pub fn cancel(&mut self) {
+        self.retry_at_ms = None;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".into())
+}
+
+/// A thread meant to run for as long as the app does
+struct Service {
+    name: &'static str,
+    spawn: Box<dyn Fn() -> JoinHandle<()> + Send>,
+    handle: Option<JoinHandle<()>>,
+    tracker: RestartTracker,
+}
+
+/// A capture worker that runs only while asked to. `restart` spawns it again
+/// from its last settings and returns `Ok(false)` if it was stopped on
+/// purpose in the meantime.
+struct CaptureWorker {
+    name: &'static str,
+    control: Arc<WorkerControl>,
+    handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    restart: Box<dyn Fn() -> Result<bool, String> + Send>,
+    tracker: RestartTracker,
+    /// `started_at_ms` of the run whose failure was already handled
+    handled_run: Option<u64>,
+}
+
+/// Owns every worker thread. `check` notices exits and panics and restarts
+/// workers with exponential backoff.
+pub struct Supervisor {
+    services: Vec<Service>,
+    workers: Vec<CaptureWorker>,
+    log: Arc<Mutex<SupervisorLog>>,
+}
+
+impl Supervisor {
+    pub fn new(log: Arc<Mutex<SupervisorLog>>) -> Self {
+        Self {
+            services: Vec::new(),
+            workers: Vec::new(),
+            log,
+        }
+    }
+
+    /// Start `spawn` now and again whenever its thread ends
+    pub fn add_service(&mut self, name: &'static str, spawn: impl Fn() -> JoinHandle<()> + Send + 'static) {
+        let handle = spawn();
+        self.services.push(Service {
+            name,
+            spawn: Box::new(spawn),
+            handle: Some(handle),
+            tracker: RestartTracker::new(current_ts_millis()),
+        });
+    }
+
+    pub fn add_worker(
+        &mut self,
+        name: &'static str,
+        control: Arc<WorkerControl>,
+        handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+        restart: impl Fn() -> Result<bool, String> + Send + 'static,
+    ) {
+        self.workers.push(CaptureWorker {
+            name,
+            control,
+            handle,
+            restart: Box::new(restart),
+            tracker: RestartTracker::new(current_ts_millis()),
+            handled_run: None,
+        });
+    }
+
+    /// Note a failure in the log and schedule the restart
+    fn failed(
+        &self,
+        events: &mut Vec<SupervisorEvent>,
+        name: &str,
+        tracker: &mut RestartTracker,
+        kind: SupervisorEventKind,
+        message: String,
+        now_ms: u64,
+    ) {
+        let mut log = self.log.lock().unwrap();
+        log.record(name, kind, message);
+        let (delay, persistent) = tracker.failed(now_ms);
+        if persistent {
+            log.record(
+                name,
+                SupervisorEventKind::PersistentFailure { failures: tracker.failures() },
+                format!("{} failed {} times in a row", name, tracker.failures()),
+            );
+        }
+        log.record(
+            name,
+            SupervisorEventKind::Restarting {
+                attempt: tracker.failures(),
+                delay_secs: delay.as_secs(),
+            },
+            format!("restarting in {}s", delay.as_secs()),
+        );
+        let added = if persistent { 3 } else { 2 };
+        events.extend(log.recent(added));
+    }
+
+    fn restarted(&self, events: &mut Vec<SupervisorEvent>, name: &str, tracker: &mut RestartTracker, now_ms: u64) {
+        tracker.restarted(now_ms);
+        let mut log = self.log.lock().unwrap();
+        let attempt = tracker.failures();
+        log.record(name, SupervisorEventKind::Restarted { attempt }, "restarted");
+        events.extend(log.recent(1));
+    }
+
+    /// Look at every worker once and restart those that are due; call this
+    /// every few seconds. Returns the events recorded on this pass.
+    pub fn check(&mut self) -> Vec<SupervisorEvent> {
+        let now = current_ts_millis();
+        let mut events = Vec::new();
+        let mut services = std::mem::take(&mut self.services);
+        for service in &mut services {
+            if service.handle.as_ref().is_some_and(|h| h.is_finished()) {
+                let (kind, message) = match service.handle.take().map(|h| h.join()) {
+                    Some(Err(payload)) => (SupervisorEventKind::Panicked, panic_message(payload.as_ref())),
+                    _ => (SupervisorEventKind::Exited, "thread returned".to_string()),
+                };
+                self.failed(&mut events, service.name, &mut service.tracker, kind, message, now);
+            }
+            if service.handle.is_none() && service.tracker.due(now) {
+                service.handle = Some((service.spawn)());
+                self.restarted(&mut events, service.name, &mut service.tracker, now);
+            }
+        }
+        self.services = services;
+
+        let mut workers = std::mem::take(&mut self.workers);
+        for worker in &mut workers {
+            let mut failure = None;
+            if worker.control.is_running() {
+                let finished = {
+                    let mut handle = worker.handle.lock().unwrap();
+                    match handle.as_ref().is_some_and(|h| h.is_finished()) {
+                        true => handle.take().map(|h| h.join()),
+                        false => None,
+                    }
+                };
+                // A loop that returns always records its outcome first, so a
+                // finished thread still marked running has panicked. Spawns
+                // join the previous thread before marking the worker running,
+                // so the handle here always belongs to the current run.
+                if let Some(joined) = finished.filter(|_| worker.control.is_running()) {
+                    let message = match joined {
+                        Err(payload) => panic_message(payload.as_ref()),
+                        Ok(()) => "thread ended without recording an outcome".to_string(),
+                    };
+                    worker.control.fail(format!("panicked: {}", message));
+                    failure = Some((SupervisorEventKind::Panicked, message));
+                }
+            }
+            let status = worker.control.status();
+            if failure.is_none() && worker.handled_run != status.started_at_ms {
+                if let WorkerState::Failed(reason) = &status.state {
+                    failure = Some((SupervisorEventKind::Failed, reason.clone()));
+                }
+            }
+            if let Some((kind, message)) = failure {
+                worker.handled_run = status.started_at_ms;
+                self.failed(&mut events, worker.name, &mut worker.tracker, kind, message, now);
+            }
+
+            if !worker.tracker.pending() {
+                continue;
+            }
+            // Someone started it by hand in the meantime
+            if worker.control.is_running() {
+                worker.tracker.restarted(now);
+                continue;
+            }
+            if !worker.tracker.due(now) {
+                continue;
+            }
+            match (worker.restart)() {
+                Ok(true) => self.restarted(&mut events, worker.name, &mut worker.tracker, now),
+                Ok(false) => worker.tracker.cancel(),
+                Err(e) => self.failed(&mut events, worker.name, &mut worker.tracker, SupervisorEventKind::Failed, e, now),
+            }
+        }
+        self.workers = workers;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restarts_back_off_until_a_healthy_run() {
+        let t0 = 1_000_000;
+        let mut tracker = RestartTracker::new(t0);
+        assert!(!tracker.pending());
+
+        assert_eq!(tracker.failed(t0 + 1_000), (Duration::from_secs(2), false));
+        assert!(tracker.pending());
+        assert!(!tracker.due(t0 + 2_999));
+        assert!(tracker.due(t0 + 3_000));
+        tracker.restarted(t0 + 3_000);
+        assert!(!tracker.pending());
+
+        // Each quick failure doubles the delay
+        assert_eq!(tracker.failed(t0 + 4_000).0, Duration::from_secs(4));
+        tracker.restarted(t0 + 8_000);
+        assert_eq!(tracker.failed(t0 + 9_000).0, Duration::from_secs(8));
+        assert_eq!(tracker.failures(), 3);
+
+        // A run that lasted long enough starts over
+        tracker.restarted(t0 + 20_000);
+        let (delay, persistent) = tracker.failed(t0 + 20_000 + HEALTHY_RUN_MS);
+        assert_eq!((delay, persistent), (Duration::from_secs(2), false));
+        assert_eq!(tracker.failures(), 1);
+    }
+
+    #[test]
+    fn persistent_failure_is_flagged_once_and_delays_stay_capped() {
+        let mut tracker = RestartTracker::new(0);
+        let mut flagged = Vec::new();
+        let mut last = Duration::ZERO;
+        for n in 1..=20u64 {
+            let (delay, persistent) = tracker.failed(n);
+            tracker.restarted(n);
+            if persistent {
+                flagged.push(tracker.failures());
+            }
+            last = delay;
+        }
+        assert_eq!(flagged, [PERSISTENT_FAILURES]);
+        assert_eq!(last, RESTART_MAX);
+
+        tracker.failed(100);
+        tracker.cancel();
+        assert!(!tracker.pending());
+        assert!(!tracker.due(u64::MAX));
+    }
+
+    #[test]
+    fn log_keeps_the_latest_events_in_order() {
+        let mut log = SupervisorLog::default();
+        for n in 0..MAX_EVENTS + 5 {
+            log.record("video", SupervisorEventKind::Failed, format!("failure {}", n));
+        }
+        assert_eq!(log.recent(usize::MAX).len(), MAX_EVENTS);
+        let last: Vec<_> = log.recent(2).into_iter().map(|e| e.message).collect();
+        assert_eq!(last, [format!("failure {}", MAX_EVENTS + 3), format!("failure {}", MAX_EVENTS + 4)]);
+    }
+
+    #[test]
+    fn panicking_service_is_reported_and_scheduled_for_restart() {
+        let log = Arc::new(Mutex::new(SupervisorLog::default()));
+        let mut supervisor = Supervisor::new(log.clone());
+        supervisor.add_service("input", || std::thread::spawn(|| panic!("hook lost")));
+        // Leave the thread time to die
+        for _ in 0..100 {
+            if supervisor.services[0].handle.as_ref().is_some_and(|h| h.is_finished()) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let events = supervisor.check();
+        let kinds: Vec<_> = events.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                SupervisorEventKind::Panicked,
+                SupervisorEventKind::Restarting { attempt: 1, delay_secs: 2 },
+            ]
+        );
+        assert_eq!(events[0].message, "hook lost");
+        assert_eq!(events[0].worker, "input");
+        // Not restarted before the delay is up
+        assert!(supervisor.check().is_empty());
+        assert!(supervisor.services[0].handle.is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! Helpers for tests that talk to `examples/mock_server.rs`
+#![allow(dead_code)]
+
+#[path = "../examples/mock_server.rs"]
+pub mod mock_server;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::SigningKey;
+
+pub use mock_server::Options;
+
+use crate::current_ts_millis;
+use crate::identity::{self, DeviceIdentity};
+
+pub const ENROLL_CODE: &str = "TEST-ENROLL";
+
+/// A fresh directory under the system temp dir
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("spectosoft-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Start a mock server on an ephemeral port with its data in `data`;
+/// returns its base URL
+pub fn start_server(data: &Path, options: Options) -> String {
+    let options = Options {
+        data: data.to_path_buf(),
+        ..options
+    };
+    let addr = mock_server::spawn("127.0.0.1:0", options).unwrap();
+    format!("http://{}", addr)
+}
+
+pub fn test_options() -> Options {
+    Options {
+        enroll_code: ENROLL_CODE.to_string(),
+        ..Options::default()
+    }
+}
+
+/// A new device identity in `dir`, enrolled with the server at `url`
+pub fn enrolled_device(dir: &Path, url: &str) -> DeviceIdentity {
+    let mut identity = DeviceIdentity::load_or_create(&dir.join("device.json"), current_ts_millis()).unwrap();
+    identity::enroll(&mut identity, url, ENROLL_CODE, current_ts_millis()).unwrap();
+    identity
+}
+
+/// The key the server at `data` signs policies and commands with
+pub fn server_key(data: &Path) -> SigningKey {
+    let secret = fs::read_to_string(data.join("server_key.hex")).unwrap();
+    let secret: Vec<u8> = (0..64).step_by(2).map(|i| u8::from_str_radix(&secret[i..i + 2], 16).unwrap()).collect();
+    SigningKey::from_bytes(&secret.try_into().unwrap())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
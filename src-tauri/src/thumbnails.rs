@@ -0,0 +1,144 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::ffmpeg;
+use crate::recording_index;
+
+/// Cached stills live under `<video dir>/thumbs/<clip stem>/`
+pub const THUMB_DIR: &str = "thumbs";
+pub const DEFAULT_HEIGHT: u32 = 180;
+pub const MAX_STRIP: u32 = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Thumbnail {
+    pub offset_secs: f64,
+    pub path: String,
+}
+
+/// Resolve a clip name from the index to a path inside `video_dir`,
+/// refusing anything that could point elsewhere
+fn clip_path(video_dir: &Path, file: &str) -> Result<PathBuf, String> {
+    let name = Path::new(file).file_name().ok_or("Invalid clip name")?;
+    if name != file {
+        return Err(format!("Invalid clip name: {}", file));
+    }
+    let path = video_dir.join(name);
+    if !path.is_file() {
+        return Err(format!("No such clip: {}", file));
+    }
+    Ok(path)
+}
+
+fn cache_path(video_dir: &Path, clip: &Path, offset_secs: f64, height: u32) -> PathBuf {
+    let stem = clip.file_stem().unwrap_or_default();
+    let offset_ms = (offset_secs * 1000.0).round() as u64;
+    video_dir
+        .join(THUMB_DIR)
+        .join(stem)
+        .join(format!("{}_{}.jpg", offset_ms, height))
+}
+
+/// JPEG of the frame at `offset_secs`, scaled to `height`. Extracted once and
+/// served from the cache afterwards.
+pub fn extract_frame(video_dir: &Path, file: &str, offset_secs: f64, height: u32) -> Result<PathBuf, String> {
+    let clip = clip_path(video_dir, file)?;
+    let offset_secs = offset_secs.max(0.0);
+    let out = cache_path(video_dir, &clip, offset_secs, height);
+    if out.is_file() {
+        return Ok(out);
+    }
+    fs::create_dir_all(out.parent().unwrap_or(video_dir)).map_err(|e| e.to_string())?;
+
+    // Write under a temporary name so a failed run never leaves a broken cache entry
+    let tmp = out.with_extension("part.jpg");
+    let output = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-ss", &format!("{:.3}", offset_secs), "-i"])
+        .arg(&clip)
+        .args(["-frames:v", "1", "-vf", &format!("scale=-2:{}", height & !1), "-q:v", "4"])
+        .arg(&tmp)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    if !output.status.success() || !tmp.is_file() {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!(
+            "Frame extraction at {:.3}s failed: {}",
+            offset_secs,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    fs::rename(&tmp, &out).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// `count` evenly spaced thumbnails across the clip, each taken from the
+/// middle of its slice so the first and last frames (often black) are avoided
+pub fn strip(video_dir: &Path, file: &str, count: u32, height: u32) -> Result<Vec<Thumbnail>, String> {
+    let clip = clip_path(video_dir, file)?;
+    let count = count.clamp(1, MAX_STRIP);
+    let indexed = recording_index::load(video_dir)
+        .into_iter()
+        .find(|e| e.file == file)
+        .and_then(|e| e.duration_secs);
+    let duration = match indexed {
+        Some(d) => d,
+        None => ffmpeg::probe(&clip)?.duration_secs,
+    };
+    if duration <= 0.0 {
+        return Err(format!("Clip {} has no duration", file));
+    }
+
+    let step = duration / count as f64;
+    (0..count)
+        .map(|i| {
+            let offset_secs = step * (i as f64 + 0.5);
+            let path = extract_frame(video_dir, file, offset_secs, height)?;
+            Ok(Thumbnail {
+                offset_secs,
+                path: path.to_string_lossy().to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_path_stays_inside_the_video_dir() {
+        let root = std::env::temp_dir().join(format!("thumbnails_clip_{}", std::process::id()));
+        let videos = root.join("videos");
+        fs::create_dir_all(&videos).unwrap();
+        fs::write(videos.join("capture_a.mp4"), b"").unwrap();
+        fs::write(root.join("secret.mp4"), b"").unwrap();
+
+        assert_eq!(clip_path(&videos, "capture_a.mp4").unwrap(), videos.join("capture_a.mp4"));
+
+        let outside = root.join("secret.mp4").to_string_lossy().to_string();
+        for file in ["../secret.mp4", "./capture_a.mp4", "sub/capture_a.mp4", "..", "", outside.as_str()] {
+            assert!(clip_path(&videos, file).is_err(), "accepted {:?}", file);
+        }
+        #[cfg(windows)]
+        assert!(clip_path(&videos, "..\\secret.mp4").is_err());
+
+        assert!(clip_path(&videos, "missing.mp4").unwrap_err().contains("No such clip"));
+        // Directories are not clips
+        fs::create_dir_all(videos.join(THUMB_DIR)).unwrap();
+        assert!(clip_path(&videos, THUMB_DIR).is_err());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn cache_path_is_keyed_by_clip_offset_and_height() {
+        let videos = Path::new("/videos");
+        let clip = videos.join("capture_a.mp4");
+        assert_eq!(
+            cache_path(videos, &clip, 12.3456, 180),
+            videos.join(THUMB_DIR).join("capture_a").join("12346_180.jpg")
+        );
+    }
+}
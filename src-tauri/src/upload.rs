@@ -0,0 +1,573 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::identity::DeviceSigner;
+use crate::worker::Backoff;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    pub enabled: bool,
+    /// Base URL of the collection server, e.g. `https://collector.example.com/api`
+    pub endpoint: String,
+    /// Large files are sent in pieces of this size so an interrupted upload
+    /// resumes where it stopped
+    pub chunk_bytes: u64,
+    pub retry_base_secs: u64,
+    pub retry_max_secs: u64,
+    /// Activity events are buffered and sent as one batch this often
+    pub activity_batch_secs: u64,
+    pub activity: bool,
+    pub screenshots: bool,
+    pub videos: bool,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: String::new(),
+            chunk_bytes: 4 * 1024 * 1024,
+            retry_base_secs: 5,
+            retry_max_secs: 15 * 60,
+            activity_batch_secs: 60,
+            activity: true,
+            screenshots: true,
+            videos: true,
+        }
+    }
+}
+
+impl UploadConfig {
+    pub fn wants(&self, kind: UploadKind) -> bool {
+        self.enabled
+            && !self.endpoint.trim().is_empty()
+            && match kind {
+                UploadKind::Activity => self.activity,
+                UploadKind::Screenshot => self.screenshots,
+                UploadKind::Video => self.videos,
+            }
+    }
+
+    fn backoff(&self) -> Backoff {
+        Backoff::new(
+            Duration::from_secs(self.retry_base_secs.max(1)),
+            Duration::from_secs(self.retry_max_secs.max(self.retry_base_secs.max(1))),
+        )
+    }
+}
+
+pub fn load_config(path: &Path) -> Result<UploadConfig, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(UploadConfig::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn save_config(path: &Path, config: &UploadConfig) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadKind {
+    Activity,
+    Screenshot,
+    Video,
+}
+
+/// One file waiting to be sent, persisted as `<queue dir>/<id>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueItem {
+    /// Starts with the zero-padded enqueue time so ids sort oldest first
+    pub id: String,
+    pub kind: UploadKind,
+    pub path: PathBuf,
+    /// Spooled copies (activity batches) are deleted once uploaded; capture
+    /// files stay for the storage manager
+    #[serde(default)]
+    pub owned: bool,
+    /// Sent with the upload, e.g. the screenshot sidecar or index entry
+    #[serde(default)]
+    pub meta: Value,
+    pub enqueued_ms: u64,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub next_attempt_ms: u64,
+    /// Server-side upload session, kept so an interrupted upload resumes
+    #[serde(default)]
+    pub upload_id: Option<String>,
+    /// Size and hash the current session was opened with
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Bytes the server has confirmed
+    #[serde(default)]
+    pub offset: u64,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl QueueItem {
+    fn restart(&mut self) {
+        self.upload_id = None;
+        self.sha256 = None;
+        self.size = 0;
+        self.offset = 0;
+    }
+}
+
+/// Copies spooled by `enqueue_bytes`, under the queue directory
+pub const DATA_DIR: &str = "data";
+/// Items the server refused for good, under the queue directory
+pub const FAILED_DIR: &str = "failed";
+
+/// Durable outbound queue. Every item is its own small JSON file written via
+/// rename, so a crash loses at most the progress of the current chunk.
+#[derive(Debug)]
+pub struct UploadQueue {
+    dir: PathBuf,
+}
+
+impl UploadQueue {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn item_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn new_id(now_ms: u64) -> String {
+        format!("{:013}_{:08x}", now_ms, rand::random::<u32>())
+    }
+
+    pub fn enqueue(&self, kind: UploadKind, path: &Path, meta: Value, now_ms: u64) -> Result<QueueItem, String> {
+        self.push(kind, path, false, meta, now_ms)
+    }
+
+    /// Spool `bytes` into the queue directory and enqueue the copy
+    pub fn enqueue_bytes(&self, kind: UploadKind, name: &str, bytes: &[u8], meta: Value, now_ms: u64) -> Result<QueueItem, String> {
+        let data_dir = self.dir.join(DATA_DIR);
+        fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+        let path = data_dir.join(format!("{}_{}", Self::new_id(now_ms), name));
+        fs::write(&path, bytes).map_err(|e| format!("Failed to spool {}: {}", path.display(), e))?;
+        self.push(kind, &path, true, meta, now_ms)
+    }
+
+    fn push(&self, kind: UploadKind, path: &Path, owned: bool, meta: Value, now_ms: u64) -> Result<QueueItem, String> {
+        let item = QueueItem {
+            id: Self::new_id(now_ms),
+            kind,
+            path: path.to_path_buf(),
+            owned,
+            meta,
+            enqueued_ms: now_ms,
+            attempts: 0,
+            next_attempt_ms: now_ms,
+            upload_id: None,
+            size: 0,
+            sha256: None,
+            offset: 0,
+            last_error: None,
+        };
+        self.save(&item)?;
+        Ok(item)
+    }
+
+    pub fn save(&self, item: &QueueItem) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(item).map_err(|e| e.to_string())?;
+        let path = self.item_path(&item.id);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    /// Pending items, oldest first. Unreadable entries are skipped.
+    pub fn items(&self) -> Vec<QueueItem> {
+        let mut items: Vec<QueueItem> = fs::read_dir(&self.dir)
+            .map(|rd| {
+                rd.filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|x| x == "json"))
+                    .filter_map(|p| fs::read_to_string(&p).ok())
+                    .filter_map(|s| serde_json::from_str(&s).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        items.sort_by(|a, b| a.id.cmp(&b.id));
+        items
+    }
+
+    pub fn complete(&self, item: &QueueItem) {
+        if item.owned {
+            let _ = fs::remove_file(&item.path);
+        }
+        let _ = fs::remove_file(self.item_path(&item.id));
+    }
+
+    /// Move an item the server refused for good to `failed/` for inspection
+    pub fn dead_letter(&self, item: &QueueItem) -> Result<(), String> {
+        let failed = self.dir.join(FAILED_DIR);
+        fs::create_dir_all(&failed).map_err(|e| e.to_string())?;
+        fs::rename(self.item_path(&item.id), failed.join(format!("{}.json", item.id))).map_err(|e| e.to_string())
+    }
+
+    pub fn failed_count(&self) -> usize {
+        fs::read_dir(self.dir.join(FAILED_DIR)).map(|rd| rd.count()).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UploadStatus {
+    pub enabled: bool,
+    pub pending: usize,
+    pub failed: usize,
+    /// False after a connection failure, until the next successful request
+    pub online: bool,
+    pub uploaded_items: u64,
+    pub uploaded_bytes: u64,
+    pub last_success_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    /// The server could not be reached
+    Offline(String),
+    /// A server-side or local error; try again later
+    Retry(String),
+    /// The server lost or rejected the upload session; start the file over
+    Restart(String),
+    /// The server refused the file itself
+    Fatal(String),
+    /// The file was deleted (e.g. by the storage manager) before it was sent
+    Missing,
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::Offline(e) | UploadError::Retry(e) | UploadError::Restart(e) | UploadError::Fatal(e) => {
+                write!(f, "{}", e)
+            }
+            UploadError::Missing => write!(f, "File no longer exists"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Progress {
+    #[serde(default)]
+    upload_id: Option<String>,
+    offset: u64,
+    #[serde(default)]
+    complete: bool,
+}
+
+fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Talks the collection server's upload protocol:
+///
+/// - `POST {endpoint}/uploads` with the file description opens a session
+/// - `GET {endpoint}/uploads/{id}` reports how many bytes arrived
+/// - `PUT {endpoint}/uploads/{id}` with `Content-Range` appends one chunk
+///
+/// Every reply is `{ "upload_id"?, "offset", "complete" }`. The server checks
+/// the SHA-256 once all bytes are in. Requests are signed with the device key
+/// over the path relative to the endpoint.
+pub struct UploadClient {
+    agent: ureq::Agent,
+    endpoint: String,
+    signer: DeviceSigner,
+}
+
+impl UploadClient {
+    pub fn new(endpoint: &str, signer: DeviceSigner) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            .timeout_read(Duration::from_secs(60))
+            .timeout_write(Duration::from_secs(60))
+            .build();
+        Self {
+            agent,
+            endpoint: endpoint.trim().trim_end_matches('/').to_string(),
+            signer,
+        }
+    }
+
+    fn classify(e: ureq::Error) -> UploadError {
+        match e {
+            ureq::Error::Status(code, resp) => {
+                let body = resp.into_string().unwrap_or_default();
+                let msg = format!("Server returned {}: {}", code, body.trim());
+                match code {
+                    404 | 409 | 410 => UploadError::Restart(msg),
+                    // 401/403: not (yet) known to the server; keep the file until it is
+                    401 | 403 | 408 | 429 | 500..=599 => UploadError::Retry(msg),
+                    _ => UploadError::Fatal(msg),
+                }
+            }
+            ureq::Error::Transport(t) => UploadError::Offline(t.to_string()),
+        }
+    }
+
+    fn send(&self, method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<Progress, UploadError> {
+        let mut req = self.agent.request(method, &format!("{}{}", self.endpoint, path));
+        for (name, value) in self.signer.headers(method, path, body, crate::current_ts_millis()) {
+            req = req.set(name, &value);
+        }
+        for (name, value) in headers {
+            req = req.set(name, value);
+        }
+        req.send_bytes(body)
+            .map_err(Self::classify)?
+            .into_json()
+            .map_err(|e| UploadError::Retry(format!("Invalid server reply: {}", e)))
+    }
+
+    /// Send `item`, resuming a session opened earlier. Progress is saved to
+    /// `queue` after every chunk.
+    pub fn upload(&self, queue: &UploadQueue, item: &mut QueueItem, chunk_bytes: u64) -> Result<(), UploadError> {
+        let size = match fs::metadata(&item.path) {
+            Ok(m) => m.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(UploadError::Missing),
+            Err(e) => return Err(UploadError::Retry(e.to_string())),
+        };
+        // Rewritten since the session opened (e.g. a downsampled screenshot)
+        if item.upload_id.is_some() && size != item.size {
+            item.restart();
+        }
+
+        let mut complete = false;
+        if let Some(id) = item.upload_id.clone() {
+            match self.send("GET", &format!("/uploads/{}", id), &[], &[]) {
+                Ok(p) => {
+                    item.offset = p.offset.min(size);
+                    complete = p.complete;
+                }
+                Err(UploadError::Restart(_)) => item.restart(),
+                Err(e) => return Err(e),
+            }
+        }
+
+        if item.upload_id.is_none() {
+            let sha256 = file_sha256(&item.path).map_err(|e| UploadError::Retry(e.to_string()))?;
+            let file_name = item.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let body = serde_json::json!({
+                "kind": item.kind,
+                "file_name": file_name,
+                "size": size,
+                "sha256": sha256,
+                "enqueued_ms": item.enqueued_ms,
+                "meta": item.meta,
+            });
+            let json = [("Content-Type", "application/json")];
+            let p = self.send("POST", "/uploads", &json, body.to_string().as_bytes())?;
+            item.upload_id = Some(p.upload_id.ok_or(UploadError::Retry("Server sent no upload_id".into()))?);
+            item.size = size;
+            item.sha256 = Some(sha256);
+            item.offset = p.offset.min(size);
+            complete = p.complete;
+            let _ = queue.save(item);
+        }
+
+        let path = format!("/uploads/{}", item.upload_id.clone().unwrap_or_default());
+        let mut file = File::open(&item.path).map_err(|e| UploadError::Retry(e.to_string()))?;
+        let mut buf = Vec::new();
+        while item.offset < size {
+            let len = chunk_bytes.max(1).min(size - item.offset);
+            buf.resize(len as usize, 0);
+            file.seek(SeekFrom::Start(item.offset))
+                .and_then(|_| file.read_exact(&mut buf))
+                .map_err(|e| UploadError::Retry(e.to_string()))?;
+            let range = format!("bytes {}-{}/{}", item.offset, item.offset + len - 1, size);
+            let headers = [("Content-Type", "application/octet-stream"), ("Content-Range", range.as_str())];
+            let p = self.send("PUT", &path, &headers, &buf)?;
+            if p.offset <= item.offset {
+                return Err(UploadError::Retry(format!("Server did not accept bytes at {}", item.offset)));
+            }
+            item.offset = p.offset.min(size);
+            complete = p.complete;
+            let _ = queue.save(item);
+        }
+
+        if complete {
+            Ok(())
+        } else {
+            Err(UploadError::Retry("Server did not confirm the upload".into()))
+        }
+    }
+}
+
+/// Try every due item once, oldest first. Stops early when the server is
+/// unreachable so an offline machine does not spin through the whole queue.
+pub fn run_once(queue: &UploadQueue, client: &UploadClient, config: &UploadConfig, now_ms: u64, status: &mut UploadStatus) {
+    for mut item in queue.items() {
+        if item.next_attempt_ms > now_ms {
+            continue;
+        }
+        match client.upload(queue, &mut item, config.chunk_bytes) {
+            Ok(()) => {
+                queue.complete(&item);
+                status.online = true;
+                status.uploaded_items += 1;
+                status.uploaded_bytes += item.size;
+                status.last_success_ms = Some(now_ms);
+            }
+            Err(UploadError::Missing) => {
+                eprintln!("📤 Dropping upload of {}: file no longer exists", item.path.display());
+                queue.complete(&item);
+            }
+            Err(UploadError::Fatal(e)) => {
+                eprintln!("📤 Server refused {}: {}", item.path.display(), e);
+                item.last_error = Some(e.clone());
+                let _ = queue.save(&item);
+                if let Err(e) = queue.dead_letter(&item) {
+                    eprintln!("Failed to move {} to failed uploads: {}", item.id, e);
+                }
+                status.last_error = Some(e);
+            }
+            Err(e) => {
+                let offline = matches!(e, UploadError::Offline(_));
+                if let UploadError::Restart(_) = e {
+                    item.restart();
+                }
+                item.next_attempt_ms = now_ms + config.backoff().delay_for(item.attempts).as_millis() as u64;
+                item.attempts += 1;
+                item.last_error = Some(e.to_string());
+                let _ = queue.save(&item);
+                status.last_error = Some(e.to_string());
+                status.online = !offline;
+                if offline {
+                    break;
+                }
+            }
+        }
+    }
+    status.enabled = config.enabled;
+    status.pending = queue.items().len();
+    status.failed = queue.failed_count();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::current_ts_millis;
+    use crate::test_support::{enrolled_device, start_server, temp_dir, test_options, Options};
+
+    const CHUNK: u64 = 1024;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    /// The single file the server stored under `<data>/<kind>/`
+    fn stored(data: &Path, kind: &str) -> Vec<u8> {
+        let files: Vec<_> = fs::read_dir(data.join(kind))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| !p.to_string_lossy().ends_with(".meta.json"))
+            .collect();
+        assert_eq!(files.len(), 1, "{:?}", files);
+        fs::read(&files[0]).unwrap()
+    }
+
+    #[test]
+    fn failed_chunks_resume_the_open_session() {
+        let dir = temp_dir("upload-retry");
+        let data = dir.join("server");
+        // Every third request fails: the session open or some chunk puts
+        let url = start_server(&data, Options { fail_every: 3, ..test_options() });
+        let identity = enrolled_device(&dir, &url);
+        let client = UploadClient::new(&url, identity.signer().unwrap());
+        let queue = UploadQueue::new(dir.join("queue"));
+        let config = UploadConfig {
+            enabled: true,
+            endpoint: url.clone(),
+            chunk_bytes: CHUNK,
+            ..UploadConfig::default()
+        };
+        let bytes = payload(10 * CHUNK as usize + 100);
+        queue
+            .enqueue_bytes(UploadKind::Screenshot, "shot.png", &bytes, serde_json::json!({ "test": true }), current_ts_millis())
+            .unwrap();
+
+        let mut status = UploadStatus::default();
+        let mut session = None;
+        let mut offset = 0;
+        let mut now = current_ts_millis();
+        for _ in 0..50 {
+            run_once(&queue, &client, &config, now, &mut status);
+            let Some(item) = queue.items().into_iter().next() else {
+                break;
+            };
+            assert!(item.last_error.as_deref().is_some_and(|e| e.contains("503")), "{:?}", item.last_error);
+            if let Some(id) = &item.upload_id {
+                assert_eq!(session.get_or_insert_with(|| id.clone()), id, "retry opened a new session");
+            }
+            assert!(item.offset >= offset, "offset went back from {} to {}", offset, item.offset);
+            offset = item.offset;
+            // Past any backoff
+            now += 3_600_000;
+        }
+
+        assert_eq!(status.uploaded_items, 1);
+        assert_eq!(status.uploaded_bytes, bytes.len() as u64);
+        assert!(queue.items().is_empty());
+        assert!(session.is_some(), "no retry happened after the session opened");
+        assert_eq!(stored(&data, "screenshot"), bytes);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn lost_session_starts_over() {
+        let dir = temp_dir("upload-restart");
+        let data = dir.join("server");
+        let url = start_server(&data, test_options());
+        let identity = enrolled_device(&dir, &url);
+        let client = UploadClient::new(&url, identity.signer().unwrap());
+        let queue = UploadQueue::new(dir.join("queue"));
+        let bytes = payload(3 * CHUNK as usize);
+        let mut item = queue
+            .enqueue_bytes(UploadKind::Video, "clip.mp4", &bytes, Value::Null, current_ts_millis())
+            .unwrap();
+        // As left behind by a server that restarted mid-upload
+        item.upload_id = Some("u0_forgotten".into());
+        item.size = bytes.len() as u64;
+        item.sha256 = Some("0".repeat(64));
+        item.offset = CHUNK;
+        queue.save(&item).unwrap();
+
+        client.upload(&queue, &mut item, CHUNK).unwrap();
+
+        assert_ne!(item.upload_id.as_deref(), Some("u0_forgotten"));
+        assert_eq!(item.offset, bytes.len() as u64);
+        assert_eq!(stored(&data, "video"), bytes);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,358 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::roles::Role;
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+/// Failed logins in a row before the account is locked
+pub const MAX_FAILED_ATTEMPTS: u32 = 5;
+pub const LOCKOUT_MS: u64 = 15 * 60 * 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    pub username: String,
+    /// Argon2id PHC string; salt and parameters are embedded
+    pub password_hash: String,
+    #[serde(default)]
+    pub role: Role,
+    /// Stores written before roles existed only had this flag
+    #[serde(default, skip_serializing)]
+    admin: bool,
+    #[serde(default)]
+    pub failed_attempts: u32,
+    #[serde(default)]
+    pub locked_until_ms: Option<u64>,
+    /// Set after an administrator reset; the user must pick a new password
+    #[serde(default)]
+    pub must_change_password: bool,
+    pub created_ms: u64,
+    #[serde(default)]
+    pub password_changed_ms: u64,
+}
+
+/// What other commands may see of a user
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSummary {
+    pub username: String,
+    pub role: Role,
+    pub locked: bool,
+    pub must_change_password: bool,
+    pub created_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    InvalidCredentials,
+    Locked { retry_in_ms: u64 },
+}
+
+/// A locked account reads like a wrong password to the caller, so probing
+/// cannot tell which accounts exist or are locked
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid credentials")
+    }
+}
+
+impl AuthError {
+    /// The real reason, for the audit log only
+    pub fn detail(&self) -> String {
+        match self {
+            AuthError::InvalidCredentials => "Invalid credentials".into(),
+            AuthError::Locked { retry_in_ms } => format!(
+                "Account locked after {} failed attempts, unlocks in {} minutes",
+                MAX_FAILED_ATTEMPTS,
+                retry_in_ms.div_ceil(60_000)
+            ),
+        }
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+/// Argon2 compares the derived hashes in constant time
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|h| Argon2::default().verify_password(password.as_bytes(), &h).is_ok())
+}
+
+/// Verified against for unknown usernames so they take as long to reject as
+/// a wrong password and do not reveal which accounts exist
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password("not-a-real-password").unwrap_or_default())
+}
+
+fn check_password_policy(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+fn normalize_username(username: &str) -> Result<String, String> {
+    let name = username.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err("Username must be 1-64 characters".into());
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '@')) {
+        return Err("Username may only contain letters, digits and . _ - @".into());
+    }
+    Ok(name.to_string())
+}
+
+/// Local accounts, persisted as JSON next to the other config files
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserStore {
+    users: Vec<UserRecord>,
+}
+
+impl UserStore {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => {
+                let mut store: Self = serde_json::from_str(&s).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+                for user in store.users.iter_mut().filter(|u| u.admin) {
+                    user.role = Role::Admin;
+                    user.admin = false;
+                }
+                Ok(store)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Written to a temporary file first so a crash never leaves a truncated store
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    /// No accounts yet: the first-run setup flow must create an admin
+    pub fn needs_setup(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    fn find_mut(&mut self, username: &str) -> Option<&mut UserRecord> {
+        let username = username.trim();
+        self.users.iter_mut().find(|u| u.username.eq_ignore_ascii_case(username))
+    }
+
+    pub fn get(&self, username: &str) -> Option<&UserRecord> {
+        let username = username.trim();
+        self.users.iter().find(|u| u.username.eq_ignore_ascii_case(username))
+    }
+
+    pub fn create(&mut self, username: &str, password: &str, role: Role, now_ms: u64) -> Result<(), String> {
+        let username = normalize_username(username)?;
+        if self.get(&username).is_some() {
+            return Err(format!("User '{}' already exists", username));
+        }
+        check_password_policy(password)?;
+        self.users.push(UserRecord {
+            username,
+            password_hash: hash_password(password)?,
+            role,
+            admin: false,
+            failed_attempts: 0,
+            locked_until_ms: None,
+            must_change_password: false,
+            created_ms: now_ms,
+            password_changed_ms: now_ms,
+        });
+        Ok(())
+    }
+
+    /// Check credentials, counting failures towards the lockout. The caller
+    /// persists the store afterwards either way.
+    pub fn authenticate(&mut self, username: &str, password: &str, now_ms: u64) -> Result<UserSummary, AuthError> {
+        let Some(user) = self.find_mut(username) else {
+            verify_password(password, dummy_hash());
+            return Err(AuthError::InvalidCredentials);
+        };
+        if let Some(until) = user.locked_until_ms.filter(|&t| t > now_ms) {
+            // Same work as any other attempt, so the lock is not visible in timing
+            verify_password(password, dummy_hash());
+            return Err(AuthError::Locked {
+                retry_in_ms: until - now_ms,
+            });
+        }
+        if verify_password(password, &user.password_hash) {
+            user.failed_attempts = 0;
+            user.locked_until_ms = None;
+            return Ok(summary(user, now_ms));
+        }
+        user.failed_attempts += 1;
+        if user.failed_attempts >= MAX_FAILED_ATTEMPTS {
+            user.failed_attempts = 0;
+            user.locked_until_ms = Some(now_ms + LOCKOUT_MS);
+            eprintln!("🔒 Account '{}' locked after {} failed logins", user.username, MAX_FAILED_ATTEMPTS);
+            return Err(AuthError::Locked { retry_in_ms: LOCKOUT_MS });
+        }
+        Err(AuthError::InvalidCredentials)
+    }
+
+    /// Self-service change; requires the current password
+    pub fn change_password(&mut self, username: &str, old: &str, new: &str, now_ms: u64) -> Result<(), String> {
+        self.authenticate(username, old, now_ms).map_err(|e| e.to_string())?;
+        check_password_policy(new)?;
+        if old == new {
+            return Err("New password must differ from the current one".into());
+        }
+        let user = self.find_mut(username).ok_or(AuthError::InvalidCredentials.to_string())?;
+        user.password_hash = hash_password(new)?;
+        user.must_change_password = false;
+        user.password_changed_ms = now_ms;
+        Ok(())
+    }
+
+    /// Administrator reset: sets a temporary password, clears any lockout and
+    /// forces a change at next login
+    pub fn reset_password(&mut self, username: &str, temporary: &str, now_ms: u64) -> Result<(), String> {
+        check_password_policy(temporary)?;
+        let user = self.find_mut(username).ok_or_else(|| format!("No such user '{}'", username))?;
+        user.password_hash = hash_password(temporary)?;
+        user.must_change_password = true;
+        user.failed_attempts = 0;
+        user.locked_until_ms = None;
+        user.password_changed_ms = now_ms;
+        Ok(())
+    }
+
+    /// Refuses to demote the last administrator, which would lock everyone
+    /// out of user management
+    pub fn set_role(&mut self, username: &str, role: Role) -> Result<(), String> {
+        let admins = self.users.iter().filter(|u| u.role == Role::Admin).count();
+        let user = self.find_mut(username).ok_or_else(|| format!("No such user '{}'", username))?;
+        if user.role == Role::Admin && role != Role::Admin && admins == 1 {
+            return Err("Cannot demote the last administrator".into());
+        }
+        user.role = role;
+        Ok(())
+    }
+
+    pub fn summaries(&self, now_ms: u64) -> Vec<UserSummary> {
+        self.users.iter().map(|u| summary(u, now_ms)).collect()
+    }
+}
+
+fn summary(user: &UserRecord, now_ms: u64) -> UserSummary {
+    UserSummary {
+        username: user.username.clone(),
+        role: user.role,
+        locked: user.locked_until_ms.is_some_and(|t| t > now_ms),
+        must_change_password: user.must_change_password,
+        created_ms: user.created_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: u64 = 1_700_000_000_000;
+
+    fn store() -> UserStore {
+        let mut store = UserStore::default();
+        store.create("alice", "correct horse", Role::Admin, T0).unwrap();
+        store
+    }
+
+    #[test]
+    fn passwords_are_argon2id_and_verify() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("correct horsE", &hash));
+        // Fresh salt every time
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+        assert!(!verify_password("anything", "not a phc string"));
+
+        let mut store = store();
+        assert!(store.authenticate("ALICE ", "correct horse", T0).is_ok());
+        assert!(store.create("bob", "short", Role::Employee, T0).is_err());
+        assert!(store.create("Alice", "long enough", Role::Employee, T0).is_err());
+    }
+
+    #[test]
+    fn unknown_users_are_checked_against_the_dummy_hash() {
+        assert!(dummy_hash().starts_with("$argon2id$"));
+        assert!(PasswordHash::new(dummy_hash()).is_ok());
+
+        let mut store = store();
+        let unknown = store.authenticate("mallory", "correct horse", T0).unwrap_err();
+        let wrong = store.authenticate("alice", "wrong password", T0).unwrap_err();
+        assert_eq!(unknown, AuthError::InvalidCredentials);
+        assert_eq!(unknown.to_string(), wrong.to_string());
+        assert!(store.get("mallory").is_none());
+    }
+
+    #[test]
+    fn five_failures_lock_the_account_for_fifteen_minutes() {
+        let mut store = store();
+        for attempt in 1..MAX_FAILED_ATTEMPTS {
+            assert_eq!(store.authenticate("alice", "wrong password", T0 + attempt as u64).unwrap_err(), AuthError::InvalidCredentials);
+        }
+        let locked = store.authenticate("alice", "wrong password", T0 + 10).unwrap_err();
+        assert_eq!(locked, AuthError::Locked { retry_in_ms: LOCKOUT_MS });
+        // Indistinguishable from a wrong password for the caller
+        assert_eq!(locked.to_string(), AuthError::InvalidCredentials.to_string());
+        assert!(locked.detail().contains("locked"));
+
+        // Even the right password is refused while locked
+        let just_before = T0 + 10 + LOCKOUT_MS - 1;
+        assert_eq!(store.authenticate("alice", "correct horse", just_before).unwrap_err(), AuthError::Locked { retry_in_ms: 1 });
+        assert!(store.summaries(just_before)[0].locked);
+
+        let unlocked = T0 + 10 + LOCKOUT_MS;
+        assert!(!store.summaries(unlocked)[0].locked);
+        assert!(store.authenticate("alice", "correct horse", unlocked).is_ok());
+        assert_eq!(store.get("alice").unwrap().failed_attempts, 0);
+    }
+
+    #[test]
+    fn successful_login_resets_the_failure_count() {
+        let mut store = store();
+        for _ in 1..MAX_FAILED_ATTEMPTS {
+            let _ = store.authenticate("alice", "wrong password", T0);
+        }
+        assert!(store.authenticate("alice", "correct horse", T0).is_ok());
+        assert_eq!(store.authenticate("alice", "wrong password", T0).unwrap_err(), AuthError::InvalidCredentials);
+    }
+
+    #[test]
+    fn reset_clears_the_lock_and_forces_a_change() {
+        let mut store = store();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            let _ = store.authenticate("alice", "wrong password", T0);
+        }
+        assert!(store.reset_password("alice", "short", T0).is_err());
+        store.reset_password("alice", "temporary pw", T0 + 1).unwrap();
+
+        let user = store.authenticate("alice", "temporary pw", T0 + 2).unwrap();
+        assert!(user.must_change_password);
+        assert!(!user.locked);
+
+        assert!(store.change_password("alice", "temporary pw", "temporary pw", T0 + 3).is_err());
+        store.change_password("alice", "temporary pw", "brand new pw", T0 + 3).unwrap();
+        let user = store.authenticate("alice", "brand new pw", T0 + 4).unwrap();
+        assert!(!user.must_change_password);
+        assert!(store.authenticate("alice", "temporary pw", T0 + 5).is_err());
+    }
+}
@@ -0,0 +1,745 @@
+use chrono::Local;
+use scrap::{Capturer, Display};
+use std::borrow::Cow;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ExitStatus};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::agent::CaptureHandle;
+use crate::capture;
+use crate::current_ts_millis;
+use crate::encoding::{EncoderBackend, EncodingProfile};
+use crate::ffmpeg::{self, DisplayGeometry, GrabBackend, MediaInfo, Segmenting, VideoArgs};
+use crate::mjpeg::{self, AviWriter};
+use crate::motion::{self, MotionConfig, MotionDetector, PreRoll};
+use crate::recording_index::{self, RecordingEntry, SegmentListReader};
+use crate::redaction;
+use crate::upload::UploadKind;
+use crate::video_health::{self, HealthEventKind};
+use crate::worker::Backoff;
+
+/// Consecutive failed ffmpeg runs tolerated before the video worker fails
+const MAX_RESTARTS: u32 = 5;
+/// No `-progress` report for this long means ffmpeg is wedged
+const STALL_TIMEOUT_MS: u64 = 30_000;
+/// A run this long counts as healthy and resets the backoff
+const HEALTHY_RUN_MS: u64 = 60_000;
+
+/// Where and how a recording session captures and encodes
+#[derive(Debug, Clone)]
+pub struct RecordingTarget {
+    pub output_dir: PathBuf,
+    pub backend: GrabBackend,
+    pub geometry: DisplayGeometry,
+    pub encoding: EncodingProfile,
+    pub encoder: EncoderBackend,
+}
+
+impl RecordingTarget {
+    fn args(&self, output: PathBuf) -> VideoArgs {
+        VideoArgs::new(self.backend, self.geometry, self.encoding.clone(), output)
+    }
+}
+
+enum RunEnd {
+    /// ffmpeg exited by itself
+    Exited(ExitStatus),
+    /// We asked it to quit because stop was requested
+    Stopped(ExitStatus),
+    /// No progress for `STALL_TIMEOUT_MS`; the process was killed
+    Stalled,
+    /// Asked to quit because the storage manager paused captures
+    Paused(ExitStatus),
+    /// Asked to quit because redaction rules were set; ffmpeg cannot redact
+    /// what it grabs itself
+    Redacting(ExitStatus),
+}
+
+/// Spawn ffmpeg with progress and stderr monitoring and babysit it until it
+/// exits, stalls or stop is requested. `on_tick` runs on every poll.
+fn run_ffmpeg(capture: &CaptureHandle, args: &VideoArgs, mut on_tick: impl FnMut()) -> Result<RunEnd, String> {
+    let control = &capture.video;
+
+    let mut child = match ffmpeg::spawn(&args.build()) {
+        Ok(c) => c,
+        Err(e) => {
+            let msg = format!("Failed to start ffmpeg: {}", e);
+            capture.video_health.lock().unwrap().record(HealthEventKind::SpawnFailed, msg.clone());
+            return Err(msg);
+        }
+    };
+    capture.video_health.lock().unwrap().started(child.id());
+    video_health::watch(&mut child, capture.video_health.clone());
+
+    // Poll rather than block in wait() so a stop request ends the clip early
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let mut health = capture.video_health.lock().unwrap();
+                let msg = format!("ffmpeg exited with {} {}", status, health.stderr_summary());
+                health.record(HealthEventKind::Exited { code: status.code() }, msg);
+                health.pid = None;
+                return Ok(RunEnd::Exited(status));
+            }
+            Ok(None) => {}
+            Err(e) => return Err(format!("Failed to wait for ffmpeg: {}", e)),
+        }
+
+        if !control.wait(Duration::from_millis(200)) {
+            println!("⏹️ Stopping ffmpeg, finalizing {}", args.output.display());
+            let status = ffmpeg::stop_gracefully(&mut child, ffmpeg::GRACEFUL_STOP_TIMEOUT)
+                .map_err(|e| format!("Failed to stop ffmpeg: {}", e))?;
+            capture.video_health.lock().unwrap().pid = None;
+            return Ok(RunEnd::Stopped(status));
+        }
+
+        if capture.captures_held() {
+            println!("⏸️ Captures paused, finalizing {}", args.output.display());
+            let status = ffmpeg::stop_gracefully(&mut child, ffmpeg::GRACEFUL_STOP_TIMEOUT)
+                .map_err(|e| format!("Failed to stop ffmpeg: {}", e))?;
+            capture.video_health.lock().unwrap().pid = None;
+            return Ok(RunEnd::Paused(status));
+        }
+
+        if redaction_active(capture) {
+            println!("🔒 Redaction rules set, finalizing {}", args.output.display());
+            let status = ffmpeg::stop_gracefully(&mut child, ffmpeg::GRACEFUL_STOP_TIMEOUT)
+                .map_err(|e| format!("Failed to stop ffmpeg: {}", e))?;
+            capture.video_health.lock().unwrap().pid = None;
+            return Ok(RunEnd::Redacting(status));
+        }
+
+        let last_progress = capture.video_health.lock().unwrap().last_progress_ms.unwrap_or(0);
+        if current_ts_millis().saturating_sub(last_progress) > STALL_TIMEOUT_MS {
+            let _ = child.kill();
+            let _ = child.wait();
+            let mut health = capture.video_health.lock().unwrap();
+            health.record(HealthEventKind::Stalled, format!("no progress for {}s, killed", STALL_TIMEOUT_MS / 1000));
+            health.pid = None;
+            return Ok(RunEnd::Stalled);
+        }
+
+        on_tick();
+    }
+}
+
+/// Wait out the next backoff delay, or give up once `MAX_RESTARTS` is reached
+fn backoff_or_give_up(capture: &CaptureHandle, backoff: &mut Backoff, reason: String) -> Result<(), String> {
+    if backoff.attempts() >= MAX_RESTARTS {
+        capture
+            .video_health
+            .lock()
+            .unwrap()
+            .record(HealthEventKind::GaveUp, format!("giving up after {} restarts", MAX_RESTARTS));
+        return Err(reason);
+    }
+    let delay = backoff.next_delay();
+    capture.video_health.lock().unwrap().record(
+        HealthEventKind::Restarting {
+            attempt: backoff.attempts(),
+            delay_secs: delay.as_secs(),
+        },
+        reason,
+    );
+    capture.video.wait(delay);
+    Ok(())
+}
+
+/// Hold off while the storage manager has captures paused. Returns false if
+/// stop was requested meanwhile.
+fn wait_while_paused(capture: &CaptureHandle) -> bool {
+    while capture.captures_held() {
+        if !capture.video.wait(Duration::from_secs(1)) {
+            return false;
+        }
+    }
+    true
+}
+
+fn redaction_active(capture: &CaptureHandle) -> bool {
+    !capture.redaction.lock().unwrap().rules().is_empty()
+}
+
+/// A grabbed BGRA frame with the privacy rules applied, evaluated against the
+/// windows on screen now. Repeated frames are redacted again since windows may
+/// have moved or come to the front since they were grabbed. Fails where the
+/// rules cannot be applied, and the frame must then not be recorded.
+fn redacted_frame<'a>(capture: &CaptureHandle, frame: &'a [u8], w: usize, h: usize) -> Result<Cow<'a, [u8]>, String> {
+    if !redaction_active(capture) {
+        return Ok(Cow::Borrowed(frame));
+    }
+    let plan = capture::redaction_plan(capture)?;
+    if plan.skip.is_none() && plan.regions.is_empty() {
+        return Ok(Cow::Borrowed(frame));
+    }
+    let mut redacted = frame.to_vec();
+    redaction::apply_to_video(&mut redacted, w, h, &plan);
+    Ok(Cow::Owned(redacted))
+}
+
+fn restart_backoff() -> Backoff {
+    Backoff::new(Duration::from_secs(1), Duration::from_secs(60))
+}
+
+/// Fill in resolution, fps and duration from ffprobe, then append to the index
+fn index_recording(capture: &CaptureHandle, output_dir: &Path, mut entry: RecordingEntry) {
+    match ffmpeg::probe(&output_dir.join(&entry.file)) {
+        Ok(info) => entry.set_media(info),
+        Err(e) => eprintln!("ffprobe failed for {}: {}", entry.file, e),
+    }
+    finish_recording(capture, output_dir, entry);
+}
+
+/// Stamp a finished clip with its origin and add it to the index and the upload queue
+fn finish_recording(capture: &CaptureHandle, output_dir: &Path, mut entry: RecordingEntry) {
+    entry.identity = Some(capture.record_identity());
+    if let Err(e) = recording_index::append(output_dir, &entry) {
+        eprintln!("Failed to index {}: {}", entry.file, e);
+    }
+    let meta = serde_json::to_value(&entry).unwrap_or_default();
+    capture.enqueue_upload(UploadKind::Video, &output_dir.join(&entry.file), meta);
+}
+
+/// Fixed-length clips with a gap of `interval_secs` between them. While
+/// redaction rules are set the clips come from `redacted_video_loop`.
+pub fn video_loop(
+    capture: &CaptureHandle,
+    target: &RecordingTarget,
+    interval_secs: u64,
+    duration_secs: u64,
+) -> Result<(), String> {
+    if target.encoder == EncoderBackend::Mjpeg {
+        return mjpeg_video_loop(capture, target, duration_secs, interval_secs);
+    }
+    let control = &capture.video;
+    let output_dir = target.output_dir.as_path();
+    let mut backoff = restart_backoff();
+
+    while control.should_run() {
+        if !wait_while_paused(capture) {
+            break;
+        }
+        if redaction_active(capture) {
+            redacted_video_loop(capture, target, duration_secs, interval_secs)?;
+            continue;
+        }
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let filename = output_dir.join(format!("capture_{}.mp4", timestamp));
+        println!("➡️ Recording video to {}", filename.display());
+
+        let mut args = target.args(filename.clone());
+        args.duration_secs = Some(duration_secs);
+
+        let start_ms = current_ts_millis();
+        let finished = match run_ffmpeg(capture, &args, || {}) {
+            Ok(RunEnd::Exited(s)) | Ok(RunEnd::Stopped(s)) if s.success() => true,
+            // A clip cut short by stop or pause is finalized by `q` even if the exit code says otherwise
+            Ok(RunEnd::Stopped(_)) | Ok(RunEnd::Paused(_)) | Ok(RunEnd::Redacting(_)) => true,
+            Ok(RunEnd::Exited(s)) => {
+                backoff_or_give_up(capture, &mut backoff, format!("ffmpeg exited with {}", s))?;
+                false
+            }
+            Ok(RunEnd::Stalled) => {
+                backoff_or_give_up(capture, &mut backoff, "ffmpeg stalled".into())?;
+                false
+            }
+            Err(e) => {
+                backoff_or_give_up(capture, &mut backoff, e)?;
+                false
+            }
+        };
+
+        if finished {
+            backoff.reset();
+            control.progress();
+            println!("Saved {}", filename.display());
+            let file = filename.file_name().unwrap_or_default().to_string_lossy().to_string();
+            index_recording(capture, output_dir, RecordingEntry::new(file, start_ms, current_ts_millis()));
+            // Gap between clips; returns early on stop
+            control.wait(Duration::from_secs(interval_secs));
+        }
+    }
+    Ok(())
+}
+
+/// One long-running ffmpeg using the segment muxer, so no frames are lost
+/// between files. Segments are indexed as ffmpeg closes them. If ffmpeg dies
+/// it is restarted with backoff and a fresh segment list. While redaction
+/// rules are set, back-to-back clips come from `redacted_video_loop` instead.
+pub fn continuous_video_loop(capture: &CaptureHandle, target: &RecordingTarget, segment_secs: u64) -> Result<(), String> {
+    if target.encoder == EncoderBackend::Mjpeg {
+        return mjpeg_video_loop(capture, target, segment_secs, 0);
+    }
+    let control = &capture.video;
+    let output_dir = target.output_dir.as_path();
+    let mut backoff = restart_backoff();
+
+    let index_new = |segments: &mut SegmentListReader| {
+        for entry in segments.poll() {
+            control.progress();
+            println!("Saved segment {}", entry.file);
+            index_recording(capture, output_dir, entry);
+        }
+    };
+
+    while control.should_run() {
+        if !wait_while_paused(capture) {
+            break;
+        }
+        if redaction_active(capture) {
+            redacted_video_loop(capture, target, segment_secs, 0)?;
+            continue;
+        }
+        let session = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let list_path = output_dir.join(format!("segments_{}.csv", session));
+        let mut args = target.args(output_dir.join("segment_%Y%m%d_%H%M%S.mp4"));
+        args.segment = Some(Segmenting {
+            segment_secs,
+            list_path: list_path.clone(),
+        });
+
+        let start_ms = current_ts_millis();
+        let mut segments = SegmentListReader::new(list_path, start_ms);
+        let end = run_ffmpeg(capture, &args, || index_new(&mut segments));
+        // The final segment is listed only once ffmpeg has closed it
+        index_new(&mut segments);
+
+        if current_ts_millis().saturating_sub(start_ms) > HEALTHY_RUN_MS {
+            backoff.reset();
+        }
+        match end {
+            Ok(RunEnd::Stopped(_)) => break,
+            Ok(RunEnd::Paused(_)) | Ok(RunEnd::Redacting(_)) => {}
+            Ok(RunEnd::Exited(s)) => {
+                backoff_or_give_up(capture, &mut backoff, format!("ffmpeg exited unexpectedly with {}", s))?
+            }
+            Ok(RunEnd::Stalled) => backoff_or_give_up(capture, &mut backoff, "ffmpeg stalled".into())?,
+            Err(e) => backoff_or_give_up(capture, &mut backoff, e)?,
+        }
+    }
+    Ok(())
+}
+
+/// An ffmpeg process encoding frames we write to its stdin
+struct PipedClip {
+    child: Child,
+    stdin: ChildStdin,
+    file: String,
+    start_ms: u64,
+}
+
+impl PipedClip {
+    fn start(capture: &CaptureHandle, args: &VideoArgs, start_ms: u64) -> Result<Self, String> {
+        let mut child = ffmpeg::spawn(&args.build()).map_err(|e| {
+            let msg = format!("Failed to start ffmpeg: {}", e);
+            capture.video_health.lock().unwrap().record(HealthEventKind::SpawnFailed, msg.clone());
+            msg
+        })?;
+        capture.video_health.lock().unwrap().started(child.id());
+        video_health::watch(&mut child, capture.video_health.clone());
+        let stdin = child.stdin.take().ok_or("ffmpeg stdin not piped")?;
+        let file = args.output.file_name().unwrap_or_default().to_string_lossy().to_string();
+        Ok(Self {
+            child,
+            stdin,
+            file,
+            start_ms,
+        })
+    }
+
+    fn write(&mut self, frame: &[u8]) -> Result<(), String> {
+        self.stdin.write_all(frame).map_err(|e| match e.kind() {
+            ErrorKind::BrokenPipe => "ffmpeg exited while recording".to_string(),
+            _ => format!("Failed to feed ffmpeg: {}", e),
+        })
+    }
+
+    /// Close stdin so ffmpeg finalizes the file, then wait for it to exit
+    fn finish(self, capture: &CaptureHandle, output_dir: &Path) -> Result<(), String> {
+        let PipedClip {
+            mut child,
+            stdin,
+            file,
+            start_ms,
+        } = self;
+        drop(stdin);
+        let status = ffmpeg::stop_gracefully(&mut child, ffmpeg::GRACEFUL_STOP_TIMEOUT)
+            .map_err(|e| format!("Failed to stop ffmpeg: {}", e))?;
+        {
+            let mut health = capture.video_health.lock().unwrap();
+            let msg = format!("ffmpeg exited with {} {}", status, health.stderr_summary());
+            health.record(HealthEventKind::Exited { code: status.code() }, msg);
+            health.pid = None;
+        }
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {}", status));
+        }
+        println!("Saved {}", file);
+        index_recording(capture, output_dir, RecordingEntry::new(file, start_ms, current_ts_millis()));
+        Ok(())
+    }
+}
+
+/// Sample the screen with scrap and only encode while it changes or the user
+/// is active. Frames from the last `pre_roll_secs` are kept in memory and
+/// written first, so each clip shows what led up to the trigger.
+pub fn motion_video_loop(capture: &CaptureHandle, target: &RecordingTarget, config: MotionConfig) -> Result<(), String> {
+    if target.encoder != EncoderBackend::Ffmpeg {
+        return Err("Motion-triggered recording requires the ffmpeg backend".into());
+    }
+    let control = &capture.video;
+    let output_dir = target.output_dir.as_path();
+    let mut backoff = restart_backoff();
+
+    let display = Display::primary().map_err(|e| format!("Failed to get display: {:?}", e))?;
+    let mut capturer = Capturer::new(display).map_err(|e| format!("Failed to create capturer: {:?}", e))?;
+    let (w, h) = (capturer.width(), capturer.height());
+
+    let interval_ms = config.frame_interval_ms();
+    let interval = Duration::from_millis(interval_ms);
+    let pre_roll_frames = config.pre_roll_frames();
+    let mut encoding = target.encoding.clone();
+    encoding.framerate = config.effective_fps();
+    let geometry = DisplayGeometry {
+        width: w as u32,
+        height: h as u32,
+        offset_x: 0,
+        offset_y: 0,
+    };
+
+    let mut detector = MotionDetector::new(config, capture.last_input_ts.load(Ordering::SeqCst));
+    let mut pre_roll = PreRoll::new(pre_roll_frames);
+    let mut last_frame: Option<Vec<u8>> = None;
+    let mut clip: Option<PipedClip> = None;
+    let mut next_tick = Instant::now();
+
+    while control.should_run() {
+        if capture.captures_held() {
+            if let Some(c) = clip.take() {
+                println!("⏸️ Captures paused, closing {}", c.file);
+                if let Err(e) = c.finish(capture, output_dir) {
+                    eprintln!("{}", e);
+                }
+            }
+            pre_roll.clear();
+            if !wait_while_paused(capture) {
+                break;
+            }
+            next_tick = Instant::now();
+        }
+
+        // scrap reports WouldBlock when nothing on screen changed; repeat the
+        // previous frame to keep a constant frame rate
+        let (frame, changed) = match capturer.frame() {
+            Ok(f) => {
+                let f = f.to_vec();
+                let changed = last_frame.as_ref().map_or(0.0, |prev| motion::changed_pct(prev, &f, w, h));
+                (f, changed)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => match last_frame.take() {
+                Some(prev) => (prev, 0.0),
+                None => {
+                    control.wait(Duration::from_millis(10));
+                    continue;
+                }
+            },
+            Err(e) => {
+                if let Some(c) = clip.take() {
+                    let _ = c.finish(capture, output_dir);
+                }
+                return Err(format!("Capture error: {:?}", e));
+            }
+        };
+
+        let now = current_ts_millis();
+        let active = detector.update(now, changed, capture.last_input_ts.load(Ordering::SeqCst));
+
+        if active && clip.is_none() {
+            let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+            let mut args = VideoArgs::new(target.backend, geometry, encoding.clone(), output_dir.join(format!("motion_{}.mp4", timestamp)));
+            args.raw_input = true;
+            let start_ms = now.saturating_sub(pre_roll.len() as u64 * interval_ms);
+            match PipedClip::start(capture, &args, start_ms) {
+                Ok(mut c) => {
+                    println!("➡️ Motion detected, recording {}", c.file);
+                    let written = pre_roll.drain().try_for_each(|f| c.write(&f));
+                    match written {
+                        Ok(()) => clip = Some(c),
+                        Err(e) => {
+                            let _ = c.finish(capture, output_dir);
+                            backoff_or_give_up(capture, &mut backoff, e)?;
+                        }
+                    }
+                }
+                Err(e) => backoff_or_give_up(capture, &mut backoff, e)?,
+            }
+        }
+
+        // Change detection runs on the raw frame; only what is encoded is redacted
+        let redacted = match redacted_frame(capture, &frame, w, h) {
+            Ok(r) => r,
+            Err(e) => {
+                if let Some(c) = clip.take() {
+                    let _ = c.finish(capture, output_dir);
+                }
+                return Err(e);
+            }
+        };
+        match clip.take() {
+            Some(mut c) => match c.write(&redacted) {
+                Ok(()) if active => clip = Some(c),
+                Ok(()) => {
+                    println!("💤 Screen quiet, closing {}", c.file);
+                    match c.finish(capture, output_dir) {
+                        Ok(()) => {
+                            backoff.reset();
+                            control.progress();
+                        }
+                        Err(e) => backoff_or_give_up(capture, &mut backoff, e)?,
+                    }
+                }
+                Err(e) => {
+                    let _ = c.finish(capture, output_dir);
+                    backoff_or_give_up(capture, &mut backoff, e)?;
+                }
+            },
+            None => pre_roll.push(&redacted),
+        }
+        last_frame = Some(frame);
+
+        next_tick += interval;
+        let now = Instant::now();
+        if next_tick > now {
+            control.wait(next_tick - now);
+        } else {
+            // Fell behind (slow encoder or capture); don't try to catch up
+            next_tick = now;
+        }
+    }
+
+    if let Some(c) = clip.take() {
+        c.finish(capture, output_dir)?;
+        control.progress();
+    }
+    Ok(())
+}
+
+/// Grab frames with scrap, redact them and pipe them to ffmpeg in clips of
+/// `clip_secs` with `gap_secs` between them. Runs in place of ffmpeg grabbing
+/// the screen itself while redaction rules are set, and returns once they are
+/// cleared so the caller can go back to direct grabbing.
+fn redacted_video_loop(capture: &CaptureHandle, target: &RecordingTarget, clip_secs: u64, gap_secs: u64) -> Result<(), String> {
+    let control = &capture.video;
+    let output_dir = target.output_dir.as_path();
+    let mut backoff = restart_backoff();
+    // Refuse before any file is started where the rules cannot be applied
+    capture::redaction_plan(capture)?;
+
+    let display = Display::primary().map_err(|e| format!("Failed to get display: {:?}", e))?;
+    let mut capturer = Capturer::new(display).map_err(|e| format!("Failed to create capturer: {:?}", e))?;
+    let (w, h) = (capturer.width(), capturer.height());
+    let mut encoding = target.encoding.clone();
+    encoding.framerate = encoding.framerate.clamp(1, 30);
+    let interval = Duration::from_millis(1000 / encoding.framerate as u64);
+    let geometry = DisplayGeometry {
+        width: w as u32,
+        height: h as u32,
+        offset_x: 0,
+        offset_y: 0,
+    };
+    let mut last_frame: Option<Vec<u8>> = None;
+
+    while control.should_run() && redaction_active(capture) {
+        if !wait_while_paused(capture) {
+            break;
+        }
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let mut args = VideoArgs::new(target.backend, geometry, encoding.clone(), output_dir.join(format!("capture_{}.mp4", timestamp)));
+        args.raw_input = true;
+        let mut clip = match PipedClip::start(capture, &args, current_ts_millis()) {
+            Ok(c) => c,
+            Err(e) => {
+                backoff_or_give_up(capture, &mut backoff, e)?;
+                continue;
+            }
+        };
+        println!("➡️ Recording video to {} (redacted)", clip.file);
+
+        let deadline = Instant::now() + Duration::from_secs(clip_secs.max(1));
+        let mut next_tick = Instant::now();
+        let mut written = Ok(());
+        while control.should_run() && Instant::now() < deadline && !capture.captures_held() && redaction_active(capture) {
+            // Repeat the previous frame when the screen has not changed
+            let frame = match capturer.frame() {
+                Ok(f) => f.to_vec(),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => match last_frame.take() {
+                    Some(prev) => prev,
+                    None => {
+                        control.wait(Duration::from_millis(10));
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    let _ = clip.finish(capture, output_dir);
+                    return Err(format!("Capture error: {:?}", e));
+                }
+            };
+            written = match redacted_frame(capture, &frame, w, h) {
+                Ok(redacted) => clip.write(&redacted),
+                Err(e) => {
+                    let _ = clip.finish(capture, output_dir);
+                    return Err(e);
+                }
+            };
+            last_frame = Some(frame);
+            if written.is_err() {
+                break;
+            }
+
+            next_tick += interval;
+            let now = Instant::now();
+            if next_tick > now {
+                control.wait(next_tick - now);
+            } else {
+                next_tick = now;
+            }
+        }
+
+        // Finish even after a failed write so ffmpeg is reaped
+        let finished = clip.finish(capture, output_dir);
+        match written.and(finished) {
+            Ok(()) => {
+                backoff.reset();
+                control.progress();
+            }
+            Err(e) => {
+                backoff_or_give_up(capture, &mut backoff, e)?;
+                continue;
+            }
+        }
+
+        if gap_secs > 0 {
+            control.wait(Duration::from_secs(gap_secs));
+        }
+    }
+    Ok(())
+}
+
+/// In-process alternative to ffmpeg: scrap frames compressed to MJPEG AVI
+/// files of `clip_secs` (rolled early at `MAX_AVI_BYTES`) with `gap_secs`
+/// between them. A gap of 0 gives back-to-back segments. Frames are redacted
+/// before they are compressed.
+fn mjpeg_video_loop(capture: &CaptureHandle, target: &RecordingTarget, clip_secs: u64, gap_secs: u64) -> Result<(), String> {
+    let control = &capture.video;
+    let output_dir = target.output_dir.as_path();
+
+    let display = Display::primary().map_err(|e| format!("Failed to get display: {:?}", e))?;
+    let mut capturer = Capturer::new(display).map_err(|e| format!("Failed to create capturer: {:?}", e))?;
+    let (w, h) = (capturer.width(), capturer.height());
+    let fps = target.encoding.framerate.clamp(1, 30);
+    let interval = Duration::from_millis(1000 / fps as u64);
+    let mut last_frame: Option<Vec<u8>> = None;
+
+    while control.should_run() {
+        if !wait_while_paused(capture) {
+            break;
+        }
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let path = output_dir.join(format!("capture_{}.avi", timestamp));
+        println!("➡️ Recording video to {} (in-process MJPEG)", path.display());
+
+        let start_ms = current_ts_millis();
+        let deadline = Instant::now() + Duration::from_secs(clip_secs.max(1));
+        // Created on the first frame by `write_mjpeg_frame`
+        let mut writer: Option<AviWriter> = None;
+        let mut next_tick = Instant::now();
+        // Ends the session, once the frames so far are finalized and indexed
+        let mut failure: Option<String> = None;
+
+        while control.should_run() && Instant::now() < deadline && !capture.captures_held() {
+            // Repeat the previous frame when the screen has not changed
+            let frame = match capturer.frame() {
+                Ok(f) => f.to_vec(),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => match last_frame.take() {
+                    Some(prev) => prev,
+                    None => {
+                        control.wait(Duration::from_millis(10));
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    failure = Some(format!("Capture error: {:?}", e));
+                    break;
+                }
+            };
+
+            let written = redacted_frame(capture, &frame, w, h).and_then(|redacted| {
+                write_mjpeg_frame(&mut writer, &path, &redacted, (w, h), target.encoding.scale_height, fps)
+            });
+            last_frame = Some(frame);
+            match written {
+                Ok(bytes) if bytes >= mjpeg::MAX_AVI_BYTES => break,
+                Ok(_) => {}
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+
+            next_tick += interval;
+            let now = Instant::now();
+            if next_tick > now {
+                control.wait(next_tick - now);
+            } else {
+                next_tick = now;
+            }
+        }
+
+        if let Some(out) = writer {
+            let info = MediaInfo {
+                width: out.width,
+                height: out.height,
+                fps: fps as f64,
+                duration_secs: out.duration_secs(),
+            };
+            out.finish().map_err(|e| format!("Failed to finalize {}: {}", path.display(), e))?;
+            control.progress();
+            println!("Saved {}", path.display());
+
+            let file = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut entry = RecordingEntry::new(file, start_ms, current_ts_millis());
+            entry.set_media(info);
+            finish_recording(capture, output_dir, entry);
+        }
+
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        if gap_secs > 0 {
+            control.wait(Duration::from_secs(gap_secs));
+        }
+    }
+    Ok(())
+}
+
+/// Compress one BGRA frame and append it to the clip, creating the file on
+/// the first frame once the (possibly scaled) size is known. Returns the
+/// clip's size so far.
+fn write_mjpeg_frame(
+    writer: &mut Option<AviWriter>,
+    path: &Path,
+    frame: &[u8],
+    (w, h): (usize, usize),
+    scale_height: Option<u32>,
+    fps: u32,
+) -> Result<u64, String> {
+    let (jpeg, fw, fh) = mjpeg::encode_bgra(frame, w as u32, h as u32, scale_height, mjpeg::JPEG_QUALITY)?;
+    let out = match writer.as_mut() {
+        Some(out) => out,
+        None => writer.insert(AviWriter::create(path, fw, fh, fps).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?),
+    };
+    out.write_jpeg(&jpeg).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(out.bytes_written())
+}
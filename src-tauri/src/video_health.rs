@@ -0,0 +1,223 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::current_ts_millis;
+
+const STDERR_TAIL_LINES: usize = 50;
+const MAX_EVENTS: usize = 100;
+
+/// One `-progress` report from ffmpeg
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
+pub struct FfmpegProgress {
+    pub frame: u64,
+    pub fps: f64,
+    pub bitrate_kbps: f64,
+    pub total_size: u64,
+    pub out_time_ms: u64,
+    pub dup_frames: u64,
+    pub drop_frames: u64,
+    pub speed: f64,
+}
+
+/// Accumulates `key=value` lines until the closing `progress=...` line
+#[derive(Default)]
+pub struct ProgressParser {
+    current: FfmpegProgress,
+}
+
+impl ProgressParser {
+    pub fn feed(&mut self, line: &str) -> Option<FfmpegProgress> {
+        let (key, value) = line.trim().split_once('=')?;
+        let value = value.trim();
+        let num = |v: &str| v.parse::<f64>().unwrap_or(0.0);
+        match key {
+            "frame" => self.current.frame = value.parse().unwrap_or(0),
+            "fps" => self.current.fps = num(value),
+            "bitrate" => self.current.bitrate_kbps = num(value.trim_end_matches("kbits/s")),
+            "total_size" => self.current.total_size = value.parse().unwrap_or(0),
+            // Despite the name ffmpeg reports microseconds here
+            "out_time_us" | "out_time_ms" => self.current.out_time_ms = value.parse::<u64>().unwrap_or(0) / 1000,
+            "dup_frames" => self.current.dup_frames = value.parse().unwrap_or(0),
+            "drop_frames" => self.current.drop_frames = value.parse().unwrap_or(0),
+            "speed" => self.current.speed = num(value.trim_end_matches('x')),
+            "progress" => return Some(self.current.clone()),
+            _ => {}
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum HealthEventKind {
+    Started { pid: u32 },
+    Exited { code: Option<i32> },
+    SpawnFailed,
+    Stalled,
+    FramesDropped { count: u64 },
+    Restarting { attempt: u32, delay_secs: u64 },
+    GaveUp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthEvent {
+    pub ts_ms: u64,
+    #[serde(flatten)]
+    pub kind: HealthEventKind,
+    pub message: String,
+}
+
+/// Live view of the current ffmpeg process, shared with its reader threads
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct VideoHealth {
+    pub pid: Option<u32>,
+    pub progress: Option<FfmpegProgress>,
+    pub last_progress_ms: Option<u64>,
+    pub restarts: u32,
+    pub stderr_tail: VecDeque<String>,
+    pub events: VecDeque<HealthEvent>,
+}
+
+impl VideoHealth {
+    pub fn record(&mut self, kind: HealthEventKind, message: impl Into<String>) {
+        let message = message.into();
+        eprintln!("🎥 ffmpeg {:?}: {}", kind, message);
+        if let HealthEventKind::Restarting { .. } = kind {
+            self.restarts += 1;
+        }
+        self.events.push_back(HealthEvent {
+            ts_ms: current_ts_millis(),
+            kind,
+            message,
+        });
+        if self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    /// Reset per-process fields when a new ffmpeg starts
+    pub fn started(&mut self, pid: u32) {
+        self.pid = Some(pid);
+        self.progress = None;
+        self.last_progress_ms = Some(current_ts_millis());
+        self.stderr_tail.clear();
+        self.record(HealthEventKind::Started { pid }, "ffmpeg started");
+    }
+
+    fn update_progress(&mut self, progress: FfmpegProgress) {
+        let previously_dropped = self.progress.as_ref().map_or(0, |p| p.drop_frames);
+        if progress.drop_frames > previously_dropped {
+            let count = progress.drop_frames - previously_dropped;
+            self.record(
+                HealthEventKind::FramesDropped { count },
+                format!("{} frames dropped ({} total)", count, progress.drop_frames),
+            );
+        }
+        self.progress = Some(progress);
+        self.last_progress_ms = Some(current_ts_millis());
+    }
+
+    fn push_stderr(&mut self, line: String) {
+        self.stderr_tail.push_back(line);
+        if self.stderr_tail.len() > STDERR_TAIL_LINES {
+            self.stderr_tail.pop_front();
+        }
+    }
+
+    /// Last few stderr lines, for error messages
+    pub fn stderr_summary(&self) -> String {
+        let lines: Vec<&str> = self.stderr_tail.iter().rev().take(3).map(|s| s.as_str()).collect();
+        lines.into_iter().rev().collect::<Vec<_>>().join(" | ")
+    }
+}
+
+/// Take the child's piped stdout (`-progress pipe:1`) and stderr and feed
+/// them into `health` from two reader threads that end with the process.
+pub fn watch(child: &mut Child, health: Arc<Mutex<VideoHealth>>) {
+    if let Some(stdout) = child.stdout.take() {
+        let health = health.clone();
+        thread::spawn(move || {
+            let mut parser = ProgressParser::default();
+            for line in lines(stdout) {
+                if let Some(progress) = parser.feed(&line) {
+                    health.lock().unwrap().update_progress(progress);
+                }
+            }
+        });
+    }
+    if let Some(stderr) = child.stderr.take() {
+        thread::spawn(move || {
+            for line in lines(stderr) {
+                health.lock().unwrap().push_stderr(line);
+            }
+        });
+    }
+}
+
+fn lines(reader: impl Read) -> impl Iterator<Item = String> {
+    BufReader::new(reader).lines().map_while(Result::ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = "frame=150\nfps=15.02\nstream_0_0_q=23.0\nbitrate= 412.3kbits/s\ntotal_size=524336\nout_time_us=10000000\nout_time=00:00:10.000000\ndup_frames=3\ndrop_frames=2\nspeed=1.01x\n";
+
+    #[test]
+    fn progress_report_is_emitted_on_the_progress_line() {
+        let mut parser = ProgressParser::default();
+        for line in REPORT.lines() {
+            assert_eq!(parser.feed(line), None);
+        }
+        let progress = parser.feed("progress=continue").unwrap();
+        assert_eq!(
+            progress,
+            FfmpegProgress {
+                frame: 150,
+                fps: 15.02,
+                bitrate_kbps: 412.3,
+                total_size: 524_336,
+                out_time_ms: 10_000,
+                dup_frames: 3,
+                drop_frames: 2,
+                speed: 1.01,
+            }
+        );
+
+        // Values carry over until ffmpeg reports them again
+        assert_eq!(parser.feed("frame=165"), None);
+        let next = parser.feed("progress=end").unwrap();
+        assert_eq!(next.frame, 165);
+        assert_eq!(next.out_time_ms, 10_000);
+    }
+
+    #[test]
+    fn unknown_and_malformed_lines_are_tolerated() {
+        let mut parser = ProgressParser::default();
+        assert_eq!(parser.feed("not a key value line"), None);
+        assert_eq!(parser.feed("bitrate=N/A"), None);
+        assert_eq!(parser.feed("speed=N/A"), None);
+        assert_eq!(parser.feed("out_time_ms=2500000"), None);
+        let progress = parser.feed("progress=continue").unwrap();
+        assert_eq!(progress.bitrate_kbps, 0.0);
+        assert_eq!(progress.speed, 0.0);
+        assert_eq!(progress.out_time_ms, 2_500);
+    }
+
+    #[test]
+    fn dropped_frames_are_recorded_as_deltas() {
+        let mut health = VideoHealth::default();
+        let report = |drop_frames| FfmpegProgress { drop_frames, ..Default::default() };
+        health.update_progress(report(0));
+        health.update_progress(report(4));
+        health.update_progress(report(4));
+        health.update_progress(report(7));
+        let drops: Vec<_> = health.events.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(drops, [HealthEventKind::FramesDropped { count: 4 }, HealthEventKind::FramesDropped { count: 3 }]);
+    }
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::current_ts_millis;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    Stopped,
+    Running,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerStatus {
+    pub state: WorkerState,
+    pub started_at_ms: Option<u64>,
+    pub last_progress_ms: Option<u64>,
+    pub progress_count: u64,
+}
+
+struct Inner {
+    state: WorkerState,
+    stop_requested: bool,
+    started_at_ms: Option<u64>,
+    last_progress_ms: Option<u64>,
+    progress_count: u64,
+}
+
+/// Run state and stop signal shared between a command handler and the
+/// background thread it spawned. Waits wake up as soon as stop is requested.
+pub struct WorkerControl {
+    inner: Mutex<Inner>,
+    wake: Condvar,
+}
+
+impl WorkerControl {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                state: WorkerState::Stopped,
+                stop_requested: false,
+                started_at_ms: None,
+                last_progress_ms: None,
+                progress_count: 0,
+            }),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Move to `Running`; fails if the worker is already running
+    pub fn start(&self) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == WorkerState::Running {
+            return Err("already running".into());
+        }
+        inner.state = WorkerState::Running;
+        inner.stop_requested = false;
+        inner.started_at_ms = Some(current_ts_millis());
+        inner.last_progress_ms = None;
+        inner.progress_count = 0;
+        Ok(())
+    }
+
+    pub fn request_stop(&self) {
+        self.inner.lock().unwrap().stop_requested = true;
+        self.wake.notify_all();
+    }
+
+    pub fn should_run(&self) -> bool {
+        !self.inner.lock().unwrap().stop_requested
+    }
+
+    /// Sleep up to `timeout`. Returns `false` if stop was requested, in which
+    /// case the caller should wind down.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let inner = self.inner.lock().unwrap();
+        let (inner, _) = self
+            .wake
+            .wait_timeout_while(inner, timeout, |i| !i.stop_requested)
+            .unwrap();
+        !inner.stop_requested
+    }
+
+    /// Note one unit of useful work (a capture, a segment, ...)
+    pub fn progress(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_progress_ms = Some(current_ts_millis());
+        inner.progress_count += 1;
+    }
+
+    /// Called by the worker thread when it gives up
+    pub fn fail(&self, reason: impl Into<String>) {
+        let reason = reason.into();
+        eprintln!("Worker failed: {}", reason);
+        self.inner.lock().unwrap().state = WorkerState::Failed(reason);
+    }
+
+    /// Called by the worker thread on a clean exit; a recorded failure is kept
+    pub fn finish(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == WorkerState::Running {
+            inner.state = WorkerState::Stopped;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.inner.lock().unwrap().state == WorkerState::Running
+    }
+
+    pub fn status(&self) -> WorkerStatus {
+        let inner = self.inner.lock().unwrap();
+        WorkerStatus {
+            state: inner.state.clone(),
+            started_at_ms: inner.started_at_ms,
+            last_progress_ms: inner.last_progress_ms,
+            progress_count: inner.progress_count,
+        }
+    }
+}
+
+impl Default for WorkerControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exponential backoff between restart attempts
+pub struct Backoff {
+    attempt: u32,
+    base: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { attempt: 0, base, max }
+    }
+
+    /// Delay before the next attempt: `base`, `2 * base`, `4 * base`, ... capped at `max`
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay_for(self.attempt);
+        self.attempt += 1;
+        delay
+    }
+
+    /// The delay `next_delay` returns after `attempt` earlier attempts, for
+    /// callers that persist the attempt count themselves
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        self.base.saturating_mul(1 << attempt.min(16)).min(self.max)
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn start_stop_and_fail_lifecycle() {
+        let control = WorkerControl::new();
+        assert_eq!(control.status().state, WorkerState::Stopped);
+
+        control.start().unwrap();
+        assert!(control.is_running());
+        assert!(control.should_run());
+        assert!(control.start().is_err());
+
+        control.progress();
+        control.progress();
+        let status = control.status();
+        assert_eq!(status.progress_count, 2);
+        assert!(status.started_at_ms.is_some() && status.last_progress_ms.is_some());
+
+        control.request_stop();
+        assert!(!control.should_run());
+        control.finish();
+        assert_eq!(control.status().state, WorkerState::Stopped);
+
+        // A restart clears the stop request and the counters
+        control.start().unwrap();
+        assert!(control.should_run());
+        assert_eq!(control.status().progress_count, 0);
+
+        control.fail("disk full");
+        control.finish();
+        assert_eq!(control.status().state, WorkerState::Failed("disk full".into()));
+        assert!(!control.is_running());
+        control.start().unwrap();
+    }
+
+    #[test]
+    fn wait_wakes_up_on_stop() {
+        let control = Arc::new(WorkerControl::new());
+        control.start().unwrap();
+        assert!(control.wait(Duration::from_millis(10)));
+
+        let waiter = {
+            let control = control.clone();
+            thread::spawn(move || {
+                let begun = Instant::now();
+                (control.wait(Duration::from_secs(30)), begun.elapsed())
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        control.request_stop();
+        let (keep_running, waited) = waiter.join().unwrap();
+        assert!(!keep_running);
+        assert!(waited < Duration::from_secs(5));
+        // Already stopping: returns at once
+        assert!(!control.wait(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(backoff.attempts(), 8);
+        assert_eq!(backoff.delay_for(1000), Duration::from_secs(60));
+
+        backoff.reset();
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+}
@@ -5,18 +5,49 @@ import { message } from "@tauri-apps/plugin-dialog";
 export default function App() {
   const [user, setUser] = useState({ username: "", password: "" });
   const [loggedIn, setLoggedIn] = useState(false);
+  const [token, setToken] = useState(null);
+  const [role, setRole] = useState(null);
   const [status, setStatus] = useState(false);
   const [intervalSec, setIntervalSec] = useState(5);
   const [outputDir, setOutputDir] = useState("");
   const [activity, setActivity] = useState([]);
   const [isIdle, setIsIdle] = useState(false);
   const [latestMetrics, setLatestMetrics] = useState(null);
+  const [storageWarning, setStorageWarning] = useState(null);
+  const [privacyPause, setPrivacyPause] = useState(null);
+  const [setupRequired, setSetupRequired] = useState(false);
+
+  // First run: no accounts exist yet, so ask for an administrator instead of a login
+  useEffect(() => {
+    invoke("auth_setup_required").then(setSetupRequired).catch(console.error);
+  }, []);
+
+  async function onSetupAdmin() {
+    try {
+      alert(await invoke("setup_admin", user));
+      setSetupRequired(false);
+    } catch (err) {
+      alert("Setup failed: " + err);
+    }
+  }
 
   // ✅ Login
   async function onLogin() {
     try {
       const res = await invoke("login", user);
       if (res.success) {
+        if (res.must_change_password) {
+          const newPassword = prompt("Your password was reset. Choose a new password:");
+          if (!newPassword) return;
+          await invoke("change_password", {
+            token: res.token,
+            oldPassword: user.password,
+            newPassword,
+          });
+          setUser({ ...user, password: newPassword });
+        }
+        setToken(res.token);
+        setRole(res.role);
         setLoggedIn(true);
         alert(res.message);
       } else {
@@ -28,6 +59,16 @@ export default function App() {
     }
   }
 
+  async function onLogout() {
+    try {
+      await invoke("logout", { token });
+    } catch (e) { console.warn(e); }
+    setToken(null);
+    setRole(null);
+    setLoggedIn(false);
+    setUser({ ...user, password: "" });
+  }
+
   async function onStartVideoCapture() {
     // if (!outputDir) {
     //   alert("Enter output folder (e.g. D:\\TauriCaptures)");
@@ -35,6 +76,7 @@ export default function App() {
     // }
     try {
       const msg = await invoke("start_video_capture", {
+        token,
         // output_dir: outputDir,  // Changed to snake_case
         intervalSecs: Number(intervalSec),  // Changed to snake_case
         durationSecs: Number(30),  // Changed to snake_case
@@ -55,11 +97,12 @@ export default function App() {
     // }
     try {
       const msg = await invoke("start_capture", {
+        token,
         // outputDir: outputDir,
         intervalSecs: Number(intervalSec),
       });
       alert(msg);
-      setStatus(await invoke("capture_status"));
+      setStatus(await invoke("capture_status", { token }));
     } catch (err) {
       console.error(err);
       alert("Failed to start capture: " + err.message);
@@ -69,14 +112,14 @@ export default function App() {
   // Stop capture
   async function onStop() {
   try {
-    await invoke("stop_capture");
+    await invoke("stop_capture", { token });
   } catch (e) { console.warn(e); }
 
   try {
-    await invoke("stop_video_capture");
+    await invoke("stop_video_capture", { token });
   } catch (e) { console.warn(e); }
 
-  setStatus(await invoke("capture_status"));
+  setStatus(await invoke("capture_status", { token }));
   await message("All captures stopped", { title: "Stopped" });
 }
 
@@ -86,7 +129,8 @@ export default function App() {
     if (!loggedIn) return;
     const timer = setInterval(async () => {
       try {
-        const data = await invoke("get_recent_activity", { limit: 10 });
+        // Employees may not view activity; asking would only log denials
+        const data = role === "employee" ? [] : await invoke("get_recent_activity", { token, limit: 10 });
         if (Array.isArray(data) && data.length > 0) {
           setActivity(data.reverse());
           // Parse the latest activity to get metrics
@@ -97,19 +141,37 @@ export default function App() {
             console.error("Failed to parse metrics:", e);
           }
         }
-        const idle = await invoke("is_idle", { thresholdSecs: 15 });
+        const idle = await invoke("is_idle", { token, thresholdSecs: 15 });
         setIsIdle(idle);
+        const storage = await invoke("storage_status", { token });
+        setStorageWarning(storage.warning);
+        setPrivacyPause(await invoke("privacy_pause_status", { token }));
       } catch (err) {
         console.error("Activity fetch error:", err);
+        // Idle timeout or expiry on the backend: back to the login screen
+        if (String(err).includes("log in again") || String(err).includes("Not logged in")) {
+          setToken(null);
+          setLoggedIn(false);
+        }
       }
     }, 3000);
     return () => clearInterval(timer);
-  }, [loggedIn]);
+  }, [loggedIn, token, role]);
+
+  async function onPrivacyPause() {
+    try {
+      await invoke("pause_capture", { token, minutes: 15 });
+      setPrivacyPause(await invoke("privacy_pause_status", { token }));
+      alert("Captures paused for 15 minutes");
+    } catch (err) {
+      alert(err);
+    }
+  }
 
   // Clear recent activity
   async function clearActivity() {
     try {
-      await invoke("clear_activity");
+      await invoke("clear_activity", { token });
       setActivity([]);
       setLatestMetrics(null);
     } catch (err) {
@@ -140,12 +202,21 @@ export default function App() {
               onChange={(e) => setUser({ ...user, password: e.target.value })}
               className="w-full border p-2 rounded"
             />
-            <button
-              onClick={onLogin}
-              className="w-full bg-blue-600 text-white py-2 rounded hover:bg-blue-700"
-            >
-              Login
-            </button>
+            {setupRequired ? (
+              <button
+                onClick={onSetupAdmin}
+                className="w-full bg-green-600 text-white py-2 rounded hover:bg-green-700"
+              >
+                Create Administrator Account
+              </button>
+            ) : (
+              <button
+                onClick={onLogin}
+                className="w-full bg-blue-600 text-white py-2 rounded hover:bg-blue-700"
+              >
+                Login
+              </button>
+            )}
           </div>
         ) : (
           <>
@@ -203,8 +274,30 @@ export default function App() {
               >
                 Stop Capture
               </button>
+              <button
+                onClick={onPrivacyPause}
+                className="flex-1 py-2 rounded bg-yellow-500 text-white hover:bg-yellow-600"
+              >
+                Pause 15 min for privacy
+              </button>
             </div>
 
+            {privacyPause && (
+              <div className="mb-3 text-xs text-gray-500">
+                {privacyPause.active
+                  ? `⏸️ Paused by ${privacyPause.paused_by} until ${new Date(privacyPause.until_ms).toLocaleTimeString()}. `
+                  : ""}
+                {Math.floor(privacyPause.remaining_today_ms / 60000)} min of privacy pause left today
+              </div>
+            )}
+
+            {/* ====================== STORAGE WARNING ======================= */}
+            {storageWarning && (
+              <div className="mb-3 px-3 py-2 rounded bg-red-100 text-red-700 text-sm font-semibold">
+                ⚠️ {storageWarning}
+              </div>
+            )}
+
             {/* ====================== STATUS LABELS ======================= */}
             <div className="flex justify-between text-sm mb-4">
               <span
@@ -216,6 +309,9 @@ export default function App() {
               >
                 {status ? "🟢 Capturing" : "🔴 Stopped"}
               </span>
+              <button onClick={onLogout} className="text-gray-500 hover:underline">
+                Log out
+              </button>
               <span
                 className={`px-2 py-1 rounded ${
                   isIdle